@@ -0,0 +1,354 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::api::schema::*;
+
+// MirrorError errors returned across the crate, each kind maps to a
+// distinct exit code of the cli (see exit_code)
+#[derive(Debug)]
+pub enum MirrorError {
+    // image set config, registries.conf or cli settings
+    Config(String),
+    // credentials, token realm or token
+    Auth(String),
+    // the registry answered with an error status (error body decoded)
+    Registry {
+        url: String,
+        status: u16,
+        errors: Vec<RegistryErrorDetail>,
+    },
+    // connection, tls, proxy or timeout
+    Network(String),
+    // downloaded or unpacked content does not match its digest
+    DigestMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    // untar of layers or archives
+    Extraction(String),
+    // file based catalog read or parse
+    Catalog(String),
+    // manifests and other json / yaml documents
+    Parse(String),
+    Io(io::Error),
+    Other(String),
+    // 'context' explains what was being done when 'source' happened
+    Context {
+        context: String,
+        source: Box<MirrorError>,
+    },
+}
+
+// RegistryErrorCode error codes of the distribution spec that need
+// different handling (the rest are kept as Other)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryErrorCode {
+    ManifestUnknown,
+    ManifestInvalid,
+    BlobUnknown,
+    NameUnknown,
+    Unauthorized,
+    Denied,
+    TooManyRequests,
+    Other(String),
+}
+
+impl RegistryErrorCode {
+    // parse - the typed code of a registry error body entry
+    pub fn parse(code: &str) -> Self {
+        match code {
+            "MANIFEST_UNKNOWN" => RegistryErrorCode::ManifestUnknown,
+            "MANIFEST_INVALID" => RegistryErrorCode::ManifestInvalid,
+            "BLOB_UNKNOWN" => RegistryErrorCode::BlobUnknown,
+            "NAME_UNKNOWN" => RegistryErrorCode::NameUnknown,
+            "UNAUTHORIZED" => RegistryErrorCode::Unauthorized,
+            "DENIED" => RegistryErrorCode::Denied,
+            "TOOMANYREQUESTS" => RegistryErrorCode::TooManyRequests,
+            _ => RegistryErrorCode::Other(code.to_string()),
+        }
+    }
+
+    // from_status - the code implied by the status of a response without an
+    // error body (e.g. HEAD requests), 'url' tells manifests and blobs apart
+    pub fn from_status(status: u16, url: &str) -> Option<Self> {
+        match status {
+            401 => Some(RegistryErrorCode::Unauthorized),
+            403 => Some(RegistryErrorCode::Denied),
+            429 => Some(RegistryErrorCode::TooManyRequests),
+            404 if url.contains("/manifests/") => Some(RegistryErrorCode::ManifestUnknown),
+            404 if url.contains("/blobs/") => Some(RegistryErrorCode::BlobUnknown),
+            404 => Some(RegistryErrorCode::NameUnknown),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RegistryErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            RegistryErrorCode::ManifestUnknown => "MANIFEST_UNKNOWN",
+            RegistryErrorCode::ManifestInvalid => "MANIFEST_INVALID",
+            RegistryErrorCode::BlobUnknown => "BLOB_UNKNOWN",
+            RegistryErrorCode::NameUnknown => "NAME_UNKNOWN",
+            RegistryErrorCode::Unauthorized => "UNAUTHORIZED",
+            RegistryErrorCode::Denied => "DENIED",
+            RegistryErrorCode::TooManyRequests => "TOOMANYREQUESTS",
+            RegistryErrorCode::Other(code) => code,
+        };
+        write!(f, "{}", code)
+    }
+}
+
+impl RegistryErrorDetail {
+    // kind - the typed code of this entry
+    pub fn kind(&self) -> RegistryErrorCode {
+        RegistryErrorCode::parse(&self.code)
+    }
+}
+
+impl MirrorError {
+    // exit_code - the cli exit code for this kind of error, registry errors
+    // refusing the credentials count as auth errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MirrorError::Context { source, .. } => source.exit_code(),
+            MirrorError::Config(_) => 2,
+            MirrorError::Auth(_) => 3,
+            MirrorError::Registry { .. } => match self.registry_code() {
+                Some(RegistryErrorCode::Unauthorized) | Some(RegistryErrorCode::Denied) => 3,
+                _ => 4,
+            },
+            MirrorError::Network(_) => 5,
+            MirrorError::DigestMismatch { .. } => 6,
+            MirrorError::Extraction(_) => 7,
+            MirrorError::Catalog(_) => 8,
+            MirrorError::Parse(_) | MirrorError::Io(_) | MirrorError::Other(_) => 1,
+        }
+    }
+
+    // is_transient - true for errors worth retrying (connection problems,
+    // rate limits, server errors and corrupted transfers)
+    pub fn is_transient(&self) -> bool {
+        match self.root() {
+            MirrorError::Network(_) | MirrorError::DigestMismatch { .. } => true,
+            MirrorError::Registry { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    // registry_code - the (first) registry error code of the chain, if any
+    pub fn registry_code(&self) -> Option<RegistryErrorCode> {
+        match self.root() {
+            MirrorError::Registry { errors, .. } => errors.first().map(|e| e.kind()),
+            _ => None,
+        }
+    }
+
+    // root - the innermost error of a context chain
+    pub fn root(&self) -> &MirrorError {
+        match self {
+            MirrorError::Context { source, .. } => source.root(),
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for MirrorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MirrorError::Config(msg) => write!(f, "config: {}", msg),
+            MirrorError::Auth(msg) => write!(f, "auth: {}", msg),
+            MirrorError::Registry {
+                url,
+                status,
+                errors,
+            } => {
+                write!(f, "registry: {} returned {}", url, status)?;
+                for error in errors.iter() {
+                    write!(f, " [{}] {}", error.code, error.message)?;
+                    if let Some(detail) = &error.detail {
+                        write!(f, " ({})", detail)?;
+                    }
+                }
+                match errors.first().map(|e| e.kind()) {
+                    Some(RegistryErrorCode::Unauthorized) => {
+                        write!(f, ", check the credentials in auth.json")
+                    }
+                    Some(RegistryErrorCode::Denied) => {
+                        write!(f, ", the credentials have no access to this repository")
+                    }
+                    Some(RegistryErrorCode::TooManyRequests) => {
+                        write!(f, ", rate limited by the registry, retry later")
+                    }
+                    _ => Ok(()),
+                }
+            }
+            MirrorError::Network(msg) => write!(f, "network: {}", msg),
+            MirrorError::DigestMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "digest mismatch: {} expected {} got {}",
+                name, expected, actual
+            ),
+            MirrorError::Extraction(msg) => write!(f, "extraction: {}", msg),
+            MirrorError::Catalog(msg) => write!(f, "catalog: {}", msg),
+            MirrorError::Parse(msg) => write!(f, "parse: {}", msg),
+            MirrorError::Io(error) => write!(f, "io: {}", error),
+            MirrorError::Other(msg) => write!(f, "{}", msg),
+            MirrorError::Context { context, source } => write!(f, "{} : {}", context, source),
+        }
+    }
+}
+
+impl Error for MirrorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MirrorError::Context { source, .. } => Some(source.as_ref()),
+            MirrorError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MirrorError {
+    fn from(error: io::Error) -> Self {
+        MirrorError::Io(error)
+    }
+}
+
+impl From<reqwest::Error> for MirrorError {
+    fn from(error: reqwest::Error) -> Self {
+        match error.status() {
+            Some(status) => {
+                let url = error.url().map(|u| u.to_string()).unwrap_or_default();
+                MirrorError::Registry {
+                    errors: status_errors(status, &url),
+                    url,
+                    status: status.as_u16(),
+                }
+            }
+            None => MirrorError::Network(error.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for MirrorError {
+    fn from(error: serde_json::Error) -> Self {
+        MirrorError::Parse(error.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for MirrorError {
+    fn from(error: serde_yaml::Error) -> Self {
+        MirrorError::Parse(error.to_string())
+    }
+}
+
+impl From<String> for MirrorError {
+    fn from(msg: String) -> Self {
+        MirrorError::Other(msg)
+    }
+}
+
+impl From<&str> for MirrorError {
+    fn from(msg: &str) -> Self {
+        MirrorError::Other(msg.to_string())
+    }
+}
+
+// status_errors - a registry error entry for a response without an error body
+pub fn status_errors(status: reqwest::StatusCode, url: &str) -> Vec<RegistryErrorDetail> {
+    match RegistryErrorCode::from_status(status.as_u16(), url) {
+        Some(code) => vec![RegistryErrorDetail {
+            code: code.to_string(),
+            message: status.canonical_reason().unwrap_or_default().to_lowercase(),
+            detail: None,
+        }],
+        None => vec![],
+    }
+}
+
+// ErrorContext - add what was being done to an error, e.g.
+// fs::read(file).context(format!("reading {}", file))?
+pub trait ErrorContext<T> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T, MirrorError>;
+}
+
+impl<T, E: Into<MirrorError>> ErrorContext<T> for Result<T, E> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T, MirrorError> {
+        self.map_err(|error| MirrorError::Context {
+            context: context.to_string(),
+            source: Box::new(error.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(code: &str) -> MirrorError {
+        MirrorError::Registry {
+            url: "https://quay.io/v2/a/manifests/v1".to_string(),
+            status: 400,
+            errors: vec![RegistryErrorDetail {
+                code: code.to_string(),
+                message: String::new(),
+                detail: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        let tests = vec![
+            (MirrorError::Config(String::new()), 2),
+            (MirrorError::Auth(String::new()), 3),
+            (registry("UNAUTHORIZED"), 3),
+            (registry("DENIED"), 3),
+            (registry("MANIFEST_UNKNOWN"), 4),
+            (registry("TOOMANYREQUESTS"), 4),
+            (
+                MirrorError::Registry {
+                    url: String::new(),
+                    status: 500,
+                    errors: vec![],
+                },
+                4,
+            ),
+            (MirrorError::Network(String::new()), 5),
+            (
+                MirrorError::DigestMismatch {
+                    name: String::new(),
+                    expected: String::new(),
+                    actual: String::new(),
+                },
+                6,
+            ),
+            (MirrorError::Extraction(String::new()), 7),
+            (MirrorError::Catalog(String::new()), 8),
+            (MirrorError::Parse(String::new()), 1),
+            (MirrorError::Io(io::Error::other("io")), 1),
+            (MirrorError::Other(String::new()), 1),
+            // the code of a context chain is the code of its root
+            (
+                MirrorError::Context {
+                    context: "pulling".to_string(),
+                    source: Box::new(MirrorError::Context {
+                        context: "manifest".to_string(),
+                        source: Box::new(registry("DENIED")),
+                    }),
+                },
+                3,
+            ),
+        ];
+        for (error, code) in tests {
+            assert_eq!(error.exit_code(), code, "{:?}", error);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 // module api
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::de::Error;
+use serde::Deserializer;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -27,65 +30,252 @@ pub struct FsLayer {
     pub blob_sum: String,
 }
 
+// ManifestV2 covers docker v2 schema 2 and oci manifests as well as
+// manifest lists / oci indexes (where only 'manifests' is populated)
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Token {
-    pub token: String,
-    #[serde(rename = "access_token")]
-    pub access_token: String,
-    #[serde(rename = "expires_in")]
-    pub expires_in: i64,
-    #[serde(rename = "issued_at")]
-    pub issued_at: String,
+pub struct ManifestV2 {
+    pub schema_version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<Layer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layers: Option<Vec<Layer>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifests: Option<Vec<Layer>>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Root {
-    pub auths: Auths,
+pub struct Layer {
+    pub media_type: Option<String>,
+    pub digest: String,
+    pub size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<ManifestPlatform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
+// ManifestPlatform platform of an entry in a manifest list / oci index
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Auths {
-    #[serde(rename = "cloud.openshift.com")]
-    pub cloud_openshift_com: CloudOpenshiftCom,
-    #[serde(rename = "quay.io")]
-    pub quay_io: QuayIo,
-    #[serde(rename = "registry.connect.redhat.com")]
-    pub registry_connect_redhat_com: RegistryConnectRedhatCom,
-    #[serde(rename = "registry.redhat.io")]
-    pub registry_redhat_io: RegistryRedhatIo,
+pub struct ManifestPlatform {
+    #[serde(rename = "architecture")]
+    pub architecture: String,
+
+    #[serde(rename = "os")]
+    pub os: String,
+
+    #[serde(rename = "variant", skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CloudOpenshiftCom {
-    pub auth: String,
-    pub email: Option<String>,
+// ImageConfig the image config blob (also the v1Compatibility of a schema 1 manifest)
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ImageConfig {
+    #[serde(rename = "architecture", default)]
+    pub architecture: String,
+
+    #[serde(rename = "os", default)]
+    pub os: String,
+
+    #[serde(rename = "created", default)]
+    pub created: Option<String>,
+
+    #[serde(rename = "config", default)]
+    pub config: Option<ContainerConfig>,
+
+    #[serde(rename = "history", default)]
+    pub history: Option<Vec<ImageHistory>>,
+}
+
+// ContainerConfig the runtime config of an image
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ContainerConfig {
+    #[serde(rename = "Labels", default)]
+    pub labels: Option<HashMap<String, String>>,
+
+    #[serde(rename = "Env", default)]
+    pub env: Option<Vec<String>>,
+
+    #[serde(rename = "Entrypoint", default)]
+    pub entrypoint: Option<Vec<String>>,
+
+    #[serde(rename = "Cmd", default)]
+    pub cmd: Option<Vec<String>>,
+}
+
+// ImageHistory a step of the image build
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ImageHistory {
+    #[serde(rename = "created", default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+
+    #[serde(rename = "created_by", default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+
+    #[serde(rename = "comment", default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+
+    #[serde(rename = "empty_layer", default)]
+    pub empty_layer: bool,
+}
+
+// RegistryErrors error body returned by a registry (distribution spec)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistryErrors {
+    #[serde(rename = "errors", default)]
+    pub errors: Vec<RegistryErrorDetail>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistryErrorDetail {
+    #[serde(rename = "code", default)]
+    pub code: String,
+
+    #[serde(rename = "message", default)]
+    pub message: String,
+
+    #[serde(rename = "detail", default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<serde_json::Value>,
+}
+
+// TagList response of the tags/list registry api
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagList {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "tags", default)]
+    pub tags: Option<Vec<String>>,
+}
+
+// RepositoryList response of the _catalog registry api
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepositoryList {
+    #[serde(rename = "repositories", default)]
+    pub repositories: Option<Vec<String>>,
+}
+
+// ManifestDescriptor digest, media type and size of a manifest (HEAD request)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestDescriptor {
+    #[serde(rename = "digest")]
+    pub digest: String,
+
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+
+    #[serde(rename = "size")]
+    pub size: i64,
+}
+
+// InspectResult summary of an image printed by the inspect action
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InspectResult {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "digest")]
+    pub digest: String,
+
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+
+    #[serde(rename = "size")]
+    pub size: i64,
+
+    #[serde(rename = "platforms", skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+
+    #[serde(rename = "architecture")]
+    pub architecture: String,
+
+    #[serde(rename = "os")]
+    pub os: String,
+
+    #[serde(rename = "created")]
+    pub created: String,
+
+    #[serde(rename = "labels")]
+    pub labels: BTreeMap<String, String>,
+
+    #[serde(rename = "env")]
+    pub env: Vec<String>,
+
+    #[serde(rename = "entrypoint")]
+    pub entrypoint: Vec<String>,
+
+    #[serde(rename = "cmd")]
+    pub cmd: Vec<String>,
+
+    #[serde(rename = "layers")]
+    pub layers: Vec<Layer>,
+
+    #[serde(rename = "history")]
+    pub history: Vec<ImageHistory>,
+
+    #[serde(rename = "referrers")]
+    pub referrers: Vec<Layer>,
+}
+
+// DockerArchiveManifest an entry in the manifest.json of a docker-archive
+// (docker save) tarball
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DockerArchiveManifest {
+    #[serde(rename = "Config")]
+    pub config: String,
+
+    #[serde(rename = "RepoTags", default)]
+    pub repo_tags: Option<Vec<String>>,
+
+    #[serde(rename = "Layers")]
+    pub layers: Vec<String>,
 }
 
+// Token response of a token realm, registries return 'token' and/or 'access_token'
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct QuayIo {
-    pub auth: String,
-    pub email: Option<String>,
+pub struct Token {
+    #[serde(default)]
+    pub token: String,
+    #[serde(rename = "access_token", default)]
+    pub access_token: String,
+    #[serde(rename = "expires_in", default)]
+    pub expires_in: u64,
+    #[serde(rename = "issued_at", default)]
+    pub issued_at: String,
 }
 
+// Root auth.json (containers-auth.json), credentials keyed by registry
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct RegistryConnectRedhatCom {
-    pub auth: String,
-    pub email: Option<String>,
+pub struct Root {
+    #[serde(default)]
+    pub auths: HashMap<String, RegistryAuth>,
 }
 
+// RegistryAuth base64 encoded 'user:password' of a registry
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct RegistryRedhatIo {
+pub struct RegistryAuth {
+    #[serde(default)]
     pub auth: String,
     pub email: Option<String>,
 }
 
+// AuthChallenge the WWW-Authenticate header of a 401 from a registry
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct AuthChallenge {
+    pub scheme: String,
+    pub realm: String,
+    pub service: Option<String>,
+    pub scope: Option<String>,
+}
+
 /// rust-container-tool cli struct
 #[derive(Parser, Debug)]
 #[command(name = "rust-container-tool")]
@@ -94,21 +284,349 @@ pub struct RegistryRedhatIo {
 #[command(about = "Used to mirror images from registry to disk and disk to registry", long_about = None)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// config file to use
-    #[arg(short, long, value_name = "config", default_value = "")]
-    pub config: Option<String>,
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// image set config file to use
+    #[arg(short, long, value_name = "config", default_value = "", global = true)]
+    pub config: String,
+
+    /// directory for catalogs, mirrored content and results
+    #[arg(long, value_name = "working-dir", default_value = "working-dir", global = true)]
+    pub working_dir: String,
+
+    /// registry credentials to use instead of $XDG_RUNTIME_DIR/containers/auth.json
+    #[arg(long, value_name = "authfile", default_value = "", global = true)]
+    pub authfile: String,
+
+    /// registries.conf to use instead of the containers defaults
+    /// ($CONTAINERS_REGISTRIES_CONF, ~/.config/containers or /etc/containers)
+    #[arg(long, value_name = "registries-conf", default_value = "", global = true)]
+    pub registries_conf: String,
+
+    /// verify registry certificates (--tls-verify=false disables verification for all registries)
+    #[arg(
+        long,
+        value_name = "tls-verify",
+        default_value = "true",
+        action = clap::ArgAction::Set,
+        global = true
+    )]
+    pub tls_verify: bool,
+
+    /// extra pem CA bundle trusted for all registries (can be repeated)
+    #[arg(long, value_name = "ca-file", global = true)]
+    pub ca_file: Vec<String>,
+
+    /// directory with per registry certificates (<host[:port]>/*.crt, *.cert and *.key)
+    /// instead of /etc/containers/certs.d, ~/.config/containers/certs.d and /etc/docker/certs.d
+    #[arg(long, value_name = "cert-dir", default_value = "", global = true)]
+    pub cert_dir: String,
+
+    /// request timeout in seconds for registry calls (0 for no limit)
+    #[arg(long, value_name = "timeout", default_value = "0", global = true)]
+    pub timeout: u64,
+
+    /// more log output (-v debug, -vv trace)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// less log output (-q warnings and errors, -qq errors only)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// log level, takes precedence over -v / -q
+    #[arg(
+        long,
+        value_name = "log-level",
+        value_parser = ["error", "warn", "info", "debug", "trace"],
+        global = true
+    )]
+    pub log_level: Option<String>,
+
+    /// log format on stderr (text or json lines with image, digest, bytes and duration_ms fields)
+    #[arg(
+        long,
+        value_name = "log-format",
+        default_value = "text",
+        value_parser = ["text", "json"],
+        global = true
+    )]
+    pub log_format: String,
+}
+
+/// rust-container-tool commands
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Download and extract a catalog index (prints its 'configs' directory)
+    Pull(CatalogArgs),
+
+    /// Push the images mirrored in the working dir to their destination
+    Push,
+
+    /// Mirror the bundle and related images of the operators in a catalog
+    Mirror(MirrorArgs),
+
+    /// Mirror the release payloads of the platform channels in the image set config
+    Release(ReleaseArgs),
+
+    /// List the packages of a catalog
+    List(ListArgs),
+
+    /// Render the upgrade graph of each channel
+    Graph(GraphArgs),
+
+    /// Search the packages of a catalog (or of every cached catalog)
+    Search(SearchArgs),
+
+    /// Show the packages, channels and bundles added, removed or changed between two catalogs
+    Diff(DiffArgs),
+
+    /// Validate a catalog (exits 1 when errors are found)
+    Validate(ValidateArgs),
+
+    /// Show the manifest, config, layers, history and referrers of an image without pulling it
+    Inspect(InspectArgs),
+
+    /// List the tags of a repository in version order
+    Tags(TagsArgs),
+
+    /// List the repositories of a registry (_catalog api)
+    Repositories(RepositoriesArgs),
+
+    /// Bundle the mirrored content into archives for an air-gapped transfer
+    Pack(PackArgs),
+
+    /// Extract and verify archives into the working dir
+    Unpack(ArchiveArgs),
+
+    /// Unpack archives and push their images to the destination registry
+    Publish(PublishArgs),
+
+    /// Remove mirrored blobs that no manifest refers to
+    Gc(GcArgs),
+
+    /// Generate shell completions
+    Completions(CompletionsArgs),
+}
+
+// OutputFormat --output of the commands printing a result (text when not set)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Yaml,
+}
+
+// ListFormat --output of the list command, table and wide print one row per bundle
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    Json,
+    Yaml,
+    Table,
+    Wide,
+}
+
+// GraphFormat --output of the graph command
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Args, Debug)]
+pub struct CatalogArgs {
+    /// catalog index image, local 'configs' directory or local source
+    /// (oci:<path>[:<tag>], docker-archive:<file.tar> or dir:<path>)
+    #[arg(value_name = "catalog")]
+    pub catalog: String,
+}
+
+#[derive(Args, Debug)]
+pub struct MirrorArgs {
+    #[command(flatten)]
+    pub catalog: CatalogArgs,
+
+    /// only mirror this package (default: the packages in the image set config or all)
+    #[arg(short, long, value_name = "filter", default_value = "all")]
+    pub filter: String,
+
+    /// destination registry (and optional namespace prefix) used for the mirror mappings
+    #[arg(short, long, value_name = "destination", default_value = "localhost:5000")]
+    pub destination: String,
+}
 
-    /// image-index to list
-    #[arg(short, long, value_name = "image-index")]
+#[derive(Args, Debug)]
+pub struct ReleaseArgs {
+    /// cincinnati update graph endpoint or a local graph json file
+    #[arg(
+        short,
+        long,
+        value_name = "graph-url",
+        default_value = "https://api.openshift.com/api/upgrades_info/v1/graph"
+    )]
+    pub graph_url: String,
+
+    /// destination registry (and optional namespace prefix) used for the mirror mappings
+    #[arg(short, long, value_name = "destination", default_value = "localhost:5000")]
+    pub destination: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub catalog: CatalogArgs,
+
+    /// only list this package
+    #[arg(short, long, value_name = "filter", default_value = "all")]
+    pub filter: String,
+
+    /// output format (default: channels and bundles per package as log lines)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<ListFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct GraphArgs {
+    #[command(flatten)]
+    pub catalog: CatalogArgs,
+
+    /// only render the graphs of this package
+    #[arg(short, long, value_name = "filter", default_value = "all")]
+    pub filter: String,
+
+    /// output format
+    #[arg(short, long, value_name = "output", value_enum, default_value_t = GraphFormat::Dot)]
+    pub output: GraphFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// search terms matched against package names, descriptions, provided apis
+    /// (crd kinds), providers, categories and keywords
+    #[arg(value_name = "query")]
+    pub query: String,
+
+    /// catalog to search (default: every cached catalog)
+    #[arg(long, value_name = "catalog", default_value = "")]
+    pub catalog: String,
+
+    /// output format (default: text)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[command(flatten)]
+    pub catalog: CatalogArgs,
+
+    /// catalog index, local 'configs' directory or local source to compare against
+    #[arg(value_name = "compare")]
+    pub compare: String,
+
+    /// output format (default: text)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub catalog: CatalogArgs,
+
+    /// only validate this package
+    #[arg(short, long, value_name = "filter", default_value = "all")]
+    pub filter: String,
+
+    /// output format (default: text)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct InspectArgs {
+    /// image to inspect (manifest lists are followed to linux/amd64 or the first image)
+    #[arg(value_name = "image")]
     pub image: String,
 
-    /// action
-    #[arg(short, long, value_name = "action")]
-    pub action: String,
+    /// output format (default: text)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct TagsArgs {
+    /// repository (registry/namespace/name)
+    #[arg(value_name = "repository")]
+    pub repository: String,
+
+    /// only list the tags starting with this prefix
+    #[arg(short, long, value_name = "filter", default_value = "all")]
+    pub filter: String,
+
+    /// output format (default: one tag per line)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct RepositoriesArgs {
+    /// registry host[:port]
+    #[arg(value_name = "registry")]
+    pub registry: String,
 
-    /// used to filter specific component for list action
+    /// only list the repositories containing this text
     #[arg(short, long, value_name = "filter", default_value = "all")]
-    pub filter: Option<String>,
+    pub filter: String,
+
+    /// output format (default: one repository per line)
+    #[arg(short, long, value_name = "output")]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct PackArgs {
+    #[command(flatten)]
+    pub archive: ArchiveArgs,
+
+    /// maximum size of each archive in MiB
+    #[arg(long, value_name = "archive-size", default_value = "4096")]
+    pub archive_size: u64,
+
+    /// destination registry (and optional namespace prefix) of the catalog image
+    #[arg(short, long, value_name = "destination", default_value = "localhost:5000")]
+    pub destination: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ArchiveArgs {
+    /// directory the archives are written to (pack) or read from (unpack, publish)
+    #[arg(long, value_name = "archive-dir", default_value = "archives")]
+    pub archive_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct PublishArgs {
+    #[command(flatten)]
+    pub archive: ArchiveArgs,
+
+    /// registry (and optional namespace prefix) to publish to instead of the mirror destination
+    #[arg(short, long, value_name = "destination")]
+    pub destination: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct GcArgs {
+    /// only report the blobs that would be removed
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// shell to generate the completions for
+    #[arg(value_name = "shell")]
+    pub shell: clap_complete::Shell,
 }
 
 /// config schema
@@ -125,6 +643,38 @@ pub struct ImageSetConfig {
 
     #[serde(rename = "mirror")]
     pub mirror: Mirror,
+
+    #[serde(rename = "proxy")]
+    pub proxy: Option<ProxyConfig>,
+}
+
+// ProxyConfig proxies used for registry calls (instead of HTTPS_PROXY etc.)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ProxyConfig {
+    #[serde(rename = "httpProxy")]
+    pub http_proxy: Option<String>,
+
+    #[serde(rename = "httpsProxy")]
+    pub https_proxy: Option<String>,
+
+    #[serde(rename = "noProxy", default)]
+    pub no_proxy: Vec<String>,
+
+    #[serde(rename = "registries", default)]
+    pub registries: Vec<RegistryProxy>,
+}
+
+// RegistryProxy per registry override, either a proxy url or a direct connection
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RegistryProxy {
+    #[serde(rename = "registry")]
+    pub registry: String,
+
+    #[serde(rename = "proxy")]
+    pub proxy: Option<String>,
+
+    #[serde(rename = "direct", default)]
+    pub direct: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -142,173 +692,819 @@ pub struct Mirror {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Image {
     #[serde(rename = "name")]
-    name: String,
+    pub name: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Operator {
     #[serde(rename = "catalog")]
-    catalog: String,
+    pub catalog: String,
 
     #[serde(rename = "packages")]
-    packages: Option<Vec<Image>>,
+    pub packages: Option<Vec<Image>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Platform {
     #[serde(rename = "channels")]
-    channels: Vec<ChannelP>,
+    pub channels: Vec<ChannelP>,
 
     #[serde(rename = "graph")]
-    graph: bool,
+    pub graph: bool,
+
+    // release architectures (amd64 when empty), 'multi' selects the
+    // manifest list payloads
+    #[serde(rename = "architectures", default)]
+    pub architectures: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChannelP {
     #[serde(rename = "name")]
-    name: String,
+    pub name: String,
 
     #[serde(rename = "type")]
-    channel_type: String,
+    pub channel_type: String,
+
+    #[serde(rename = "minVersion")]
+    pub min_version: Option<String>,
+
+    #[serde(rename = "maxVersion")]
+    pub max_version: Option<String>,
+
+    #[serde(rename = "shortestPath")]
+    pub shortest_path: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StorageConfig {
     #[serde(rename = "local")]
-    local: Local,
+    pub local: Local,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Local {
     #[serde(rename = "path")]
-    path: String,
+    pub path: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Catalog {
-    #[serde(rename = "overview")]
-    pub overview: serde_json::Value,
+// DeclarativeConfig a single file based catalog object tagged on 'schema'
+// objects with a schema we don't know about are passed through untouched
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "schema")]
+pub enum DeclarativeConfig {
+    #[serde(rename = "olm.package")]
+    Package(Package),
+
+    #[serde(rename = "olm.channel")]
+    Channel(Channel),
+
+    #[serde(rename = "olm.bundle")]
+    Bundle(Bundle),
+
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
 }
 
-// DeclarativeConfig this updates the existing dclrcfg
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DeclarativeEntries {
-    #[serde(rename = "entries")]
-    pub entries: Option<Vec<ChannelEntry>>,
+// deserialize on the 'schema' field so that errors in known objects are
+// reported rather than silently treated as an unknown schema
+impl<'de> serde::Deserialize<'de> for DeclarativeConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <serde_json::Value as serde::Deserialize>::deserialize(deserializer)?;
+        let res = match value.get("schema").and_then(|s| s.as_str()) {
+            Some("olm.package") => serde_json::from_value(value).map(DeclarativeConfig::Package),
+            Some("olm.channel") => serde_json::from_value(value).map(DeclarativeConfig::Channel),
+            Some("olm.bundle") => serde_json::from_value(value).map(DeclarativeConfig::Bundle),
+            _ => return Ok(DeclarativeConfig::Unknown(value)),
+        };
+        res.map_err(D::Error::custom)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DeclarativeConfig {
-    #[serde(rename = "schema")]
-    pub schema: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RelatedImage {
+    #[serde(rename = "name", default)]
+    pub name: String,
+
+    #[serde(rename = "image")]
+    pub image: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Package {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "defaultChannel")]
+    pub default_channel: Option<String>,
+
+    #[serde(rename = "description", skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(rename = "icon", skip_serializing_if = "Option::is_none")]
+    pub icon: Option<serde_json::Value>,
+}
 
+// Channel used in parsing channel data
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Channel {
     #[serde(rename = "name")]
     pub name: String,
 
     #[serde(rename = "package")]
-    pub package: Option<String>,
+    pub package: String,
 
-    #[serde(rename = "relatedImages")]
-    pub related_images: Option<Vec<RelatedImage>>,
+    #[serde(rename = "entries", default)]
+    pub entries: Vec<ChannelEntry>,
+}
 
-    #[serde(rename = "defaultChannel")]
-    pub default_channel: Option<String>,
+// ChannelEntry used in the Channel struct
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelEntry {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "replaces", skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+
+    #[serde(rename = "skips", skip_serializing_if = "Option::is_none")]
+    pub skips: Option<Vec<String>>,
+
+    #[serde(rename = "skipRange", skip_serializing_if = "Option::is_none")]
+    pub skip_range: Option<String>,
+}
+
+// Bundle specifies all metadata and data of a bundle object.
+// Top-level fields are the source of truth, i.e. not CSV values.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bundle {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "package")]
+    pub package: String,
+
+    #[serde(rename = "image", default)]
+    pub image: String,
+
+    #[serde(rename = "properties", default)]
+    pub properties: Vec<Property>,
+
+    #[serde(rename = "relatedImages", default)]
+    pub related_images: Vec<RelatedImage>,
+}
+
+// Property a bundle property tagged on 'type' with its 'value'
+// unknown property types are passed through untouched
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "value")]
+pub enum Property {
+    #[serde(rename = "olm.package")]
+    Package(PackageProperty),
+
+    #[serde(rename = "olm.gvk")]
+    Gvk(GvkProperty),
+
+    #[serde(rename = "olm.bundle.object")]
+    BundleObject(BundleObjectProperty),
+
+    #[serde(rename = "olm.csv.metadata")]
+    CsvMetadata(CsvMetadata),
+
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
 
-    #[serde(rename = "description")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackageProperty {
+    #[serde(rename = "packageName")]
+    pub package_name: String,
+
+    #[serde(rename = "version")]
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GvkProperty {
+    #[serde(rename = "group")]
+    pub group: String,
+
+    #[serde(rename = "kind")]
+    pub kind: String,
+
+    #[serde(rename = "version")]
+    pub version: String,
+}
+
+// BundleObjectProperty base64 encoded manifest (usually the csv or a crd)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BundleObjectProperty {
+    #[serde(rename = "data")]
+    pub data: String,
+}
+
+// CsvMetadata the subset of the csv carried in the catalog
+// fields we don't use are kept in 'extra' so the object can be written back
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CsvMetadata {
+    #[serde(rename = "annotations", skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+
+    #[serde(rename = "description", skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+
+    #[serde(rename = "keywords", skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<Vec<String>>,
+
+    #[serde(rename = "provider", skip_serializing_if = "Option::is_none")]
+    pub provider: Option<Provider>,
+
+    #[serde(rename = "maturity", skip_serializing_if = "Option::is_none")]
+    pub maturity: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Provider {
+    #[serde(rename = "name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(rename = "url", skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Bundle {
+    // version of the bundle from the olm.package property
+    pub fn version(&self) -> Option<String> {
+        self.properties.iter().find_map(|p| match p {
+            Property::Package(pkg) => Some(pkg.version.clone()),
+            _ => None,
+        })
+    }
 }
 
+// MirrorReport summary of a related images mirror run
+// also used as the source -> destination mapping for later steps
 #[derive(Serialize, Deserialize, Debug)]
-pub struct RelatedImage {
+pub struct MirrorReport {
+    #[serde(rename = "catalog")]
+    pub catalog: String,
+
+    #[serde(rename = "total")]
+    pub total: usize,
+
+    #[serde(rename = "unique")]
+    pub unique: usize,
+
+    #[serde(rename = "mirrored")]
+    pub mirrored: usize,
+
+    #[serde(rename = "failed")]
+    pub failed: usize,
+
+    #[serde(rename = "images")]
+    pub images: Vec<MirroredImage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MirroredImage {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "source")]
+    pub source: String,
+
+    #[serde(rename = "destination")]
+    pub destination: String,
+
+    #[serde(rename = "digest")]
+    pub digest: String,
+
+    #[serde(rename = "mirrored")]
+    pub mirrored: bool,
+}
+
+// ArchiveMetadata written to every set of archives created by the pack action
+// lists the images to publish and the checksum of every file in the archives
+// sequence 0 is a full (non incremental) set
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchiveMetadata {
+    #[serde(rename = "sequence", default)]
+    pub sequence: u32,
+
+    #[serde(rename = "created")]
+    pub created: u64,
+
+    #[serde(rename = "images")]
+    pub images: Vec<MirroredImage>,
+
+    #[serde(rename = "files")]
+    pub files: Vec<ArchiveFile>,
+}
+
+// StorageMetadata every sequence packed (kept in the storage config path) or
+// applied (kept in the working dir on the disconnected side)
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct StorageMetadata {
+    #[serde(rename = "sequences")]
+    pub sequences: Vec<ArchiveMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchiveFile {
+    #[serde(rename = "path")]
+    pub path: String,
+
+    #[serde(rename = "digest")]
+    pub digest: String,
+
+    #[serde(rename = "size")]
+    pub size: u64,
+}
+
+// cluster resources generated from the mirror mappings
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObjectMeta {
     #[serde(rename = "name")]
     pub name: String,
 
+    #[serde(rename = "namespace", skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+// ImageContentSourcePolicy (operator.openshift.io/v1alpha1)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageContentSourcePolicy {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+
+    #[serde(rename = "kind")]
+    pub kind: String,
+
+    #[serde(rename = "metadata")]
+    pub metadata: ObjectMeta,
+
+    #[serde(rename = "spec")]
+    pub spec: ImageContentSourcePolicySpec,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageContentSourcePolicySpec {
+    #[serde(rename = "repositoryDigestMirrors")]
+    pub repository_digest_mirrors: Vec<MirrorSource>,
+}
+
+// ImageDigestMirrorSet and ImageTagMirrorSet (config.openshift.io/v1)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageMirrorSet {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+
+    #[serde(rename = "kind")]
+    pub kind: String,
+
+    #[serde(rename = "metadata")]
+    pub metadata: ObjectMeta,
+
+    #[serde(rename = "spec")]
+    pub spec: ImageMirrorSetSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageMirrorSetSpec {
+    #[serde(rename = "imageDigestMirrors", skip_serializing_if = "Option::is_none")]
+    pub image_digest_mirrors: Option<Vec<MirrorSource>>,
+
+    #[serde(rename = "imageTagMirrors", skip_serializing_if = "Option::is_none")]
+    pub image_tag_mirrors: Option<Vec<MirrorSource>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MirrorSource {
+    #[serde(rename = "source")]
+    pub source: String,
+
+    #[serde(rename = "mirrors")]
+    pub mirrors: Vec<String>,
+}
+
+// CatalogSource (operators.coreos.com/v1alpha1)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatalogSource {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+
+    #[serde(rename = "kind")]
+    pub kind: String,
+
+    #[serde(rename = "metadata")]
+    pub metadata: ObjectMeta,
+
+    #[serde(rename = "spec")]
+    pub spec: CatalogSourceSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatalogSourceSpec {
+    #[serde(rename = "sourceType")]
+    pub source_type: String,
+
     #[serde(rename = "image")]
     pub image: String,
 }
 
+// Graph cincinnati update graph
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Package {
-    #[serde(rename = "schema")]
-    pub schema: String,
+pub struct Graph {
+    #[serde(rename = "nodes")]
+    pub nodes: Vec<GraphNode>,
+
+    #[serde(rename = "edges")]
+    pub edges: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphNode {
+    #[serde(rename = "version")]
+    pub version: String,
+
+    #[serde(rename = "payload")]
+    pub payload: String,
+
+    #[serde(rename = "metadata")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+// ImageStream used in the release payload 'release-manifests/image-references'
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageStream {
+    #[serde(rename = "kind")]
+    pub kind: String,
+
+    #[serde(rename = "spec")]
+    pub spec: ImageStreamSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageStreamSpec {
+    #[serde(rename = "tags")]
+    pub tags: Vec<TagReference>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagReference {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "from")]
+    pub from: ObjectReference,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObjectReference {
+    #[serde(rename = "kind")]
+    pub kind: String,
 
     #[serde(rename = "name")]
     pub name: String,
+}
+
+// PackageSummary structured list output for a single package
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageSummary {
+    #[serde(rename = "name")]
+    pub name: String,
 
     #[serde(rename = "defaultChannel")]
     pub default_channel: Option<String>,
 
-    #[serde(rename = "description")]
-    pub description: String,
+    #[serde(rename = "channels")]
+    pub channels: Vec<ChannelSummary>,
+
+    #[serde(rename = "bundles")]
+    pub bundles: Vec<BundleSummary>,
 }
 
-// Channel used in parsing channel data
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Channel {
-    #[serde(rename = "schema")]
-    pub schema: String,
-
+pub struct ChannelSummary {
     #[serde(rename = "name")]
     pub name: String,
 
-    #[serde(rename = "package")]
-    pub package: String,
+    #[serde(rename = "head")]
+    pub head: Option<String>,
 
     #[serde(rename = "entries")]
-    pub entries: Vec<ChannelEntry>,
+    pub entries: Vec<EntrySummary>,
 }
 
-// ChannelEntry used in the Channel struct
 #[derive(Serialize, Deserialize, Debug)]
-pub struct ChannelEntry {
+pub struct EntrySummary {
     #[serde(rename = "name")]
     pub name: String,
 
-    #[serde(rename = "replaces")]
+    #[serde(rename = "version")]
+    pub version: Option<String>,
+
+    #[serde(rename = "replaces", skip_serializing_if = "Option::is_none")]
     pub replaces: Option<String>,
 
-    #[serde(rename = "skips")]
-    pub skips: Option<String>,
+    #[serde(rename = "skips", skip_serializing_if = "Option::is_none")]
+    pub skips: Option<Vec<String>>,
 
-    #[serde(rename = "skipRange")]
+    #[serde(rename = "skipRange", skip_serializing_if = "Option::is_none")]
     pub skip_range: Option<String>,
 }
 
-// Bundle specifies all metadata and data of a bundle object.
-// Top-level fields are the source of truth, i.e. not CSV values.
-//
-// Notes:
-//   - Any field slice type field or type containing a slice somewhere
-//     where two types/fields are equal if their contents are equal regardless
-//     of order must have a `hash:"set"` field tag for bundle comparison.
-//   - Any fields that have a `json:"-"` tag must be included in the equality
-//     evaluation in bundlesEqual().
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Bundle {
-    #[serde(rename = "schema")]
-    pub schema: String,
+pub struct BundleSummary {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "version")]
+    pub version: Option<String>,
+
+    #[serde(rename = "image")]
+    pub image: String,
+}
+
+// CatalogDiff differences between two catalog index versions
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatalogDiff {
+    #[serde(rename = "from")]
+    pub from: String,
+
+    #[serde(rename = "to")]
+    pub to: String,
+
+    #[serde(rename = "addedPackages")]
+    pub added_packages: Vec<String>,
+
+    #[serde(rename = "removedPackages")]
+    pub removed_packages: Vec<String>,
+
+    #[serde(rename = "changedPackages")]
+    pub changed_packages: Vec<PackageDiff>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PackageDiff {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "defaultChannel", skip_serializing_if = "Option::is_none")]
+    pub default_channel: Option<ValueChange>,
+
+    #[serde(rename = "addedChannels")]
+    pub added_channels: Vec<String>,
+
+    #[serde(rename = "removedChannels")]
+    pub removed_channels: Vec<String>,
+
+    #[serde(rename = "channels")]
+    pub channels: Vec<ChannelDiff>,
+
+    #[serde(rename = "addedRelatedImages")]
+    pub added_related_images: Vec<String>,
+
+    #[serde(rename = "removedRelatedImages")]
+    pub removed_related_images: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValueChange {
+    #[serde(rename = "from")]
+    pub from: Option<String>,
+
+    #[serde(rename = "to")]
+    pub to: Option<String>,
+}
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChannelDiff {
     #[serde(rename = "name")]
     pub name: String,
 
+    #[serde(rename = "addedBundles")]
+    pub added_bundles: Vec<String>,
+
+    #[serde(rename = "removedBundles")]
+    pub removed_bundles: Vec<String>,
+}
+
+// SearchResult a ranked package match for the search action
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResult {
+    #[serde(rename = "catalog")]
+    pub catalog: String,
+
     #[serde(rename = "package")]
     pub package: String,
 
-    #[serde(rename = "image")]
-    pub image: String,
+    #[serde(rename = "score")]
+    pub score: u32,
 
-    #[serde(rename = "relatedImages")]
-    pub related_images: Vec<RelatedImage>,
-    // These fields are present so that we can continue serving
-    // the GRPC API the way packageserver expects us to in a
-    // backwards-compatible way. These are populated from
-    // any `olm.bundle.object` properties.
-    //
-    // These fields will never be persisted in the bundle blob as
-    // first class fields.
+    #[serde(rename = "matches")]
+    pub matches: Vec<String>,
+
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
 
-    //CsvJSON string   `json:"-"`
-    //Objects []string `json:"-"`
+    #[serde(rename = "provider")]
+    pub provider: Option<String>,
+
+    #[serde(rename = "providedApis")]
+    pub provided_apis: Vec<String>,
+}
+
+// ValidationFinding a problem found by the validate action
+// severity is either 'error' or 'warning'
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValidationFinding {
+    #[serde(rename = "severity")]
+    pub severity: String,
+
+    #[serde(rename = "package")]
+    pub package: String,
+
+    #[serde(rename = "object")]
+    pub object: String,
+
+    #[serde(rename = "message")]
+    pub message: String,
+}
+
+// UpgradeGraph the upgrade graph of a single channel
+// an edge goes from the bundle being upgraded to the bundle upgrading it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpgradeGraph {
+    #[serde(rename = "package")]
+    pub package: String,
+
+    #[serde(rename = "channel")]
+    pub channel: String,
+
+    #[serde(rename = "nodes")]
+    pub nodes: Vec<String>,
+
+    #[serde(rename = "edges")]
+    pub edges: Vec<UpgradeEdge>,
+
+    #[serde(rename = "head")]
+    pub head: Option<String>,
+
+    #[serde(rename = "heads")]
+    pub heads: Vec<String>,
+
+    #[serde(rename = "unreachable")]
+    pub unreachable: Vec<String>,
+
+    #[serde(rename = "missing")]
+    pub missing: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpgradeEdge {
+    #[serde(rename = "from")]
+    pub from: String,
+
+    #[serde(rename = "to")]
+    pub to: String,
+
+    #[serde(rename = "kind")]
+    pub kind: String,
+}
+
+// RegistriesConf containers-registries.conf(5), version 2 with the version 1
+// insecure and block lists
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistriesConf {
+    #[serde(rename = "registry", default)]
+    pub registries: Vec<RegistryConf>,
+
+    #[serde(rename = "registries", default)]
+    pub v1: Option<RegistriesV1>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistryConf {
+    #[serde(rename = "prefix")]
+    pub prefix: Option<String>,
+
+    #[serde(rename = "location")]
+    pub location: Option<String>,
+
+    #[serde(rename = "insecure", default)]
+    pub insecure: bool,
+
+    #[serde(rename = "blocked", default)]
+    pub blocked: bool,
+
+    #[serde(rename = "mirror-by-digest-only", default)]
+    pub mirror_by_digest_only: bool,
+
+    #[serde(rename = "mirror", default)]
+    pub mirrors: Vec<RegistryMirror>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistryMirror {
+    #[serde(rename = "location")]
+    pub location: String,
+
+    #[serde(rename = "insecure", default)]
+    pub insecure: bool,
+
+    #[serde(rename = "pull-from-mirror")]
+    pub pull_from_mirror: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistriesV1 {
+    #[serde(rename = "insecure")]
+    pub insecure: Option<RegistryList>,
+
+    #[serde(rename = "block")]
+    pub block: Option<RegistryList>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RegistryList {
+    #[serde(rename = "registries", default)]
+    pub registries: Vec<String>,
+}
+
+// PullSource a location an image can be pulled from (a mirror or the source)
+#[derive(Debug, Clone)]
+pub struct PullSource {
+    pub img_ref: ImageReference,
+    pub insecure: bool,
+    pub mirror: bool,
+}
+
+// ClientSettings tls and timeout settings shared by every http client
+#[derive(Debug, Clone, Default)]
+pub struct ClientSettings {
+    pub tls_verify: bool,
+    pub ca_files: Vec<String>,
+    pub cert_dir: String,
+    pub timeout: u64,
+    pub proxy: Option<ProxyConfig>,
+}
+
+// LogFields structured fields of a log line (only written in json log lines)
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LogFields {
+    #[serde(rename = "image", skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+
+    #[serde(rename = "digest", skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+
+    #[serde(rename = "bytes", skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+
+    #[serde(rename = "duration_ms", skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+// GcReport blobs removed from the mirror directory by the gc command
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GcReport {
+    #[serde(rename = "blobs")]
+    pub blobs: u64,
+
+    #[serde(rename = "removed")]
+    pub removed: u64,
+
+    #[serde(rename = "bytes")]
+    pub bytes: u64,
+
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+}
+
+// TransferStats summary of a set of blob downloads
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TransferStats {
+    #[serde(rename = "blobs")]
+    pub blobs: u64,
+
+    #[serde(rename = "cached")]
+    pub cached: u64,
+
+    #[serde(rename = "bytes")]
+    pub bytes: u64,
+
+    #[serde(rename = "retries")]
+    pub retries: u64,
+
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
 }
 
 // ImageReference
@@ -319,3 +1515,18 @@ pub struct ImageReference {
     pub name: String,
     pub version: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tls_verify_takes_a_value() {
+        let args = Cli::try_parse_from(["rust-container-tool", "--tls-verify=false", "push"]).unwrap();
+        assert!(!args.tls_verify);
+        let args = Cli::try_parse_from(["rust-container-tool", "push", "--tls-verify", "false"]).unwrap();
+        assert!(!args.tls_verify);
+        let args = Cli::try_parse_from(["rust-container-tool", "push"]).unwrap();
+        assert!(args.tls_verify);
+    }
+}
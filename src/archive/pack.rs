@@ -0,0 +1,784 @@
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tar::{Archive, Builder, Header};
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::image::copy::*;
+use crate::image::push::*;
+use crate::image::source::*;
+use crate::log::logging::*;
+use crate::manifests::resources::*;
+use crate::mirror::related::*;
+
+// metadata file stored in the first archive of a set
+const METADATA_FILE: &str = "mirror-metadata.json";
+
+// sequence history kept in the storage config path (and the working dir
+// on the disconnected side)
+const STORAGE_METADATA_FILE: &str = "mirror-sequences.json";
+
+// directories (relative to the working dir) that are packed
+const PACK_DIRS: [&str; 2] = ["mirror", "results"];
+
+// pack_mirror - bundle the mirrored blobs and manifests, the catalog image
+// and the generated resources into tar archives of at most 'size' MiB
+// with a 'storage' path (storageConfig.local) each run is a new sequence that
+// only holds the images, blobs and manifests not packed in earlier sequences
+pub fn pack_mirror(
+    working_dir: String,
+    archive_dir: String,
+    size: u64,
+    destination: String,
+    storage: Option<String>,
+) -> Result<ArchiveMetadata, MirrorError> {
+    let mirror_dir = working_dir.clone() + "/mirror";
+    let mut images = get_mirrored_images(&mirror_dir)?;
+
+    // the catalog index is rebuilt as an oci image so it can be published with the rest,
+    // the CatalogSource in the results expects it so a pack without it fails
+    let report_file = mirror_dir.clone() + "/related-images.json";
+    if let Ok(data) = fs::read_to_string(&report_file) {
+        let report: MirrorReport = serde_json::from_str(&data)
+            .map_err(|why| MirrorError::Parse(format!("{}: {}", report_file, why)))?;
+        let img = build_catalog_image(report.catalog.clone(), destination, working_dir.clone())
+            .context(format!("building the image of catalog {}", report.catalog))?;
+        images.push(img);
+    }
+
+    let mut history = match &storage {
+        Some(dir) => read_storage_metadata(dir)?,
+        None => {
+            log_warn("no storage config, creating a full (non incremental) set of archives");
+            StorageMetadata::default()
+        }
+    };
+    let sequence = match &storage {
+        Some(_) => history.sequences.last().map(|s| s.sequence).unwrap_or(0) + 1,
+        None => 0,
+    };
+    let packed: HashSet<(String, String)> = history
+        .sequences
+        .iter()
+        .flat_map(|s| s.files.iter())
+        .filter(|f| is_content_addressed(&f.path))
+        .map(|f| (f.path.clone(), f.digest.clone()))
+        .collect();
+    let published: HashSet<(String, String)> = history
+        .sequences
+        .iter()
+        .flat_map(|s| s.images.iter())
+        .map(|img| (img.destination.clone(), img.digest.clone()))
+        .collect();
+    images.retain(|img| !published.contains(&(img.destination.clone(), img.digest.clone())));
+    if images.is_empty() {
+        return Err(MirrorError::Other(format!(
+            "no new mirrored images found in {} since sequence {}",
+            mirror_dir,
+            sequence.saturating_sub(1)
+        )));
+    }
+
+    let mut paths = vec![];
+    for dir in PACK_DIRS {
+        collect_files(&Path::new(&working_dir).join(dir), &mut paths)?;
+    }
+    paths.sort();
+    let mut files = vec![];
+    for path in paths.iter() {
+        let (digest, size) = get_file_digest(path)?;
+        let path = path
+            .strip_prefix(&working_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string();
+        if packed.contains(&(path.clone(), digest.clone())) {
+            continue;
+        }
+        files.push(ArchiveFile { path, digest, size });
+    }
+    let metadata = ArchiveMetadata {
+        sequence,
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        images,
+        files,
+    };
+    write_archives(&metadata, &working_dir, &archive_dir, size * 1024 * 1024)?;
+    if let Some(dir) = storage {
+        history.sequences.push(metadata.clone());
+        write_storage_metadata(&dir, &history)?;
+        log_info(&format!("recorded sequence {} in {}", sequence, dir));
+    }
+    Ok(metadata)
+}
+
+// is_content_addressed - blobs and manifests are stored by digest, once packed
+// they never need to be sent again
+fn is_content_addressed(path: &str) -> bool {
+    path.starts_with("mirror/blobs/") || path.starts_with("mirror/manifests/")
+}
+
+// read_storage_metadata - sequences recorded in 'dir' (empty if there are none yet)
+pub fn read_storage_metadata(dir: &str) -> Result<StorageMetadata, MirrorError> {
+    let file = Path::new(dir).join(STORAGE_METADATA_FILE);
+    if !file.exists() {
+        return Ok(StorageMetadata::default());
+    }
+    let data = fs::read_to_string(&file).context(format!("reading {}", file.display()))?;
+    serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Parse(format!("{}: {}", file.display(), why)))
+}
+
+// write_storage_metadata - record the sequences in 'dir'
+fn write_storage_metadata(dir: &str, metadata: &StorageMetadata) -> Result<(), MirrorError> {
+    let file = Path::new(dir).join(STORAGE_METADATA_FILE);
+    fs::create_dir_all(dir).context(format!("creating {}", dir))?;
+    fs::write(&file, serde_json::to_string_pretty(metadata)?)
+        .context(format!("writing {}", file.display()))
+}
+
+// get_mirrored_images - every successfully mirrored image from the reports in 'dir'
+fn get_mirrored_images(dir: &str) -> Result<Vec<MirroredImage>, MirrorError> {
+    let mut images = vec![];
+    let mut seen = HashSet::new();
+    let mut reports: Vec<PathBuf> = fs::read_dir(dir)
+        .context(format!("reading {}", dir))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"))
+        .collect();
+    reports.sort();
+    for file in reports {
+        let data = fs::read_to_string(&file).context(format!("reading {}", file.display()))?;
+        let report: MirrorReport = match serde_json::from_str(&data) {
+            Ok(report) => report,
+            Err(_) => continue,
+        };
+        for img in report.images.into_iter().filter(|img| img.mirrored) {
+            if seen.insert(img.destination.clone()) {
+                images.push(img);
+            }
+        }
+    }
+    Ok(images)
+}
+
+// build_catalog_image - rebuild the downloaded catalog index (v1 manifest and
+// layer blobs) as an oci image in the mirror directory, the container config
+// is taken from the v1 history so the image still serves the catalog
+// local catalogs (oci:, docker-archive:, dir: or a 'configs' directory) have
+// no downloaded index to rebuild and are refused
+pub fn build_catalog_image(
+    catalog: String,
+    destination: String,
+    working_dir: String,
+) -> Result<MirroredImage, MirrorError> {
+    if is_local_source(&catalog) || Path::new(&catalog).is_dir() {
+        return Err(MirrorError::Catalog(format!(
+            "{} is a local catalog, only a catalog index pulled from a registry can be packed \
+             (mirror from the index image or push the catalog image to the destination yourself)",
+            catalog
+        )));
+    }
+    let img_ref = parse_image(catalog.clone());
+    let catalog_dir = format!("{}/{}/{}", working_dir, img_ref.name, img_ref.version);
+    let manifests_dir = working_dir.clone() + "/mirror/manifests/";
+    let blobs_dir = working_dir + "/mirror/blobs/sha256/";
+    fs::create_dir_all(&manifests_dir).context(format!("creating {}", manifests_dir))?;
+    fs::create_dir_all(&blobs_dir).context(format!("creating {}", blobs_dir))?;
+
+    let data = fs::read_to_string(catalog_dir.clone() + "/manifest.json")
+        .context(format!("reading {}/manifest.json", catalog_dir))?;
+    let v1 = parse_json_manifest(data)?;
+
+    // v1 manifests list the layers top most first
+    let mut layers = vec![];
+    let mut diff_ids = vec![];
+    for fs_layer in v1.fs_layers.iter().rev() {
+        if layers.iter().any(|l: &Layer| l.digest == fs_layer.blob_sum) {
+            continue;
+        }
+        let hex = fs_layer.blob_sum.split(':').nth(1).unwrap_or_default();
+        let blob = catalog_dir.clone() + "/blobs/sha256/" + hex;
+        let target = blobs_dir.clone() + hex;
+        if !Path::new(&target).exists() {
+            fs::copy(&blob, &target).context(format!("copying {}", blob))?;
+        }
+        let mut hasher = Sha256::new();
+        let file = File::open(&blob).context(format!("reading {}", blob))?;
+        io::copy(&mut GzDecoder::new(file), &mut hasher)
+            .map_err(|why| MirrorError::Extraction(format!("layer {}: {}", hex, why)))?;
+        diff_ids.push(format!("sha256:{}", hex::encode(hasher.finalize())));
+        layers.push(Layer {
+            media_type: Some("application/vnd.oci.image.layer.v1.tar+gzip".to_string()),
+            digest: fs_layer.blob_sum.clone(),
+            size: Some(
+                fs::metadata(&target)
+                    .context(format!("reading {}", target))?
+                    .len() as i64,
+            ),
+            ..Default::default()
+        });
+    }
+
+    let history: serde_json::Value = match v1.history.first() {
+        Some(h) => serde_json::from_str(&h.v1compatibility)
+            .map_err(|why| MirrorError::Parse(format!("v1Compatibility: {}", why)))?,
+        None => serde_json::Value::Null,
+    };
+    let config = serde_json::json!({
+        "architecture": history["architecture"].as_str().unwrap_or("amd64"),
+        "os": history["os"].as_str().unwrap_or("linux"),
+        "config": history["config"],
+        "rootfs": { "type": "layers", "diff_ids": diff_ids },
+    })
+    .to_string();
+    let config_digest = get_digest(config.as_bytes());
+    fs::write(blobs_dir + &config_digest[7..], &config).context("writing the catalog config")?;
+
+    let manifest = ManifestV2 {
+        schema_version: 2,
+        media_type: Some("application/vnd.oci.image.manifest.v1+json".to_string()),
+        config: Some(Layer {
+            media_type: Some("application/vnd.oci.image.config.v1+json".to_string()),
+            digest: config_digest,
+            size: Some(config.len() as i64),
+            ..Default::default()
+        }),
+        layers: Some(layers),
+        manifests: None,
+    };
+    let manifest = serde_json::to_string(&manifest)?;
+    let digest = get_digest(manifest.as_bytes());
+    fs::write(manifests_dir + &digest[7..] + ".json", &manifest)
+        .context("writing the catalog manifest")?;
+    log_info(&format!("catalog image built {}", digest));
+
+    Ok(MirroredImage {
+        name: img_ref.name.clone(),
+        destination: get_catalog_destination(&catalog, destination),
+        source: catalog,
+        digest,
+        mirrored: true,
+    })
+}
+
+// collect_files - all files below 'dir' (a missing directory is skipped)
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), MirrorError> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).context(format!("reading {}", dir.display()))? {
+        let path = entry.context(format!("reading {}", dir.display()))?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// get_file_digest - sha256 digest and size of a file
+fn get_file_digest(path: &Path) -> Result<(String, u64), MirrorError> {
+    let mut hasher = Sha256::new();
+    let mut file = File::open(path).context(format!("reading {}", path.display()))?;
+    let size = io::copy(&mut file, &mut hasher).context(format!("reading {}", path.display()))?;
+    Ok((format!("sha256:{}", hex::encode(hasher.finalize())), size))
+}
+
+// write_archives - write the metadata and files into 'mirror_seqS_NNNNNN.tar'
+// archives (or 'mirror_NNNNNN.tar' for a full set), a new archive is started when the next file would exceed 'limit'
+// a single file larger than the limit is an error (checked before anything is written)
+fn write_archives(
+    metadata: &ArchiveMetadata,
+    working_dir: &str,
+    archive_dir: &str,
+    limit: u64,
+) -> Result<(), MirrorError> {
+    // each entry has a 512 byte header and is padded to 512 bytes
+    let entry_size = |file: &ArchiveFile| 512 + file.size.div_ceil(512) * 512;
+    // files are not split across archives
+    if let Some(file) = metadata.files.iter().find(|f| entry_size(f) > limit) {
+        return Err(MirrorError::Config(format!(
+            "{} ({} bytes) does not fit in an archive of {} bytes, raise --archive-size",
+            file.path, file.size, limit
+        )));
+    }
+
+    fs::create_dir_all(archive_dir).context(format!("creating {}", archive_dir))?;
+    let prefix = match metadata.sequence {
+        0 => format!("{}/mirror", archive_dir),
+        seq => format!("{}/mirror_seq{}", archive_dir, seq),
+    };
+    let new_archive = |n: usize| -> Result<(String, Builder<File>), MirrorError> {
+        let name = format!("{}_{:06}.tar", prefix, n);
+        log_info(&format!("writing archive {}", name));
+        let file = File::create(&name).context(format!("creating {}", name))?;
+        Ok((name, Builder::new(file)))
+    };
+
+    let mut count = 0;
+    let (mut name, mut builder) = new_archive(count)?;
+    let data = serde_json::to_string_pretty(metadata)?;
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(metadata.created);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, METADATA_FILE, data.as_bytes())
+        .context(format!("writing {}", name))?;
+    let mut current = data.len() as u64 + 512;
+
+    for file in metadata.files.iter() {
+        let entry_size = entry_size(file);
+        if current > 0 && current + entry_size > limit {
+            builder.finish().context(format!("writing {}", name))?;
+            count += 1;
+            (name, builder) = new_archive(count)?;
+            current = 0;
+        }
+        builder
+            .append_path_with_name(Path::new(working_dir).join(&file.path), &file.path)
+            .context(format!("adding {} to {}", file.path, name))?;
+        current += entry_size;
+    }
+    builder.finish().context(format!("writing {}", name))?;
+    log_info(&format!(
+        "packed {} files in {} archives",
+        metadata.files.len(),
+        count + 1
+    ));
+    Ok(())
+}
+
+// unpack_archives - extract the archive sets in 'archive_dir' into the working
+// dir in sequence order and verify the checksum of each file listed in the metadata
+// a sequence that does not follow the last applied sequence is refused, sequences
+// already applied are skipped, returns the metadata of each set applied
+pub fn unpack_archives(
+    archive_dir: String,
+    working_dir: String,
+) -> Result<Vec<ArchiveMetadata>, MirrorError> {
+    let mut archives: Vec<PathBuf> = fs::read_dir(&archive_dir)
+        .context(format!("reading {}", archive_dir))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("tar"))
+        .collect();
+    archives.sort();
+    if archives.is_empty() {
+        return Err(MirrorError::Extraction(format!(
+            "no archives found in {}",
+            archive_dir
+        )));
+    }
+
+    // group the archives of each set (name without the _NNNNNN suffix), the
+    // metadata is read from the first archive of the set
+    let mut sets: Vec<(ArchiveMetadata, Vec<PathBuf>)> = vec![];
+    let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for archive in archives {
+        let stem = archive
+            .file_stem()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let group = stem
+            .rsplit_once('_')
+            .map(|(g, _)| g.to_string())
+            .unwrap_or(stem);
+        groups.entry(group).or_default().push(archive);
+    }
+    for (_, archives) in groups {
+        let metadata = read_archive_metadata(&archives[0])?;
+        sets.push((metadata, archives));
+    }
+    sets.sort_by_key(|(metadata, _)| metadata.sequence);
+
+    fs::create_dir_all(&working_dir).context(format!("creating {}", working_dir))?;
+    let mut applied = read_storage_metadata(&working_dir)?;
+    let mut result = vec![];
+    for (metadata, archives) in sets {
+        let last = applied.sequences.last().map(|s| s.sequence).unwrap_or(0);
+        if metadata.sequence > 0 && metadata.sequence <= last {
+            log_warn(&format!(
+                "sequence {} already applied skipping",
+                metadata.sequence
+            ));
+            continue;
+        }
+        if metadata.sequence > 0 && metadata.sequence != last + 1 {
+            return Err(MirrorError::Extraction(format!(
+                "sequence {} is out of order, the next sequence to apply is {}",
+                metadata.sequence,
+                last + 1
+            )));
+        }
+        for archive in archives.iter() {
+            log_info(&format!("unpacking archive {}", archive.display()));
+            let file = File::open(archive).context(format!("reading {}", archive.display()))?;
+            Archive::new(file).unpack(&working_dir).map_err(|why| {
+                MirrorError::Extraction(format!("{}: {}", archive.display(), why))
+            })?;
+        }
+        verify_files(&metadata, &working_dir)?;
+        if metadata.sequence > 0 {
+            applied.sequences.push(metadata.clone());
+            write_storage_metadata(&working_dir, &applied)?;
+        }
+        result.push(metadata);
+    }
+    Ok(result)
+}
+
+// read_archive_metadata - read the metadata file from an archive without extracting it
+fn read_archive_metadata(archive: &Path) -> Result<ArchiveMetadata, MirrorError> {
+    let archive_error =
+        |why: io::Error| MirrorError::Extraction(format!("{}: {}", archive.display(), why));
+    let mut tar = Archive::new(File::open(archive).map_err(archive_error)?);
+    for entry in tar.entries().map_err(archive_error)? {
+        let entry = entry.map_err(archive_error)?;
+        if entry.path().map_err(archive_error)?.to_string_lossy() == METADATA_FILE {
+            return serde_json::from_reader(entry).map_err(|why| {
+                MirrorError::Extraction(format!("{} in {}: {}", METADATA_FILE, archive.display(), why))
+            });
+        }
+    }
+    Err(MirrorError::Extraction(format!(
+        "{} not found in {}",
+        METADATA_FILE,
+        archive.display()
+    )))
+}
+
+// verify_files - check the size and digest of every file in the metadata
+fn verify_files(metadata: &ArchiveMetadata, working_dir: &str) -> Result<(), MirrorError> {
+    let mut failed = 0;
+    let mut first = None;
+    for file in metadata.files.iter() {
+        let path = Path::new(working_dir).join(&file.path);
+        match get_file_digest(&path) {
+            Ok((digest, size)) if digest == file.digest && size == file.size => {}
+            Ok((digest, _)) => {
+                log_error(&format!("checksum mismatch {}", file.path));
+                failed += 1;
+                first.get_or_insert(MirrorError::DigestMismatch {
+                    name: file.path.clone(),
+                    expected: file.digest.clone(),
+                    actual: digest,
+                });
+            }
+            Err(error) => {
+                log_error(&format!("{} : {}", file.path, error));
+                failed += 1;
+                first.get_or_insert(MirrorError::Extraction(format!("{} : {}", file.path, error)));
+            }
+        }
+    }
+    if let Some(error) = first {
+        return Err(error).context(format!("{} files failed verification", failed));
+    }
+    log_info(&format!(
+        "verified {} files (sequence {})",
+        metadata.files.len(),
+        metadata.sequence
+    ));
+    Ok(())
+}
+
+// publish_images - push the images listed in the metadata of each set from
+// the unpacked mirror directory to their destination, or below 'destination'
+// when it is set (see set_destination)
+pub async fn publish_images(
+    sets: &[ArchiveMetadata],
+    destination: Option<String>,
+    working_dir: String,
+) -> MirrorReport {
+    let mut images: Vec<MirroredImage> = sets.iter().flat_map(|s| s.images.clone()).collect();
+    if let Some(destination) = destination {
+        set_destination(&mut images, &destination);
+        log_warn(&format!(
+            "the packed cluster resources still refer to the mirror destination, not {}",
+            destination
+        ));
+    }
+    let images: Vec<&MirroredImage> = images.iter().collect();
+    push_images("publish", &images, working_dir).await
+}
+
+// set_destination - replace the registry (and namespace prefix) the images
+// were mirrored to with 'destination', the prefix is found from an image
+// whose destination ends with its source repository (see get_destination)
+// and falls back to the registry of the first image
+pub fn set_destination(images: &mut [MirroredImage], destination: &str) {
+    let prefix = images
+        .iter()
+        .filter(|img| !is_local_source(&img.source))
+        .find_map(|img| {
+            let suffix = get_destination(String::new(), parse_image(img.source.clone()));
+            img.destination.strip_suffix(&suffix).map(|p| p.to_string())
+        })
+        .or(images.first().map(|img| parse_image(img.destination.clone()).registry));
+    let Some(prefix) = prefix else {
+        return;
+    };
+    let destination = destination.trim_end_matches('/');
+    for img in images.iter_mut() {
+        if let Some(rest) = img.destination.strip_prefix(&(prefix.clone() + "/")) {
+            img.destination = format!("{}/{}", destination, rest);
+        }
+    }
+}
+
+// push_images - push 'images' from the mirror directory of 'working_dir'
+// to their destination, 'name' is used as the catalog of the report
+pub async fn push_images(
+    name: &str,
+    images: &[&MirroredImage],
+    working_dir: String,
+) -> MirrorReport {
+    let mut report = MirrorReport {
+        catalog: name.to_string(),
+        total: images.len(),
+        unique: images.len(),
+        mirrored: 0,
+        failed: 0,
+        images: vec![],
+    };
+    for img in images.iter() {
+        log_info(&format!("publishing {}", img.destination));
+        let start = Instant::now();
+        let mut published = (*img).clone();
+        match push_image(
+            working_dir.clone() + "/mirror",
+            img.digest.clone(),
+            img.destination.clone(),
+        )
+        .await
+        {
+            Ok(()) => {
+                log_event(
+                    LogLevel::Info,
+                    &format!("published {}", img.destination),
+                    LogFields {
+                        image: Some(img.destination.clone()),
+                        digest: Some(img.digest.clone()),
+                        duration_ms: Some(start.elapsed().as_millis() as u64),
+                        ..Default::default()
+                    },
+                );
+                report.mirrored += 1;
+            }
+            Err(error) => {
+                log_error(&format!("publishing {} : {}", img.destination, error));
+                published.mirrored = false;
+                report.failed += 1;
+            }
+        }
+        report.images.push(published);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // add_image - a mirrored image 'name' with a single blob holding 'content'
+    fn add_image(working_dir: &Path, name: &str, content: &str) {
+        let digest = get_digest(content.as_bytes());
+        let blobs = working_dir.join("mirror/blobs/sha256");
+        fs::create_dir_all(&blobs).unwrap();
+        fs::write(blobs.join(&digest[7..]), content).unwrap();
+        let report = MirrorReport {
+            catalog: "test".to_string(),
+            total: 1,
+            unique: 1,
+            mirrored: 1,
+            failed: 0,
+            images: vec![MirroredImage {
+                name: name.to_string(),
+                source: format!("quay.io/test/{}:v1", name),
+                destination: format!("localhost:5000/test/{}:v1", name),
+                digest,
+                mirrored: true,
+            }],
+        };
+        fs::write(
+            working_dir.join(format!("mirror/{}.json", name)),
+            serde_json::to_string(&report).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn paths(metadata: &ArchiveMetadata) -> Vec<String> {
+        metadata.files.iter().map(|f| f.path.clone()).collect()
+    }
+
+    #[test]
+    fn pack_and_unpack_sequences() {
+        let base = std::env::temp_dir().join(format!("pack-sequences-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let dir = |name: &str| base.join(name).to_string_lossy().to_string();
+        let working_dir = base.join("work");
+        let pack = |archives: &str| {
+            pack_mirror(
+                dir("work"),
+                dir(archives),
+                1,
+                "localhost:5000".to_string(),
+                Some(dir("storage")),
+            )
+        };
+
+        add_image(&working_dir, "a", "one");
+        let first = pack("first").unwrap();
+        assert_eq!(first.sequence, 1);
+        assert_eq!(first.images.len(), 1);
+        let blob_a = format!("mirror/blobs/sha256/{}", &first.images[0].digest[7..]);
+        assert!(paths(&first).contains(&blob_a));
+
+        // nothing new since the first sequence
+        assert!(matches!(pack("empty"), Err(MirrorError::Other(_))));
+
+        // only the new image and its blob are packed in the next sequence
+        add_image(&working_dir, "b", "two");
+        let second = pack("second").unwrap();
+        assert_eq!(second.sequence, 2);
+        assert_eq!(second.images.len(), 1);
+        assert_eq!(second.images[0].name, "b");
+        let blob_b = format!("mirror/blobs/sha256/{}", &second.images[0].digest[7..]);
+        assert!(paths(&second).contains(&blob_b));
+        assert!(!paths(&second).contains(&blob_a));
+        let history = read_storage_metadata(&dir("storage")).unwrap();
+        assert_eq!(history.sequences.len(), 2);
+
+        // sequences are applied in order on the disconnected side
+        let target = dir("disconnected");
+        let res = unpack_archives(dir("second"), target.clone());
+        assert!(matches!(res, Err(MirrorError::Extraction(_))));
+        let applied = unpack_archives(dir("first"), target.clone()).unwrap();
+        assert_eq!(applied.len(), 1);
+        assert!(Path::new(&target).join(&blob_a).exists());
+        assert!(unpack_archives(dir("first"), target.clone())
+            .unwrap()
+            .is_empty());
+        let applied = unpack_archives(dir("second"), target.clone()).unwrap();
+        assert_eq!(applied[0].sequence, 2);
+        assert!(Path::new(&target).join(&blob_b).exists());
+        assert_eq!(read_storage_metadata(&target).unwrap().sequences.len(), 2);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn local_catalog_is_not_packed() {
+        let base = std::env::temp_dir().join(format!("pack-local-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let dir = |name: &str| base.join(name).to_string_lossy().to_string();
+        add_image(&base.join("work"), "a", "one");
+        let report = MirrorReport {
+            catalog: "oci:/tmp/catalogs/my-index:v1".to_string(),
+            total: 0,
+            unique: 0,
+            mirrored: 0,
+            failed: 0,
+            images: vec![],
+        };
+        fs::write(
+            base.join("work/mirror/related-images.json"),
+            serde_json::to_string(&report).unwrap(),
+        )
+        .unwrap();
+        let error = pack_mirror(
+            dir("work"),
+            dir("archives"),
+            1,
+            "localhost:5000".to_string(),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(error.exit_code(), 8, "{}", error);
+        assert!(!base.join("archives").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn full_set_without_storage() {
+        let base = std::env::temp_dir().join(format!("pack-full-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let dir = |name: &str| base.join(name).to_string_lossy().to_string();
+        add_image(&base.join("work"), "a", "one");
+        for _ in 0..2 {
+            let metadata = pack_mirror(
+                dir("work"),
+                dir("archives"),
+                1,
+                "localhost:5000".to_string(),
+                None,
+            )
+            .unwrap();
+            assert_eq!(metadata.sequence, 0);
+            assert_eq!(metadata.images.len(), 1);
+        }
+        assert!(base.join("archives/mirror_000000.tar").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn oversized_file() {
+        let base = std::env::temp_dir().join(format!("pack-oversized-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let metadata = ArchiveMetadata {
+            sequence: 0,
+            created: 0,
+            images: vec![],
+            files: vec![ArchiveFile {
+                path: "mirror/blobs/sha256/large".to_string(),
+                digest: "sha256:large".to_string(),
+                size: 4096,
+            }],
+        };
+        let archives = base.to_string_lossy().to_string();
+        let res = write_archives(&metadata, "work", &archives, 2048);
+        assert!(matches!(res, Err(MirrorError::Config(_))));
+        // nothing is written when a file does not fit
+        assert!(!base.exists());
+    }
+
+    #[test]
+    fn publish_destination() {
+        let image = |source: &str, destination: &str| MirroredImage {
+            name: "app".to_string(),
+            source: source.to_string(),
+            destination: destination.to_string(),
+            digest: "sha256:abc".to_string(),
+            mirrored: true,
+        };
+        let mut images = vec![
+            image("oci:/tmp/catalog", "mirror.local:5000/ocp/catalog:latest"),
+            image("quay.io/org/app:v1", "mirror.local:5000/ocp/org/app:v1"),
+            image("graph-data", "mirror.local:5000/ocp/openshift/graph-image:latest"),
+        ];
+        set_destination(&mut images, "registry.internal/");
+        let destinations: Vec<&str> = images.iter().map(|i| i.destination.as_str()).collect();
+        assert_eq!(
+            destinations,
+            vec![
+                "registry.internal/catalog:latest",
+                "registry.internal/org/app:v1",
+                "registry.internal/openshift/graph-image:latest"
+            ]
+        );
+    }
+}
@@ -1,99 +1,453 @@
+use crate::api::error::*;
 use crate::api::schema::*;
-use crate::Path;
+use crate::log::logging::*;
+use crate::registry::client::*;
+use std::collections::HashMap;
+use std::path::Path;
 use base64::{engine::general_purpose, Engine as _};
 use std::fs::File;
 use std::io::Read;
 use std::str;
 use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-// read the credentials from set path (see podman credential reference) 
-pub fn get_credentials() -> Result<String, Box<dyn std::error::Error>> {
+// auth file set with --authfile
+static AUTH_FILE: OnceLock<String> = OnceLock::new();
+
+// tokens already issued keyed by registry and scope, with their expiry
+type TokenCache = HashMap<(String, String), (String, Instant)>;
+static TOKENS: OnceLock<Mutex<TokenCache>> = OnceLock::new();
+
+// challenges keyed by registry (None for registries allowing anonymous access)
+static CHALLENGES: OnceLock<Mutex<HashMap<String, Option<AuthChallenge>>>> = OnceLock::new();
+
+// lifetime of a token when the realm does not tell (distribution spec default)
+const TOKEN_EXPIRY: u64 = 60;
+
+// scope of a token for the _catalog api
+pub const CATALOG_SCOPE: &str = "registry:catalog:*";
+
+// token actions for pulls and pushes
+pub const PULL: &str = "pull";
+pub const PUSH: &str = "pull,push";
+
+fn tokens() -> &'static Mutex<TokenCache> {
+    TOKENS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn challenges() -> &'static Mutex<HashMap<String, Option<AuthChallenge>>> {
+    CHALLENGES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// set_auth_file - read the credentials from 'file' instead of the default
+// location (set once at startup)
+pub fn set_auth_file(file: String) {
+    let _ = AUTH_FILE.set(file);
+}
+
+// read the credentials from set path (see podman credential reference)
+// None when no auth file was set and the default one does not exist
+pub fn get_credentials() -> Result<Option<String>, MirrorError> {
     // Create a path to the desired file
-    // using $XDG_RUNTIME_DIR envar
-    let u = match env::var_os("XDG_RUNTIME_DIR") {
-        Some(v) => v.into_string().unwrap(),
-        None => panic!("$XDG_RUNTIME_DIR is not set")
+    // using $XDG_RUNTIME_DIR envar unless an auth file was set
+    let binding = &match AUTH_FILE.get() {
+        Some(file) => file.clone(),
+        None => match env::var("XDG_RUNTIME_DIR") {
+            Ok(dir) if Path::new(&dir).join("containers/auth.json").exists() => {
+                dir + "/containers/auth.json"
+            }
+            _ => {
+                log_debug("no auth file found, using anonymous access");
+                return Ok(None);
+            }
+        },
     };
-    let binding = &(u.to_owned() + "/containers/auth.json");
     let path = Path::new(binding);
     let display = path.display();
 
     // Open the path in read-only mode, returns `io::Result<File>`
-    let mut file = match File::open(&binding) {
-        Err(why) => panic!("couldn't open {}: {}", display, why),
-        Ok(file) => file,
-    };
+    let mut file = File::open(binding)
+        .map_err(|why| MirrorError::Auth(format!("couldn't open {}: {}", display, why)))?;
 
     // Read the file contents into a string, returns `io::Result<usize>`
     let mut s = String::new();
-    file.read_to_string(&mut s)?;
-    Ok(s)
+    file.read_to_string(&mut s).context(format!("reading {}", display))?;
+    Ok(Some(s))
 }
 
-// parse the json credentials to a struct
-pub fn parse_json_creds(data: String) -> Result<String, Box<dyn std::error::Error>> {
+// parse the json credentials and return the user and password of 'registry'
+// keys can carry a scheme (https://quay.io) and docker hub is also known as
+// index.docker.io
+pub fn parse_json_creds(
+    data: String,
+    registry: &str,
+) -> Result<Option<(String, String)>, MirrorError> {
     // Parse the string of data into serde_json::Root.
-    let creds: Root = serde_json::from_str(&data)?;
-    Ok(creds.auths.registry_redhat_io.auth)
+    let creds: Root = serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Auth(format!("parsing auth.json: {}", why)))?;
+    let normalize = |key: &str| {
+        let key = key
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches("/v1/")
+            .trim_end_matches('/');
+        match key {
+            "index.docker.io" | "registry-1.docker.io" => "docker.io".to_string(),
+            _ => key.to_string(),
+        }
+    };
+    let registry = normalize(registry);
+    let entry = creds.auths.iter().find(|(key, _)| normalize(key) == registry);
+    let Some((key, entry)) = entry else {
+        return Ok(None);
+    };
+    // decode to base64
+    let bytes = general_purpose::STANDARD
+        .decode(&entry.auth)
+        .map_err(|e| MirrorError::Auth(format!("invalid base64 credentials for {}: {}", key, e)))?;
+    let s = str::from_utf8(&bytes)
+        .map_err(|e| MirrorError::Auth(format!("invalid UTF-8 sequence: {}", e)))?;
+    // get user and password form json
+    let (user, pwd) = s.split_once(':').ok_or(MirrorError::Auth(format!(
+        "credentials for {} are not in user:password form",
+        key
+    )))?;
+    Ok(Some((user.to_string(), pwd.to_string())))
 }
 
-// parse the json from the api call 
-pub fn parse_json_token(data: String) -> Result<String, Box<dyn std::error::Error>> {
+// parse the json from the api call, the token and its lifetime in seconds
+pub fn parse_json_token(data: String) -> Result<(String, u64), MirrorError> {
     // Parse the string of data into serde_json::Token.
-    let root: Token = serde_json::from_str(&data)?;
-    Ok(root.access_token)
+    let root: Token = serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Auth(format!("parsing token response: {}", why)))?;
+    let token = match root.token.is_empty() {
+        true => root.access_token,
+        false => root.token,
+    };
+    if token.is_empty() {
+        return Err(MirrorError::Auth("token response without a token".to_string()));
+    }
+    let expires_in = match root.expires_in {
+        0 => TOKEN_EXPIRY,
+        seconds => seconds,
+    };
+    Ok((token, expires_in))
+}
+
+// parse_auth_challenge - scheme and parameters of a WWW-Authenticate header
+// e.g. Bearer realm="https://quay.io/v2/auth",service="quay.io"
+pub fn parse_auth_challenge(header: &str) -> Option<AuthChallenge> {
+    let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    let mut challenge = AuthChallenge {
+        scheme: scheme.to_lowercase(),
+        ..Default::default()
+    };
+    // values are quoted and can hold commas (scope="repository:a:pull,push")
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim();
+        let value = value.trim_start();
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, next)) => (value, next),
+                None => (quoted, ""),
+            },
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        match key {
+            "realm" => challenge.realm = value.to_string(),
+            "service" => challenge.service = Some(value.to_string()),
+            "scope" => challenge.scope = Some(value.to_string()),
+            _ => {}
+        }
+        rest = next.trim_start_matches(',').trim();
+    }
+    match challenge.scheme.is_empty() {
+        true => None,
+        false => Some(challenge),
+    }
 }
 
 // parse the manifest json
-pub fn parse_json_manifest(data: String) -> Result<ManifestSchema, Box<dyn std::error::Error>> {
+pub fn parse_json_manifest(data: String) -> Result<ManifestSchema, MirrorError> {
     // Parse the string of data into serde_json::ManifestSchema.
-    let root: ManifestSchema = serde_json::from_str(&data)?;
+    let root: ManifestSchema = serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Parse(format!("manifest: {}", why)))?;
     Ok(root)
 }
 
-// async api call with basic auth
+// parse the v2 (docker or oci) manifest json
+pub fn parse_json_manifest_v2(data: String) -> Result<ManifestV2, MirrorError> {
+    // Parse the string of data into serde_json::ManifestV2.
+    let root: ManifestV2 = serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Parse(format!("manifest: {}", why)))?;
+    Ok(root)
+}
+
+// async api call to the token realm with basic auth (anonymous without credentials)
 pub async fn get_auth_json(
     url: String,
-    user: String,
-    password: String,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let pwd: Option<String> = Some(password);
-    let body = client
-        .get(url)
-        .basic_auth(user, pwd)
-        .send()
-        .await?
-        .text()
-        .await?;
+    query: Vec<(&str, String)>,
+    credentials: Option<(String, String)>,
+) -> Result<String, MirrorError> {
+    let client = get_registry_client(&url)?;
+    let mut req = client.get(url).query(&query);
+    if let Some((user, password)) = credentials {
+        req = req.basic_auth(user, Some(password));
+    }
+    let resp = req.send().await?;
+    let body = match check_response(resp).await {
+        Ok(resp) => resp.text().await?,
+        Err(error @ MirrorError::Registry { status: 401, .. })
+        | Err(error @ MirrorError::Registry { status: 403, .. }) => {
+            return Err(MirrorError::Auth(format!("token request rejected : {}", error)))
+        }
+        Err(error) => return Err(error),
+    };
     Ok(body)
 }
 
-// process all relative functions in this module to actaully get the token
-pub async fn get_token(name: String) -> String {
-    let token_url = match name.as_str() {
-        "registry.redhat.io" => "https://sso.redhat.com/auth/realms/rhcc/protocol/redhat-docker-v2/auth?service=docker-registry&client_id=curl&scope=repository:rhel:pull".to_string(),
-        &_ => "none".to_string(),
+// get_repository_scope - token scope for 'actions' (PULL or PUSH) on the
+// repository of 'img_ref' e.g. repository:openshift-release-dev/ocp-release:pull
+pub fn get_repository_scope(img_ref: &ImageReference, actions: &str) -> String {
+    let repository = match img_ref.namespace.is_empty() {
+        true => img_ref.name.clone(),
+        false => format!("{}/{}", img_ref.namespace, img_ref.name),
     };
-    // get creds from $XDG_RUNTIME_DIR
-    let creds = get_credentials().unwrap();
-    // parse the json data
-    let rhauth = parse_json_creds(creds).unwrap();
-    // decode to base64
-    let bytes = general_purpose::STANDARD.decode(rhauth).unwrap();
+    format!("repository:{}:{}", repository, actions)
+}
 
-    let s = match str::from_utf8(&bytes) {
-        Ok(v) => v,
-        Err(e) => panic!("ERROR: invalid UTF-8 sequence: {}", e),
+// get_auth_challenge - the challenge of 'registry' from an unauthenticated
+// request to /v2/ (checked once per registry), None if no token is needed
+async fn get_auth_challenge(registry: &str) -> Result<Option<AuthChallenge>, MirrorError> {
+    if let Some(challenge) = lock(challenges()).get(registry) {
+        return Ok(challenge.clone());
+    }
+    let url = format!("{}://{}/v2/", get_registry_scheme(registry), registry);
+    let resp = get_registry_client(&url)?.get(&url).send().await?;
+    let challenge = match resp.status().as_u16() {
+        401 => {
+            let header = resp
+                .headers()
+                .get("WWW-Authenticate")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let challenge = parse_auth_challenge(&header).ok_or(MirrorError::Auth(format!(
+                "{} answered 401 without a WWW-Authenticate challenge",
+                url
+            )))?;
+            Some(challenge)
+        }
+        _ => {
+            check_response(resp).await?;
+            None
+        }
     };
-    // get user and password form json
-    let user = s.split(":").nth(0).unwrap();
-    let pwd = s.split(":").nth(1).unwrap();
+    lock(challenges()).insert(registry.to_string(), challenge.clone());
+    Ok(challenge)
+}
+
+// get_token - bearer token for 'scope' on 'registry' (empty for anonymous access)
+// an unauthenticated request to the registry tells the token realm
+// (WWW-Authenticate: Bearer realm=..,service=..), the token is requested from
+// the realm with the auth.json credentials of the registry (if any), tokens
+// are reused until they expire
+pub async fn get_token(registry: String, scope: String) -> Result<String, MirrorError> {
+    let key = (registry.clone(), scope.clone());
+    if let Some((token, expiry)) = lock(tokens()).get(&key) {
+        if Instant::now() < *expiry {
+            return Ok(token.clone());
+        }
+    }
+    let challenge = match get_auth_challenge(&registry).await? {
+        Some(challenge) => challenge,
+        None => return Ok(String::new()),
+    };
+    if challenge.scheme != "bearer" {
+        log_warn(&format!(
+            "{} asks for {} authentication, only bearer tokens are supported trying anonymous access",
+            registry, challenge.scheme
+        ));
+        return Ok(String::new());
+    }
+    // get creds from $XDG_RUNTIME_DIR (or --authfile)
+    let credentials = match get_credentials()? {
+        Some(data) => parse_json_creds(data, &registry)?,
+        None => None,
+    };
+    if credentials.is_none() {
+        log_debug(&format!("no credentials for {}, requesting an anonymous token", registry));
+    }
+    let mut query = vec![("scope", scope)];
+    if let Some(service) = challenge.service {
+        query.push(("service", service));
+    }
     // call the realm url to get a token with the creds
-    let res = get_auth_json(token_url, user.to_string(), pwd.to_string())
-        .await
-        .unwrap();
+    let res = get_auth_json(challenge.realm, query, credentials).await?;
     // if all goes well we should have a valid token
-    let token = parse_json_token(res).unwrap();
-    token
+    let (token, expires_in) = parse_json_token(res)?;
+    // renew a little before the realm expires it
+    let expiry = Instant::now() + Duration::from_secs(expires_in.saturating_sub(10).max(1));
+    lock(tokens()).insert(key, (token.clone(), expiry));
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::fs;
+    use std::sync::mpsc;
+
+    #[test]
+    fn auth_challenges() {
+        let tests = vec![
+            (
+                r#"Bearer realm="https://quay.io/v2/auth",service="quay.io""#,
+                Some(("bearer", "https://quay.io/v2/auth", Some("quay.io"), None)),
+            ),
+            (
+                r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/busybox:pull,push""#,
+                Some((
+                    "bearer",
+                    "https://auth.docker.io/token",
+                    Some("registry.docker.io"),
+                    Some("repository:library/busybox:pull,push"),
+                )),
+            ),
+            (
+                r#"Basic realm="Registry Realm""#,
+                Some(("basic", "Registry Realm", None, None)),
+            ),
+            ("", None),
+        ];
+        for (header, expected) in tests {
+            let challenge = parse_auth_challenge(header);
+            let challenge = challenge.as_ref().map(|c| {
+                (
+                    c.scheme.as_str(),
+                    c.realm.as_str(),
+                    c.service.as_deref(),
+                    c.scope.as_deref(),
+                )
+            });
+            assert_eq!(challenge, expected, "{}", header);
+        }
+    }
+
+    #[test]
+    fn registry_credentials() {
+        let encode = |s: &str| general_purpose::STANDARD.encode(s);
+        let data = serde_json::json!({
+            "auths": {
+                "quay.io": { "auth": encode("quay-user:quay-pwd") },
+                "https://index.docker.io/v1/": { "auth": encode("hub-user:hub-pwd") },
+                "registry.redhat.io": { "auth": encode("rh-user:rh:pwd") },
+                "broken.io": { "auth": "not base64!" }
+            }
+        })
+        .to_string();
+        let creds = |registry: &str| parse_json_creds(data.clone(), registry);
+        let pair = |user: &str, pwd: &str| Some((user.to_string(), pwd.to_string()));
+        assert_eq!(creds("quay.io").unwrap(), pair("quay-user", "quay-pwd"));
+        assert_eq!(creds("docker.io").unwrap(), pair("hub-user", "hub-pwd"));
+        assert_eq!(creds("registry.redhat.io").unwrap(), pair("rh-user", "rh:pwd"));
+        assert_eq!(creds("localhost:5000").unwrap(), None);
+        assert!(matches!(creds("broken.io"), Err(MirrorError::Auth(_))));
+    }
+
+    #[test]
+    fn token_responses() {
+        let tests = vec![
+            (r#"{"token":"a","expires_in":300}"#, Some(("a", 300))),
+            (r#"{"access_token":"b"}"#, Some(("b", TOKEN_EXPIRY))),
+            (r#"{"token":"","access_token":"c","expires_in":30}"#, Some(("c", 30))),
+            (r#"{"expires_in":30}"#, None),
+        ];
+        for (data, expected) in tests {
+            let res = parse_json_token(data.to_string()).ok();
+            let res = res.as_ref().map(|(t, e)| (t.as_str(), *e));
+            assert_eq!(res, expected, "{}", data);
+        }
+    }
+
+    #[tokio::test]
+    async fn token_from_realm() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry = listener.local_addr().unwrap().to_string();
+        let (sender, requests) = mpsc::channel();
+        let realm = format!("http://{}/token", registry);
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0; 4096];
+                let n = std::io::Read::read(&mut stream, &mut buf).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let (status, header, body) = if request.starts_with("GET /v2/ ") {
+                    (
+                        "401 Unauthorized",
+                        format!(
+                            "WWW-Authenticate: Bearer realm=\"{}\",service=\"test\"\r\n",
+                            realm
+                        ),
+                        String::new(),
+                    )
+                } else if request.starts_with("GET /token") {
+                    sender.send(request).unwrap();
+                    (
+                        "200 OK",
+                        String::new(),
+                        r#"{"token":"secret-token","expires_in":300}"#.to_string(),
+                    )
+                } else {
+                    ("400 Bad Request", String::new(), String::new())
+                };
+                let _ = stream.write_all(
+                    format!(
+                        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        header,
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                );
+            }
+        });
+        set_insecure_registry(&registry).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("auth-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("auth.json");
+        let auth = general_purpose::STANDARD.encode("user:password");
+        fs::write(
+            &file,
+            serde_json::json!({ "auths": { registry.clone(): { "auth": auth } } }).to_string(),
+        )
+        .unwrap();
+        set_auth_file(file.to_string_lossy().to_string());
+
+        let img_ref = ImageReference {
+            registry: registry.clone(),
+            namespace: "org".to_string(),
+            name: "app".to_string(),
+            version: "v1".to_string(),
+        };
+        let scope = get_repository_scope(&img_ref, PUSH);
+        assert_eq!(scope, "repository:org/app:pull,push");
+        let token = get_token(registry.clone(), scope.clone()).await.unwrap();
+        assert_eq!(token, "secret-token");
+        let request = requests.recv().unwrap();
+        assert!(request.contains("scope=repository%3Aorg%2Fapp%3Apull%2Cpush"), "{}", request);
+        assert!(request.contains("service=test"), "{}", request);
+        let basic = format!("Basic {}", auth);
+        assert!(request.contains(&basic), "{}", request);
+
+        // the token is reused until it expires
+        assert_eq!(get_token(registry, scope).await.unwrap(), "secret-token");
+        assert!(requests.try_recv().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
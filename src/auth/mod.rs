@@ -1 +1,25 @@
-pub mod credentials;
+//! Registry credentials and tokens.
+
+pub(crate) mod credentials;
+
+use crate::api::error::*;
+use crate::image::copy::*;
+
+/// Read the credentials from 'file' instead of `$XDG_RUNTIME_DIR/containers/auth.json`,
+/// only the first call has an effect.
+pub fn set_auth_file(file: &str) {
+    credentials::set_auth_file(file.to_string())
+}
+
+/// Bearer token for the repository of 'image' with pull access (and push
+/// access when 'push' is set), empty for registries accessed anonymously.
+pub async fn get_token(image: &str, push: bool) -> Result<String, MirrorError> {
+    let img_ref = parse_image(image.to_string());
+    let actions = if push {
+        credentials::PUSH
+    } else {
+        credentials::PULL
+    };
+    let scope = credentials::get_repository_scope(&img_ref, actions);
+    credentials::get_token(img_ref.registry, scope).await
+}
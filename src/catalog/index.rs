@@ -0,0 +1,174 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::image::copy::*;
+use crate::image::source::*;
+use crate::log::logging::*;
+use crate::manifests::catalogs::*;
+use crate::registry::config::*;
+
+// working directory set with --working-dir
+static WORKING_DIR: OnceLock<String> = OnceLock::new();
+
+// get_catalog - download and untar the catalog index (if not cached)
+// returns the cache directory
+pub async fn get_catalog(img_ref: ImageReference) -> Result<String, MirrorError> {
+    let manifest_json = get_manifest_json_file(img_ref.name.clone(), img_ref.version.clone());
+    let working_dir_blobs = get_blobs_dir(img_ref.name.clone(), img_ref.version.clone());
+    let working_dir_cache = get_cache_dir(img_ref.name.clone(), img_ref.version.clone());
+
+    // check if the directory exists
+    if !Path::new(&working_dir_blobs).exists() {
+        let sources = get_pull_sources(&img_ref).await?;
+        // try the registries.conf mirrors first, then the source
+        let mut pulled = None;
+        let mut last_error = None;
+        for source in sources {
+            let scope = get_repository_scope(&source.img_ref, PULL);
+            let token = match get_token(source.img_ref.registry.clone(), scope).await {
+                Ok(token) => token,
+                Err(error) => {
+                    log_warn(&format!("{} : {}", source.img_ref.registry, error));
+                    last_error = Some(error);
+                    continue;
+                }
+            };
+            // use token to get manifest
+            let manifest_url = get_image_manifest_url(source.img_ref.clone());
+            let manifest = match get_manifest(manifest_url.clone(), token.clone()).await {
+                Ok(manifest) => manifest,
+                Err(error) => {
+                    log_warn(&error.to_string());
+                    last_error = Some(error);
+                    continue;
+                }
+            };
+            match parse_json_manifest(manifest.clone()) {
+                Ok(res) => {
+                    pulled = Some((source.img_ref, token, manifest, res));
+                    break;
+                }
+                Err(error) => {
+                    log_warn(&format!("{} : {}", manifest_url, error));
+                    last_error = Some(error);
+                }
+            }
+        }
+        let (source_ref, token, manifest, res) = pulled.ok_or_else(|| MirrorError::Context {
+            context: "unable to get the catalog index manifest".to_string(),
+            source: Box::new(last_error.unwrap_or(MirrorError::Other("no pull source".to_string()))),
+        })?;
+
+        // create the full path
+        fs::create_dir_all(&working_dir_blobs).context(format!("creating {}", working_dir_blobs))?;
+        fs::write(&manifest_json, manifest).context(format!("writing {}", manifest_json))?;
+        let blobs_url = get_blobs_url(source_ref);
+        let layers = res
+            .fs_layers
+            .into_iter()
+            .map(|layer| Layer {
+                digest: layer.blob_sum,
+                ..Default::default()
+            })
+            .collect();
+        if let Err(error) = get_blobs(blobs_url, token, layers, working_dir_blobs.clone()).await {
+            // don't leave a partial download behind as a valid cache
+            let _ = fs::remove_dir_all(&working_dir_blobs);
+            return Err(error).context("downloading the catalog index");
+        }
+        log_info("completed image index download");
+    } else {
+        log_info("catalog index exists nothing to do");
+    }
+    // check if the cache directory exists
+    if !Path::new(&working_dir_cache).exists() {
+        // create the cache directory
+        fs::create_dir_all(&working_dir_cache).context(format!("creating {}", working_dir_cache))?;
+        untar_layers(working_dir_blobs.clone()).await?;
+        log_info("completed untar of layers");
+    } else {
+        log_info("cache exists nothing to do");
+    }
+    Ok(working_dir_cache)
+}
+
+// get_configs_dir - the 'configs' directory of a catalog, either a local
+// directory, a local source (oci:, docker-archive:, dir:) or a catalog index
+pub async fn get_configs_dir(catalog: String) -> Result<String, MirrorError> {
+    if Path::new(&catalog).is_dir() {
+        return Ok(catalog);
+    }
+    let dir = if is_local_source(&catalog) {
+        get_source_catalog(catalog.clone()).await?
+    } else {
+        let cache = get_catalog(parse_image_index(catalog.clone())).await?;
+        find_dir(cache, "configs".to_string()).await
+    };
+    if dir.is_empty() {
+        return Err(MirrorError::Catalog(format!(
+            "configs directory not found in {}",
+            catalog
+        )));
+    }
+    Ok(dir)
+}
+
+// get_manifest_json
+fn get_manifest_json_file(name: String, version: String) -> String {
+    let mut file = get_working_dir() + "/";
+    file.push_str(&name);
+    file.push('/');
+    file.push_str(&version);
+    file.push('/');
+    file.push_str("manifest.json");
+    file
+}
+
+// get_blobs_dir
+fn get_blobs_dir(name: String, version: String) -> String {
+    let mut file = get_working_dir() + "/";
+    file.push_str(&name);
+    file.push('/');
+    file.push_str(&version);
+    file.push('/');
+    file.push_str("blobs/sha256/");
+    file
+}
+
+// get_cache_dir
+fn get_cache_dir(name: String, version: String) -> String {
+    let mut file = get_working_dir() + "/";
+    file.push_str(&name);
+    file.push('/');
+    file.push_str(&version);
+    file.push('/');
+    file.push_str("cache");
+    file
+}
+
+// get_working_dir
+pub fn get_working_dir() -> String {
+    WORKING_DIR
+        .get()
+        .cloned()
+        .unwrap_or("working-dir".to_string())
+}
+
+// set_working_dir - use 'dir' instead of ./working-dir (set once at startup)
+pub fn set_working_dir(dir: String) {
+    let _ = WORKING_DIR.set(dir.trim_end_matches('/').to_string());
+}
+
+// get_mirror_dir
+pub fn get_mirror_dir() -> String {
+    get_working_dir() + "/mirror/"
+}
+
+// get_results_dir
+pub fn get_results_dir() -> String {
+    get_working_dir() + "/results/"
+}
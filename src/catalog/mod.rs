@@ -0,0 +1,69 @@
+//! Operator catalogs: pulling a catalog index and reading its file based catalog.
+
+pub(crate) mod index;
+
+use std::path::Path;
+
+use crate::api::error::*;
+use crate::list::components::*;
+use crate::manifests::catalogs::*;
+use crate::mirror::related::*;
+use crate::validate::catalog::*;
+
+pub use crate::api::schema::{DeclarativeConfig, PackageSummary, ValidationFinding};
+
+/// Set the directory catalogs are pulled to and mirrored content is kept in
+/// (default `working-dir`), only the first call has an effect.
+pub fn set_working_dir(dir: &str) {
+    index::set_working_dir(dir.to_string())
+}
+
+/// Pull 'catalog' (an index image or a local oci, docker-archive or dir
+/// source) when it is not cached yet and return its `configs` directory,
+/// a directory is returned as is.
+pub async fn pull(catalog: &str) -> Result<String, MirrorError> {
+    index::get_configs_dir(catalog.to_string()).await
+}
+
+/// The packages in a `configs` directory in name order.
+pub fn list_packages(configs_dir: &str) -> Result<Vec<String>, MirrorError> {
+    select_packages(configs_dir.to_string(), "all".to_string(), None)
+}
+
+/// Every declarative config (package, channels, bundles) of 'package'.
+pub fn read_package(
+    configs_dir: &str,
+    package: &str,
+) -> Result<Vec<DeclarativeConfig>, MirrorError> {
+    read_operator_catalog(
+        Path::new(configs_dir)
+            .join(package)
+            .to_string_lossy()
+            .to_string(),
+    )
+}
+
+/// Default channel, channels and versions of 'package'.
+pub fn get_summary(configs_dir: &str, package: &str) -> Result<PackageSummary, MirrorError> {
+    let dc = read_package(configs_dir, package)?;
+    Ok(get_package_summary(package.to_string(), dc))
+}
+
+/// Stream the declarative configs of a single json or yaml catalog file to 'handle'.
+pub fn parse_file<F>(file: &Path, handle: F) -> Result<(), MirrorError>
+where
+    F: FnMut(DeclarativeConfig),
+{
+    parse_catalog_file(file, handle)
+}
+
+/// Validate 'package' (every package when `None`) in a `configs` directory.
+pub fn validate(
+    configs_dir: &str,
+    package: Option<&str>,
+) -> Result<Vec<ValidationFinding>, MirrorError> {
+    validate_catalog(
+        configs_dir.to_string(),
+        package.unwrap_or("all").to_string(),
+    )
+}
@@ -0,0 +1,248 @@
+use clap::CommandFactory;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::archive::pack::*;
+use crate::auth::credentials::*;
+use crate::catalog::index::*;
+use crate::config::read::*;
+use crate::diff::compare::*;
+use crate::graph::upgrade::*;
+use crate::inspect::image::*;
+use crate::list::components::*;
+use crate::log::logging::*;
+use crate::manifests::resources::*;
+use crate::mirror::gc::*;
+use crate::mirror::related::*;
+use crate::registry::client::*;
+use crate::registry::config::*;
+use crate::release::payload::*;
+use crate::search::packages::*;
+use crate::validate::catalog::*;
+
+// run - execute the parsed command line, errors are returned to the
+// binary which logs them and exits with their code (see fail)
+pub async fn run(args: Cli) -> Result<(), MirrorError> {
+    // log level from --log-level or -v / -q, text or json lines on stderr
+    let log_level = match &args.log_level {
+        Some(level) => LogLevel::parse(level),
+        None => Ok(LogLevel::from_verbosity(args.verbose, args.quiet)),
+    };
+    match (log_level, LogFormat::parse(&args.log_format)) {
+        (Ok(level), Ok(format)) => init_logging(level, format),
+        (Err(error), _) | (_, Err(error)) => return Err(MirrorError::Config(error)),
+    }
+
+    // completions need no config, credentials or registry access
+    if let Command::Completions(completions) = &args.command {
+        let mut cmd = Cli::command();
+        let name = cmd.get_name().to_string();
+        clap_complete::generate(completions.shell, &mut cmd, name, &mut std::io::stdout());
+        return Ok(());
+    }
+    log_debug(&format!("{:?}", args.command));
+
+    set_working_dir(args.working_dir.clone());
+    if !args.authfile.is_empty() {
+        set_auth_file(args.authfile.clone());
+    }
+
+    // mirrors, blocked and insecure registries used for every pull
+    load_registries_conf(args.registries_conf.clone())?;
+
+    // Parse the config serde_yaml::ImageSetConfig.
+    let mut isc: Option<ImageSetConfig> = None;
+    if !args.config.is_empty() {
+        let res = load_config(args.config.clone()).and_then(parse_yaml_config)?;
+        log_debug(&format!("{:#?}", res.mirror.platform));
+        isc = Some(res);
+    }
+
+    // ca files, client certificates, timeouts and proxies used for every registry call
+    let client_settings = ClientSettings {
+        tls_verify: args.tls_verify,
+        ca_files: args.ca_file.clone(),
+        cert_dir: args.cert_dir.clone(),
+        timeout: args.timeout,
+        proxy: isc.as_ref().and_then(|isc| isc.proxy.clone()),
+    };
+    init_registry_client(client_settings)?;
+
+    match args.command {
+        Command::Pull(pull) => {
+            println!("{}", get_configs(pull.catalog).await?);
+        }
+        Command::Push => {
+            // the images recorded by earlier mirror and release runs
+            let images = read_reports(get_mirror_dir());
+            if images.is_empty() {
+                return Err(MirrorError::Other(format!(
+                    "no mirrored images found in {}",
+                    get_mirror_dir()
+                )));
+            }
+            let images: Vec<&MirroredImage> = images.iter().collect();
+            let report = push_images("push", &images, get_working_dir()).await;
+            write_report(&report, get_results_dir() + "push-report.json")?;
+        }
+        Command::Mirror(mirror) => {
+            let catalog = mirror.catalog.catalog.clone();
+            let dir = get_configs(mirror.catalog.catalog).await?;
+            // use the packages from the image set config for this catalog (if any)
+            let packages = isc.and_then(|isc| {
+                isc.mirror
+                    .operators
+                    .into_iter()
+                    .find(|op| op.catalog == catalog)
+                    .and_then(|op| op.packages)
+            });
+            let selected = select_packages(dir.clone(), mirror.filter, packages)?;
+            let images = collect_related_images(dir, selected);
+            let total = images.len();
+            let unique = dedupe_related_images(images);
+            log_info(&format!(
+                "found {} related images ({} unique)",
+                total,
+                unique.len()
+            ));
+            let mirror_dir = get_mirror_dir();
+            let report = mirror_related_images(
+                catalog,
+                total,
+                unique,
+                mirror.destination.clone(),
+                mirror_dir.clone(),
+            )
+            .await;
+            write_report(&report, mirror_dir + "related-images.json")?;
+            write_resources(&report, mirror.destination, get_results_dir())?;
+        }
+        // release mirroring does not need a catalog index
+        Command::Release(release) => match isc {
+            Some(isc) => {
+                let report = mirror_platform(
+                    isc.mirror.platform,
+                    release.graph_url,
+                    release.destination,
+                    get_mirror_dir(),
+                )
+                .await?;
+                write_release_resources(&report, get_results_dir())?;
+            }
+            None => {
+                return Err(MirrorError::Config(
+                    "release requires an image set config (--config)".to_string(),
+                ))
+            }
+        },
+        Command::List(list) => {
+            let dir = get_configs(list.catalog.catalog).await?;
+            list_components(
+                "operator".to_string(),
+                dir,
+                list.filter,
+                list.output,
+            )
+            .await?;
+        }
+        Command::Graph(graph) => {
+            let dir = get_configs(graph.catalog.catalog).await?;
+            graph_components(dir, graph.filter, graph.output)?;
+        }
+        // search the given catalog or (without --catalog) every cached catalog
+        Command::Search(search) => {
+            let catalogs = if search.catalog.is_empty() {
+                find_cached_catalogs(get_working_dir()).await
+            } else {
+                let dir = get_configs(search.catalog.clone()).await?;
+                vec![(search.catalog, dir)]
+            };
+            let catalogs: Vec<(String, String)> = catalogs
+                .into_iter()
+                .filter(|(_, dir)| !dir.is_empty())
+                .collect();
+            if catalogs.is_empty() {
+                return Err(MirrorError::Catalog(
+                    "no catalogs found to search".to_string(),
+                ));
+            }
+            let results = search_catalogs(catalogs, search.query)?;
+            print_search(&results, search.output)?;
+        }
+        // compare against a local 'configs' directory (e.g. a copy kept from an earlier pull,
+        // pulling the same tag again reuses its cache), a local source or another catalog index
+        Command::Diff(diff) => {
+            let from = diff.catalog.catalog.clone();
+            let from_dir = get_configs(diff.catalog.catalog).await?;
+            let to_dir = get_configs(diff.compare.clone()).await?;
+            let res = diff_catalogs(from, from_dir, diff.compare, to_dir)?;
+            print_diff(&res, diff.output)?;
+        }
+        // validate a catalog (e.g. a local 'configs' directory before pushing a modified catalog)
+        Command::Validate(validate) => {
+            let catalog = validate.catalog.catalog.clone();
+            let dir = get_configs(validate.catalog.catalog).await?;
+            let findings = validate_catalog(dir, validate.filter)?;
+            let errors = print_findings(&findings, validate.output)?;
+            if errors > 0 {
+                return Err(MirrorError::Other(format!(
+                    "catalog {} is not valid ({} errors)",
+                    catalog, errors
+                )));
+            }
+        }
+        // registry queries for any image, no catalog index needed
+        Command::Inspect(inspect) => {
+            let res = inspect_image(inspect.image).await?;
+            print_inspect(&res, inspect.output)?;
+        }
+        Command::Tags(tags) => {
+            let list = list_tags(tags.repository, tags.filter).await?;
+            print_list(&list, tags.output)?;
+        }
+        Command::Repositories(repositories) => {
+            let list = list_repositories(repositories.registry, repositories.filter).await?;
+            print_list(&list, repositories.output)?;
+        }
+        // carry mirrored content across an air gap
+        Command::Pack(pack) => {
+            let storage = isc.map(|isc| isc.storage_config.local.path);
+            pack_mirror(
+                get_working_dir(),
+                pack.archive.archive_dir,
+                pack.archive_size,
+                pack.destination,
+                storage,
+            )
+            .context("pack")?;
+        }
+        Command::Unpack(unpack) => {
+            unpack_archives(unpack.archive_dir, get_working_dir()).context("unpack")?;
+        }
+        Command::Publish(publish) => {
+            let sets = unpack_archives(publish.archive.archive_dir, get_working_dir())
+                .context("unpack")?;
+            let report = publish_images(&sets, publish.destination, get_working_dir()).await;
+            write_report(&report, get_results_dir() + "publish-report.json")?;
+        }
+        Command::Gc(gc) => {
+            collect_garbage(get_mirror_dir(), gc.dry_run)?;
+        }
+        Command::Completions(_) => {}
+    }
+    Ok(())
+}
+
+// get_configs - the 'configs' directory of a catalog (pulled when needed)
+async fn get_configs(catalog: String) -> Result<String, MirrorError> {
+    let dir = get_configs_dir(catalog).await?;
+    log_info(&format!("full path for directory 'configs' {} ", &dir));
+    Ok(dir)
+}
+
+// fail - log the error (with its context chain) and exit with the code
+// of its kind (see MirrorError::exit_code)
+pub fn fail(error: MirrorError) -> ! {
+    log_error(&error.to_string());
+    std::process::exit(error.exit_code());
+}
@@ -0,0 +1,7 @@
+//! The command line of the rust-container-tool binary, exposed so that the
+//! binary stays a thin wrapper and other tools can embed the same commands.
+
+mod commands;
+
+pub use crate::api::schema::Cli;
+pub use commands::{fail, run};
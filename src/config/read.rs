@@ -1,30 +1,29 @@
 use std::fs::File;
 use std::io::Read;
-use crate::Path;
+use std::path::Path;
+use crate::api::error::*;
 use crate::api::schema::*;
 
 // read the 'image set config' file
-pub fn load_config(dir: String) -> Result<String, Box<dyn std::error::Error>> {
+pub fn load_config(dir: String) -> Result<String, MirrorError> {
     // Create a path to the desired file
     let path = Path::new(&dir);
     let display = path.display();
 
     // Open the path in read-only mode, returns `io::Result<File>`
-    let mut file = match File::open(&path) {
-        Err(why) => panic!("couldn't open {}: {}", display, why),
-        Ok(file) => file,
-    };
+    let mut file = File::open(path)
+        .map_err(|why| MirrorError::Config(format!("couldn't open {}: {}", display, why)))?;
 
     // Read the file contents into a string, returns `io::Result<usize>`
     let mut s = String::new();
-    file.read_to_string(&mut s)?;
+    file.read_to_string(&mut s).context(format!("reading {}", display))?;
     Ok(s)
 }
 
 // parse the 'image set config' file
-pub fn parse_yaml_config(data: String) -> Result<ImageSetConfig,serde_yaml::Error> {
+pub fn parse_yaml_config(data: String) -> Result<ImageSetConfig, MirrorError> {
     // Parse the string of data into serde_json::ImageSetConfig.
-    let res = serde_yaml::from_str::<ImageSetConfig>(&data);
-    res
+    serde_yaml::from_str::<ImageSetConfig>(&data)
+        .map_err(|why| MirrorError::Config(format!("parsing image set config: {}", why)))
 }
 
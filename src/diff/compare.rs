@@ -0,0 +1,348 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::log::logging::*;
+use crate::manifests::catalogs::*;
+use crate::mirror::related::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+// PackageContent the parts of a package we compare
+#[derive(Default)]
+struct PackageContent {
+    default_channel: Option<String>,
+    channels: BTreeMap<String, Vec<String>>,
+    related_images: BTreeSet<String>,
+}
+
+// get_package_content - default channel, channel entries and related images
+// (including the bundle images) of a package
+fn get_package_content(dc: Vec<DeclarativeConfig>) -> PackageContent {
+    let mut content = PackageContent::default();
+    for obj in dc {
+        match obj {
+            DeclarativeConfig::Package(pkg) => content.default_channel = pkg.default_channel,
+            DeclarativeConfig::Channel(ch) => {
+                let entries = ch.entries.into_iter().map(|e| e.name).collect();
+                content.channels.insert(ch.name, entries);
+            }
+            DeclarativeConfig::Bundle(bundle) => {
+                if !bundle.image.is_empty() {
+                    content.related_images.insert(bundle.image);
+                }
+                for ri in bundle.related_images {
+                    content.related_images.insert(ri.image);
+                }
+            }
+            DeclarativeConfig::Unknown(_) => {}
+        }
+    }
+    content
+}
+
+// get_added_removed - items only in 'new' and items only in 'old'
+fn get_added_removed<'a>(
+    old: impl Iterator<Item = &'a String>,
+    new: impl Iterator<Item = &'a String>,
+) -> (Vec<String>, Vec<String>) {
+    let old: BTreeSet<&String> = old.collect();
+    let new: BTreeSet<&String> = new.collect();
+    let added = new.difference(&old).map(|s| s.to_string()).collect();
+    let removed = old.difference(&new).map(|s| s.to_string()).collect();
+    (added, removed)
+}
+
+// diff_package - compare two versions of a package, None if nothing changed
+pub fn diff_package(
+    name: String,
+    old: Vec<DeclarativeConfig>,
+    new: Vec<DeclarativeConfig>,
+) -> Option<PackageDiff> {
+    let old = get_package_content(old);
+    let new = get_package_content(new);
+    let mut diff = PackageDiff {
+        name,
+        ..Default::default()
+    };
+    if old.default_channel != new.default_channel {
+        diff.default_channel = Some(ValueChange {
+            from: old.default_channel.clone(),
+            to: new.default_channel.clone(),
+        });
+    }
+    (diff.added_channels, diff.removed_channels) =
+        get_added_removed(old.channels.keys(), new.channels.keys());
+    for (channel, old_entries) in old.channels.iter() {
+        if let Some(new_entries) = new.channels.get(channel) {
+            let (added, removed) = get_added_removed(old_entries.iter(), new_entries.iter());
+            if !added.is_empty() || !removed.is_empty() {
+                diff.channels.push(ChannelDiff {
+                    name: channel.clone(),
+                    added_bundles: added,
+                    removed_bundles: removed,
+                });
+            }
+        }
+    }
+    (diff.added_related_images, diff.removed_related_images) =
+        get_added_removed(old.related_images.iter(), new.related_images.iter());
+
+    let changed = diff.default_channel.is_some()
+        || !diff.added_channels.is_empty()
+        || !diff.removed_channels.is_empty()
+        || !diff.channels.is_empty()
+        || !diff.added_related_images.is_empty()
+        || !diff.removed_related_images.is_empty();
+    if changed {
+        Some(diff)
+    } else {
+        None
+    }
+}
+
+// diff_catalogs - compare every package in two 'configs' directories
+// a package that can't be read in either catalog fails the comparison
+pub fn diff_catalogs(
+    from: String,
+    from_dir: String,
+    to: String,
+    to_dir: String,
+) -> Result<CatalogDiff, MirrorError> {
+    let old = select_packages(from_dir.clone(), "all".to_string(), None)?;
+    let new = select_packages(to_dir.clone(), "all".to_string(), None)?;
+    let (added_packages, removed_packages) = get_added_removed(old.iter(), new.iter());
+    let mut changed_packages = vec![];
+    for pkg in old.iter().filter(|pkg| new.contains(pkg)) {
+        let old_dc = read_operator_catalog(from_dir.clone() + "/" + pkg);
+        let new_dc = read_operator_catalog(to_dir.clone() + "/" + pkg);
+        match (old_dc, new_dc) {
+            (Ok(old_dc), Ok(new_dc)) => {
+                if let Some(diff) = diff_package(pkg.clone(), old_dc, new_dc) {
+                    changed_packages.push(diff);
+                }
+            }
+            (Err(error), _) | (_, Err(error)) => {
+                return Err(error).context(format!("comparing package {}", pkg))
+            }
+        }
+    }
+    Ok(CatalogDiff {
+        from,
+        to,
+        added_packages,
+        removed_packages,
+        changed_packages,
+    })
+}
+
+// render_diff - human readable form of the catalog differences
+pub fn render_diff(diff: &CatalogDiff) -> String {
+    let mut out = format!("--- {}\n+++ {}\n", diff.from, diff.to);
+    for pkg in diff.added_packages.iter() {
+        out.push_str(&format!("+ package {}\n", pkg));
+    }
+    for pkg in diff.removed_packages.iter() {
+        out.push_str(&format!("- package {}\n", pkg));
+    }
+    for pkg in diff.changed_packages.iter() {
+        out.push_str(&format!("~ package {}\n", pkg.name));
+        if let Some(change) = &pkg.default_channel {
+            out.push_str(&format!(
+                "    defaultChannel {} => {}\n",
+                change.from.clone().unwrap_or_default(),
+                change.to.clone().unwrap_or_default()
+            ));
+        }
+        for ch in pkg.added_channels.iter() {
+            out.push_str(&format!("  + channel {}\n", ch));
+        }
+        for ch in pkg.removed_channels.iter() {
+            out.push_str(&format!("  - channel {}\n", ch));
+        }
+        for ch in pkg.channels.iter() {
+            out.push_str(&format!("  ~ channel {}\n", ch.name));
+            for bundle in ch.added_bundles.iter() {
+                out.push_str(&format!("    + bundle {}\n", bundle));
+            }
+            for bundle in ch.removed_bundles.iter() {
+                out.push_str(&format!("    - bundle {}\n", bundle));
+            }
+        }
+        for image in pkg.added_related_images.iter() {
+            out.push_str(&format!("  + image {}\n", image));
+        }
+        for image in pkg.removed_related_images.iter() {
+            out.push_str(&format!("  - image {}\n", image));
+        }
+    }
+    out
+}
+
+// print_diff - write the differences to stdout in the requested format
+pub fn print_diff(diff: &CatalogDiff, output: Option<OutputFormat>) -> Result<(), MirrorError> {
+    match output {
+        Some(OutputFormat::Json) => println!("{}", serde_json::to_string_pretty(diff)?),
+        Some(OutputFormat::Yaml) => print!("{}", serde_yaml::to_string(diff)?),
+        None => print!("{}", render_diff(diff)),
+    }
+    log_info(&format!(
+        "packages added {} removed {} changed {}",
+        diff.added_packages.len(),
+        diff.removed_packages.len(),
+        diff.changed_packages.len()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn package(default_channel: &str) -> DeclarativeConfig {
+        DeclarativeConfig::Package(Package {
+            name: "pkg".to_string(),
+            default_channel: Some(default_channel.to_string()),
+            description: None,
+            icon: None,
+        })
+    }
+
+    fn channel(name: &str, entries: &[&str]) -> DeclarativeConfig {
+        DeclarativeConfig::Channel(Channel {
+            name: name.to_string(),
+            package: "pkg".to_string(),
+            entries: entries
+                .iter()
+                .map(|entry| ChannelEntry {
+                    name: entry.to_string(),
+                    replaces: None,
+                    skips: None,
+                    skip_range: None,
+                })
+                .collect(),
+        })
+    }
+
+    fn bundle(name: &str, images: &[&str]) -> DeclarativeConfig {
+        DeclarativeConfig::Bundle(Bundle {
+            name: name.to_string(),
+            package: "pkg".to_string(),
+            image: format!("quay.io/pkg/bundle:{}", name),
+            properties: vec![],
+            related_images: images
+                .iter()
+                .map(|image| RelatedImage {
+                    name: String::new(),
+                    image: image.to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn package_changes() {
+        let base = || {
+            vec![
+                package("stable"),
+                channel("stable", &["v1"]),
+                bundle("v1", &["quay.io/pkg/operand:v1"]),
+            ]
+        };
+        let tests = vec![
+            ("unchanged", base(), vec![]),
+            (
+                "default channel",
+                vec![
+                    package("fast"),
+                    channel("stable", &["v1"]),
+                    bundle("v1", &["quay.io/pkg/operand:v1"]),
+                ],
+                vec!["    defaultChannel stable => fast"],
+            ),
+            (
+                "channel added",
+                vec![
+                    package("stable"),
+                    channel("stable", &["v1"]),
+                    channel("fast", &["v1"]),
+                    bundle("v1", &["quay.io/pkg/operand:v1"]),
+                ],
+                vec!["  + channel fast"],
+            ),
+            (
+                "channel removed",
+                vec![package("stable"), bundle("v1", &["quay.io/pkg/operand:v1"])],
+                vec!["  - channel stable"],
+            ),
+            (
+                "bundle added",
+                vec![
+                    package("stable"),
+                    channel("stable", &["v1", "v2"]),
+                    bundle("v1", &["quay.io/pkg/operand:v1"]),
+                    bundle("v2", &["quay.io/pkg/operand:v2"]),
+                ],
+                vec![
+                    "  ~ channel stable",
+                    "    + bundle v2",
+                    "  + image quay.io/pkg/bundle:v2",
+                    "  + image quay.io/pkg/operand:v2",
+                ],
+            ),
+            (
+                "bundle removed",
+                vec![package("stable"), channel("stable", &[])],
+                vec![
+                    "  ~ channel stable",
+                    "    - bundle v1",
+                    "  - image quay.io/pkg/bundle:v1",
+                    "  - image quay.io/pkg/operand:v1",
+                ],
+            ),
+        ];
+        for (name, new, expected) in tests {
+            let diff = diff_package("pkg".to_string(), base(), new);
+            let rendered = match diff {
+                Some(diff) => render_diff(&CatalogDiff {
+                    from: String::new(),
+                    to: String::new(),
+                    added_packages: vec![],
+                    removed_packages: vec![],
+                    changed_packages: vec![diff],
+                }),
+                None => String::new(),
+            };
+            // the lines below the ---/+++ header and "~ package pkg"
+            let changes: Vec<&str> = rendered.lines().skip(3).collect();
+            assert_eq!(changes, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn catalog_changes() {
+        let root = std::env::temp_dir().join(format!("diff-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |catalog: &str, pkg: &str, data: &str| {
+            let dir = root.join(catalog).join(pkg);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("catalog.json"), data).unwrap();
+        };
+        let pkg = r#"{"schema":"olm.package","name":"shared","defaultChannel":"stable"}"#;
+        write("old", "shared", pkg);
+        write("old", "removed", pkg);
+        write("new", "shared", pkg);
+        write("new", "added", pkg);
+        let dir = |catalog: &str| root.join(catalog).to_string_lossy().to_string();
+        let diff = diff_catalogs("old".to_string(), dir("old"), "new".to_string(), dir("new"))
+            .unwrap();
+        assert_eq!(diff.added_packages, vec!["added"]);
+        assert_eq!(diff.removed_packages, vec!["removed"]);
+        assert!(diff.changed_packages.is_empty());
+
+        // a package that can't be read is an error, not a missing difference
+        write("new", "shared", "{not json");
+        let error = diff_catalogs("old".to_string(), dir("old"), "new".to_string(), dir("new"))
+            .unwrap_err();
+        assert!(error.to_string().contains("comparing package shared"), "{}", error);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::log::logging::*;
+use crate::manifests::catalogs::*;
+use crate::mirror::related::*;
+use semver::{BuildMetadata, Op, Version, VersionReq};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// graph_components - print the upgrade graph of every channel for the
+// selected packages in either dot (default) or mermaid format
+pub fn graph_components(dir: String, filter: String, output: GraphFormat) -> Result<(), MirrorError> {
+    for pkg in select_packages(dir.clone(), filter, None)? {
+        let dc = match read_operator_catalog(dir.clone() + "/" + &pkg) {
+            Ok(dc) => dc,
+            Err(error) => {
+                log_error(&error.to_string());
+                continue;
+            }
+        };
+        let versions = get_bundle_versions(&dc);
+        for obj in dc.iter() {
+            if let DeclarativeConfig::Channel(channel) = obj {
+                let graph = build_upgrade_graph(channel, &versions);
+                match output {
+                    GraphFormat::Mermaid => println!("{}", render_mermaid(&graph)),
+                    GraphFormat::Dot => println!("{}", render_dot(&graph)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// get_bundle_versions - map each bundle name to its (olm.package) version
+pub fn get_bundle_versions(dc: &[DeclarativeConfig]) -> HashMap<String, Version> {
+    let mut versions = HashMap::new();
+    for obj in dc.iter() {
+        if let DeclarativeConfig::Bundle(bundle) = obj {
+            if let Some(version) = bundle.version().and_then(|v| Version::parse(&v).ok()) {
+                versions.insert(bundle.name.clone(), version);
+            }
+        }
+    }
+    versions
+}
+
+// get_entry_version - version of a channel entry, if the bundle is not in the
+// catalog fall back to the usual '<package>.v<version>' naming convention
+fn get_entry_version(name: &str, versions: &HashMap<String, Version>) -> Option<Version> {
+    match versions.get(name) {
+        Some(version) => Some(version.clone()),
+        None => name
+            .split_once(".v")
+            .and_then(|(_, v)| Version::parse(v).ok()),
+    }
+}
+
+// parse_skip_range - convert a blang/semver range (as used by olm) into
+// semver requirements, comparators are space separated and '||' is an or
+// blang semantics: a bare version (or '==') is an exact match, an operator
+// can be separated from its version by spaces and '!=' (or '!') excludes a
+// version, it is expanded to '<v || >v' as semver has no such comparator
+pub fn parse_skip_range(range: &str) -> Vec<VersionReq> {
+    let mut reqs = vec![];
+    for part in range.split("||") {
+        let mut groups: Vec<Vec<String>> = vec![vec![]];
+        let mut pending = String::new();
+        for token in part.split_whitespace() {
+            let op_len = token
+                .find(|c: char| !matches!(c, '<' | '>' | '=' | '!'))
+                .unwrap_or(token.len());
+            // an operator on its own belongs to the next token
+            if op_len == token.len() {
+                pending.push_str(token);
+                continue;
+            }
+            let op = std::mem::take(&mut pending) + &token[..op_len];
+            let version = &token[op_len..];
+            let alternatives = match op.as_str() {
+                "" | "=" | "==" => vec![format!("={}", version)],
+                "!" | "!=" => vec![format!("<{}", version), format!(">{}", version)],
+                _ => vec![op + version],
+            };
+            groups = groups
+                .into_iter()
+                .flat_map(|group| {
+                    alternatives.iter().map(move |comparator| {
+                        let mut group = group.clone();
+                        group.push(comparator.clone());
+                        group
+                    })
+                })
+                .collect();
+        }
+        for group in groups {
+            match VersionReq::parse(&group.join(", ")) {
+                Ok(req) => reqs.push(req),
+                Err(error) => log_warn(&format!("invalid skipRange {} : {}", range, error)),
+            }
+        }
+    }
+    reqs
+}
+
+// matches_skip_range - true when 'version' is in one of the requirements of
+// parse_skip_range, compared by semver precedence as blang does, so a
+// prerelease is in '>=4.1.0-0 <4.2.0' (4.1.5-rc.1 and 4.2.0-rc.1 included)
+// VersionReq::matches differs, it only lets a prerelease match a comparator
+// of the same major.minor.patch
+pub fn matches_skip_range(reqs: &[VersionReq], version: &Version) -> bool {
+    reqs.iter().any(|req| {
+        req.comparators.iter().all(|comparator| {
+            let (minor, patch) = match (comparator.minor, comparator.patch) {
+                (Some(minor), Some(patch)) => (minor, patch),
+                _ => return comparator.matches(version),
+            };
+            let bound = Version {
+                major: comparator.major,
+                minor,
+                patch,
+                pre: comparator.pre.clone(),
+                build: BuildMetadata::EMPTY,
+            };
+            let order = version.cmp_precedence(&bound);
+            match comparator.op {
+                Op::Exact => order == Ordering::Equal,
+                Op::Greater => order == Ordering::Greater,
+                Op::GreaterEq => order != Ordering::Less,
+                Op::Less => order == Ordering::Less,
+                Op::LessEq => order != Ordering::Greater,
+                _ => comparator.matches(version),
+            }
+        })
+    })
+}
+
+// build_upgrade_graph - build the upgrade dag for a channel from the
+// replaces, skips and skipRange fields of each entry
+pub fn build_upgrade_graph(channel: &Channel, versions: &HashMap<String, Version>) -> UpgradeGraph {
+    let names: Vec<String> = channel.entries.iter().map(|e| e.name.clone()).collect();
+    let mut edges = vec![];
+    let mut missing = vec![];
+    let mut replaced = HashSet::new();
+
+    for entry in channel.entries.iter() {
+        let mut sources = vec![];
+        if let Some(replaces) = &entry.replaces {
+            sources.push((replaces.clone(), "replaces"));
+        }
+        for skip in entry.skips.clone().unwrap_or_default() {
+            sources.push((skip, "skips"));
+        }
+        for (from, kind) in sources {
+            if !names.contains(&from) && !missing.contains(&from) {
+                missing.push(from.clone());
+            }
+            replaced.insert(from.clone());
+            edges.push(UpgradeEdge {
+                from,
+                to: entry.name.clone(),
+                kind: kind.to_string(),
+            });
+        }
+        if let Some(range) = &entry.skip_range {
+            let reqs = parse_skip_range(range);
+            for other in channel.entries.iter() {
+                if other.name == entry.name {
+                    continue;
+                }
+                let matched = match get_entry_version(&other.name, versions) {
+                    Some(version) => matches_skip_range(&reqs, &version),
+                    None => false,
+                };
+                if matched {
+                    edges.push(UpgradeEdge {
+                        from: other.name.clone(),
+                        to: entry.name.clone(),
+                        kind: "skipRange".to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    // the head is the entry no other entry replaces or skips
+    // (a duplicated entry is only counted once)
+    let mut seen = HashSet::new();
+    let heads: Vec<String> = names
+        .iter()
+        .filter(|name| !replaced.contains(*name) && seen.insert(*name))
+        .cloned()
+        .collect();
+
+    // with more than one candidate (invalid for olm) the highest version wins
+    let head = heads
+        .iter()
+        .max_by_key(|name| get_entry_version(name, versions))
+        .cloned();
+
+    // walk the edges backwards from the head, anything not visited
+    // has no upgrade path to the head
+    let mut previous: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges.iter() {
+        previous
+            .entry(edge.to.as_str())
+            .or_default()
+            .push(edge.from.as_str());
+    }
+    let mut visited: HashSet<&str> = head.iter().map(|h| h.as_str()).collect();
+    let mut queue: VecDeque<&str> = head.iter().map(|h| h.as_str()).collect();
+    while let Some(current) = queue.pop_front() {
+        for from in previous.get(current).cloned().unwrap_or_default() {
+            if visited.insert(from) {
+                queue.push_back(from);
+            }
+        }
+    }
+    let unreachable = names
+        .iter()
+        .filter(|name| !visited.contains(name.as_str()))
+        .cloned()
+        .collect();
+
+    let mut nodes = names;
+    nodes.extend(missing.clone());
+    UpgradeGraph {
+        package: channel.package.clone(),
+        channel: channel.name.clone(),
+        nodes,
+        edges,
+        head,
+        heads,
+        unreachable,
+        missing,
+    }
+}
+
+// render_dot - graphviz representation of the upgrade graph
+pub fn render_dot(graph: &UpgradeGraph) -> String {
+    let mut dot = format!("digraph \"{}/{}\" {{\n", graph.package, graph.channel);
+    dot.push_str("  rankdir=LR;\n");
+    for node in graph.nodes.iter() {
+        let attrs = if graph.head.as_ref() == Some(node) {
+            ", shape=doublecircle, style=filled, fillcolor=palegreen"
+        } else if graph.unreachable.contains(node) {
+            ", style=filled, fillcolor=lightcoral"
+        } else if graph.missing.contains(node) {
+            ", style=dashed, color=grey"
+        } else {
+            ""
+        };
+        dot.push_str(&format!("  \"{}\" [label=\"{}\"{}];\n", node, node, attrs));
+    }
+    for edge in graph.edges.iter() {
+        let style = match edge.kind.as_str() {
+            "skips" => ", style=dashed",
+            "skipRange" => ", style=dotted",
+            _ => "",
+        };
+        dot.push_str(&format!(
+            "  \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+            edge.from, edge.to, edge.kind, style
+        ));
+    }
+    dot.push('}');
+    dot
+}
+
+// render_mermaid - mermaid flowchart representation of the upgrade graph
+pub fn render_mermaid(graph: &UpgradeGraph) -> String {
+    // mermaid node ids can't contain dots so we use the node index
+    let id = |name: &str| {
+        let pos = graph.nodes.iter().position(|n| n == name).unwrap_or(0);
+        format!("n{}", pos)
+    };
+    let mut mmd = format!("---\ntitle: {}/{}\n---\n", graph.package, graph.channel);
+    mmd.push_str("graph LR\n");
+    for node in graph.nodes.iter() {
+        mmd.push_str(&format!("  {}[\"{}\"]\n", id(node), node));
+    }
+    for edge in graph.edges.iter() {
+        let arrow = match edge.kind.as_str() {
+            "replaces" => "-->",
+            _ => "-.->",
+        };
+        mmd.push_str(&format!(
+            "  {} {}|{}| {}\n",
+            id(&edge.from),
+            arrow,
+            edge.kind,
+            id(&edge.to)
+        ));
+    }
+    mmd.push_str("  classDef head fill:#98fb98,stroke:#333\n");
+    mmd.push_str("  classDef unreachable fill:#f08080,stroke:#333\n");
+    mmd.push_str("  classDef missing stroke-dasharray:5 5\n");
+    let head: Vec<String> = graph.head.iter().cloned().collect();
+    for (class, names) in [
+        ("head", &head),
+        ("unreachable", &graph.unreachable),
+        ("missing", &graph.missing),
+    ] {
+        if !names.is_empty() {
+            let ids: Vec<String> = names.iter().map(|n| id(n)).collect();
+            mmd.push_str(&format!("  class {} {}\n", ids.join(","), class));
+        }
+    }
+    mmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(range: &str, version: &str) -> bool {
+        let version = Version::parse(version).unwrap();
+        matches_skip_range(&parse_skip_range(range), &version)
+    }
+
+    #[test]
+    fn skip_range() {
+        for (range, version, expected) in [
+            // ranges as found in the redhat and community catalogs
+            (">=4.1.0 <4.2.0", "4.1.0", true),
+            (">=4.1.0 <4.2.0", "4.1.9", true),
+            (">=4.1.0 <4.2.0", "4.2.0", false),
+            (">=0.9.0 <0.9.4", "0.9.2", true),
+            (">=0.9.0 <0.9.4", "0.9.4", false),
+            (">=1.0.0 <1.3.1", "1.2.0", true),
+            ("<1.3.1", "0.1.0", true),
+            ("<1.3.1", "1.3.1", false),
+            (">=4.10.0-0 <4.11.0", "4.10.0-0", true),
+            (">= 2.1.0 < 2.4.0", "2.3.0", true),
+            (">= 2.1.0 < 2.4.0", "2.4.0", false),
+            (">=1.0.0 <1.1.0 || >=2.0.0 <2.1.0", "2.0.5", true),
+            (">=1.0.0 <1.1.0 || >=2.0.0 <2.1.0", "1.5.0", false),
+            // a bare version is an exact match (not a caret requirement)
+            ("1.2.3", "1.2.3", true),
+            ("1.2.3", "1.2.4", false),
+            ("==1.2.3", "1.2.3", true),
+            // != excludes a single version
+            (">=1.0.0 !=1.0.2 <1.1.0", "1.0.1", true),
+            (">=1.0.0 !=1.0.2 <1.1.0", "1.0.2", false),
+            (">=1.0.0 !1.0.2 <1.1.0", "1.0.3", true),
+            // prereleases are ordered by precedence (blang), not only next to
+            // a comparator of the same major.minor.patch (semver crate)
+            (">=4.1.0-0 <4.2.0", "4.1.0-0", true),
+            (">=4.1.0-0 <4.2.0", "4.1.5-rc.1", true),
+            (">=4.1.0-0 <4.2.0", "4.2.0-rc.1", true),
+            (">=4.1.0-0 <4.2.0", "4.2.0", false),
+            (">=4.1.0 <4.2.0", "4.1.0-rc.1", false),
+            (">=4.1.0 <4.2.0", "4.1.3-rc.1", true),
+            ("4.1.0-rc.1", "4.1.0-rc.1", true),
+            ("4.1.0-rc.1", "4.1.0", false),
+        ] {
+            assert_eq!(matches(range, version), expected, "{} {}", range, version);
+        }
+    }
+
+    #[test]
+    fn skip_range_of_a_prerelease_bundle() {
+        let entry = |name: &str, skip_range: Option<&str>| ChannelEntry {
+            name: name.to_string(),
+            replaces: None,
+            skips: None,
+            skip_range: skip_range.map(|r| r.to_string()),
+        };
+        let channel = Channel {
+            name: "stable-4.2".to_string(),
+            package: "cluster-logging".to_string(),
+            entries: vec![
+                entry("cluster-logging.v4.1.5-rc.1", None),
+                entry("cluster-logging.v4.1.9", None),
+                entry("cluster-logging.v4.2.0", Some(">=4.1.0-0 <4.2.0")),
+            ],
+        };
+        let graph = build_upgrade_graph(&channel, &HashMap::new());
+        let edges: Vec<(&str, &str)> = graph
+            .edges
+            .iter()
+            .map(|e| (e.from.as_str(), e.kind.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                ("cluster-logging.v4.1.5-rc.1", "skipRange"),
+                ("cluster-logging.v4.1.9", "skipRange"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_skip_range() {
+        assert!(parse_skip_range("not a range").is_empty());
+    }
+}
@@ -1,149 +1,491 @@
 use flate2::read::GzDecoder;
 use futures::{stream, StreamExt};
-use reqwest::Client;
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
-use std::env;
 use std::fs;
 use std::fs::File;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 //use std::str;
 use tar::Archive;
+use tokio::io::AsyncWriteExt;
 
+use crate::api::error::*;
 use crate::api::schema::*;
+use crate::auth::credentials::*;
 use crate::log::logging::*;
+use crate::log::progress::*;
+use crate::registry::client::*;
+use crate::registry::config::*;
+
+// media types we can process when copying an image
+pub const MANIFEST_MEDIA_TYPES: [&str; 4] = [
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
 
 // get manifest async api call
-pub async fn get_manifest(
-    url: String,
-    token: String,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let mut header_bearer: String = "Bearer ".to_owned();
-    header_bearer.push_str(&token);
-    let body = client
-        .get(url)
+// an error status is decoded into MirrorError::Registry, the body is only
+// returned when it is a manifest
+pub async fn get_manifest(url: String, token: String) -> Result<String, MirrorError> {
+    let client = get_registry_client(&url)?;
+    let mut req = client
+        .get(url.clone())
         .header("Accept", "application/vnd.oci.image.manifest.v1+json")
-        .header("Content-Type", "application/json")
-        .header("Authorization", header_bearer)
-        .send()
-        .await?
-        .text()
-        .await?;
+        .header("Content-Type", "application/json");
+    if !token.is_empty() {
+        req = req.bearer_auth(token);
+    }
+    let body = check_response(req.send().await?).await?.text().await?;
+    validate_manifest(&url, &body)?;
     Ok(body)
 }
 
+// get image manifest async api call
+// accepts manifest lists and indexes as well as single image manifests
+pub async fn get_image_manifest(url: String, token: String) -> Result<String, MirrorError> {
+    let client = get_registry_client(&url)?;
+    let mut req = client
+        .get(url.clone())
+        .header("Accept", MANIFEST_MEDIA_TYPES.join(","));
+    // anonymous access when there is no token
+    if !token.is_empty() {
+        req = req.bearer_auth(token);
+    }
+    let body = check_response(req.send().await?).await?.text().await?;
+    validate_manifest(&url, &body)?;
+    Ok(body)
+}
+
+// validate_manifest - check that 'data' (from 'url') is a manifest (or manifest
+// list / index) and not an error body or html page served with a success status
+pub fn validate_manifest(url: &str, data: &str) -> Result<(), MirrorError> {
+    let invalid = |why: String| MirrorError::Parse(format!("{} : {}", url, why));
+    let value: serde_json::Value = serde_json::from_str(data)
+        .map_err(|error| invalid(format!("manifest is not json: {}", error)))?;
+    if let Ok(body) = serde_json::from_value::<RegistryErrors>(value.clone()) {
+        if !body.errors.is_empty() {
+            return Err(MirrorError::Registry {
+                url: url.to_string(),
+                status: 200,
+                errors: body.errors,
+            });
+        }
+    }
+    if !value["schemaVersion"].is_i64() {
+        return Err(invalid("manifest has no schemaVersion".to_string()));
+    }
+    let content = ["layers", "fsLayers", "manifests"];
+    if !content.iter().any(|key| value[key].is_array()) {
+        return Err(invalid("manifest has no layers or manifests".to_string()));
+    }
+    Ok(())
+}
+
+// copy_image - copy a single image (following manifest lists) to the
+// 'dir' layout, manifests are stored by digest in 'dir/manifests' and
+// blobs are shared across all images in 'dir/blobs/sha256'
+// the mirrors from registries.conf are tried before the source
+// returns the digest of the top level manifest
+pub async fn copy_image(img_ref: ImageReference, dir: String) -> Result<String, MirrorError> {
+    let mut last_error = None;
+    for source in get_pull_sources(&img_ref).await? {
+        let scope = get_repository_scope(&source.img_ref, PULL);
+        let source_token = match get_token(source.img_ref.registry.clone(), scope).await {
+            Ok(token) => token,
+            Err(error) => {
+                log_warn(&format!("{} : {}", source.img_ref.registry, error));
+                last_error = Some(error);
+                continue;
+            }
+        };
+        match copy_image_from(source.img_ref.clone(), source_token, dir.clone()).await {
+            Ok(digest) => return Ok(digest),
+            Err(error) => {
+                if source.mirror {
+                    log_warn(&format!(
+                        "mirror {}/{} : {} trying next location",
+                        source.img_ref.registry, source.img_ref.name, error
+                    ));
+                }
+                last_error = Some(error);
+            }
+        }
+    }
+    Err(last_error.unwrap_or(MirrorError::Config("no pull source".to_string())))
+}
+
+// copy_image_from - copy an image from a single location (see copy_image)
+async fn copy_image_from(
+    img_ref: ImageReference,
+    token: String,
+    dir: String,
+) -> Result<String, MirrorError> {
+    let manifests_dir = dir.clone() + "/manifests/";
+    let blobs_dir = dir + "/blobs/sha256/";
+    fs::create_dir_all(&manifests_dir).context(format!("creating {}", manifests_dir))?;
+    fs::create_dir_all(&blobs_dir).context(format!("creating {}", blobs_dir))?;
+
+    let manifest_url = get_image_manifest_url(img_ref.clone());
+    let manifest = get_image_manifest(manifest_url, token.clone()).await?;
+    let digest = get_digest(manifest.as_bytes());
+    // a location answering a pull by digest with other content is not trusted
+    if img_ref.version.starts_with("sha256:") && digest != img_ref.version {
+        return Err(MirrorError::DigestMismatch {
+            name: format!("manifest of {}/{}", img_ref.namespace, img_ref.name),
+            expected: img_ref.version,
+            actual: digest,
+        });
+    }
+    let res = parse_json_manifest_v2(manifest.clone())?;
+
+    // for manifest lists we copy every referenced image manifest
+    let mut images = vec![(digest.clone(), manifest, res)];
+    if let Some(children) = images[0].2.manifests.clone() {
+        for child in children {
+            let mut child_ref = img_ref.clone();
+            child_ref.version = child.digest.clone();
+            let child_url = get_image_manifest_url(child_ref);
+            let child_manifest = get_image_manifest(child_url, token.clone()).await?;
+            let child_digest = get_digest(child_manifest.as_bytes());
+            if child_digest != child.digest {
+                return Err(MirrorError::DigestMismatch {
+                    name: format!("manifest of {}/{}", img_ref.namespace, img_ref.name),
+                    expected: child.digest,
+                    actual: child_digest,
+                });
+            }
+            let child_res = parse_json_manifest_v2(child_manifest.clone())?;
+            images.push((child.digest, child_manifest, child_res));
+        }
+    }
+
+    let mut layers = vec![];
+    for (manifest_digest, manifest, res) in images {
+        let hex = manifest_digest.split(':').nth(1).unwrap_or_default().to_string();
+        let file = manifests_dir.clone() + &hex + ".json";
+        fs::write(&file, manifest).context(format!("writing {}", file))?;
+        layers.extend(res.config);
+        layers.extend(res.layers.unwrap_or_default());
+    }
+    let blobs_url = get_blobs_url(img_ref);
+    let stats = get_blobs(blobs_url, token, layers, blobs_dir).await?;
+    add_transfer_stats(&stats);
+    Ok(digest)
+}
+
+// the blob downloads of the images copied since the last summary
+static TRANSFERS: OnceLock<Mutex<TransferStats>> = OnceLock::new();
+
+fn transfers() -> &'static Mutex<TransferStats> {
+    TRANSFERS.get_or_init(|| Mutex::new(TransferStats::default()))
+}
+
+// add_transfer_stats - count the downloads of one image in the run summary
+fn add_transfer_stats(stats: &TransferStats) {
+    let mut total = lock(transfers());
+    total.blobs += stats.blobs;
+    total.cached += stats.cached;
+    total.bytes += stats.bytes;
+    total.retries += stats.retries;
+    total.duration_ms += stats.duration_ms;
+}
+
+// log_transfer_stats - log (and reset) the summary of the blob downloads of
+// every image copied since the last summary
+pub fn log_transfer_stats() {
+    let stats = std::mem::take(&mut *lock(transfers()));
+    log_event(
+        LogLevel::Info,
+        &format!(
+            "transferred {} blobs ({}), {} cached, {} retries in {:.1}s",
+            stats.blobs,
+            format_bytes(stats.bytes),
+            stats.cached,
+            stats.retries,
+            stats.duration_ms as f64 / 1000.0
+        ),
+        LogFields {
+            bytes: Some(stats.bytes),
+            duration_ms: Some(stats.duration_ms),
+            ..Default::default()
+        },
+    );
+}
+
+// get_digest - calculate the sha256 digest of a manifest or blob
+pub fn get_digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("sha256:{}", hex::encode(hasher.finalize()))
+}
+
 // get each blob referred to by the vector in parallel 
 // set by the PARALLEL_REQUESTS value
-pub async fn get_blobs(url: String, token: String, layers: Vec<FsLayer>, dir: String) {
+// blobs that already exist in 'dir' are skipped, every blob is checked
+// against its digest before it is stored, transient failures are retried
+// progress is drawn on a terminal (the sizes in 'layers' seed the total)
+// and a summary is logged at the end
+pub async fn get_blobs(
+    url: String,
+    token: String,
+    layers: Vec<Layer>,
+    dir: String,
+) -> Result<TransferStats, MirrorError> {
     const PARALLEL_REQUESTS: usize = 8;
 
+    let start = Instant::now();
     let inner_dir = &dir;
-    let client = Client::new();
+    let client = get_registry_client(&url)?;
     let mut header_bearer: String = "Bearer ".to_owned();
     header_bearer.push_str(&token);
 
     // remove all duplicates in FsLayer
+    let mut stats = TransferStats::default();
     let mut images = Vec::new();
     let mut seen = HashSet::new();
     for img in layers {
-        if !seen.contains(&img.blob_sum) {
-            seen.insert(img.blob_sum.clone());
-            images.push(img.blob_sum);
+        if !seen.insert(img.digest.clone()) {
+            continue;
+        }
+        let exists = match img.digest.split(':').nth(1) {
+            Some(hex) => Path::new(&(dir.clone() + hex)).exists(),
+            None => false,
+        };
+        if exists {
+            stats.cached += 1;
+        } else {
+            let size = img.size.and_then(|size| u64::try_from(size).ok());
+            images.push((img.digest, size));
         }
     }
 
-    let fetches = stream::iter(images.into_iter().map(|blob| {
+    let progress = TransferProgress::new("downloading");
+    progress.add_total(images.iter().filter_map(|(_, size)| *size).sum());
+    let progress = &progress;
+    let fetches = stream::iter(images.into_iter().map(|(blob, size)| {
         let client = client.clone();
         let url = url.clone();
         let header_bearer = header_bearer.clone();
+        let token = token.clone();
         async move {
-            match client
-                .get(url + &blob)
-                .header("Authorization", header_bearer)
-                .send()
-                .await
-            {
-                Ok(resp) => match resp.bytes().await {
-                    Ok(bytes) => {
-                        let blob = blob.split(":").nth(1).unwrap();
-                        fs::write(inner_dir.to_owned() + &blob, bytes.clone())
-                            .expect("unable to write blob");
-                        let msg = format!("writing blob {}", blob);
-                        log_info(&msg);
+            let hex = blob.split(':').nth(1).unwrap_or_default().to_string();
+            let mut retries = 0;
+            loop {
+                let attempt = Instant::now();
+                let mut req = client.get(url.clone() + &blob);
+                if !token.is_empty() {
+                    req = req.header("Authorization", header_bearer.clone());
+                }
+                let file = inner_dir.to_owned() + &hex;
+                match get_blob_data(req, &blob, size, &file, progress).await {
+                    Ok(size) => {
+                        log_event(
+                            LogLevel::Info,
+                            &format!("writing blob {}", hex),
+                            LogFields {
+                                digest: Some(blob.clone()),
+                                bytes: Some(size),
+                                duration_ms: Some(attempt.elapsed().as_millis() as u64),
+                                ..Default::default()
+                            },
+                        );
+                        return Ok((size, retries));
                     }
-                    Err(_) => {
-                        let msg = format!("reading blob {}", &blob);
-                        log_error(&msg);
+                    Err(error) if error.is_transient() && retries < MAX_RETRIES => {
+                        retries += 1;
+                        log_warn(&format!(
+                            "blob {} : {} (retry {}/{})",
+                            hex, error, retries, MAX_RETRIES
+                        ));
+                        tokio::time::sleep(Duration::from_secs(retries)).await;
                     }
-                },
-                Err(_) => {
-                    let msg = format!("downloading blob {}", &blob);
-                    log_error(&msg);
+                    Err(error) => return Err(error),
                 }
             }
         }
     }))
     .buffer_unordered(PARALLEL_REQUESTS)
-    .collect::<Vec<()>>();
-    log_info("downloading blobs...");
-    fetches.await;
+    .collect::<Vec<Result<(u64, u64), MirrorError>>>();
+    let results = fetches.await;
+    for (size, retries) in results.iter().filter_map(|r| r.as_ref().ok()) {
+        stats.blobs += 1;
+        stats.bytes += size;
+        stats.retries += retries;
+    }
+    stats.duration_ms = start.elapsed().as_millis() as u64;
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    log_event(
+        LogLevel::Info,
+        &format!(
+            "downloaded {} blobs ({}), {} cached, {} failed, {} retries in {:.1}s",
+            stats.blobs,
+            format_bytes(stats.bytes),
+            stats.cached,
+            failed,
+            stats.retries,
+            stats.duration_ms as f64 / 1000.0
+        ),
+        LogFields {
+            bytes: Some(stats.bytes),
+            duration_ms: Some(stats.duration_ms),
+            ..Default::default()
+        },
+    );
+    let first = results.into_iter().find_map(|r| r.err());
+    match first {
+        Some(error) if failed > 1 => Err(MirrorError::Context {
+            context: format!("{} blobs failed, first error", failed),
+            source: Box::new(error),
+        }),
+        Some(error) => Err(error),
+        None => Ok(stats),
+    }
+}
+
+// number of times a blob download is retried after a transient error
+const MAX_RETRIES: u64 = 3;
+
+// get_blob_data - download a single blob of 'size' bytes (when known from the
+// manifest) to 'file', advancing its progress bar, the chunks are hashed as
+// they are written to 'file.partial' which is only renamed to 'file' once it
+// matches the digest, returns the number of bytes written
+async fn get_blob_data(
+    req: reqwest::RequestBuilder,
+    blob: &str,
+    size: Option<u64>,
+    file: &str,
+    progress: &TransferProgress,
+) -> Result<u64, MirrorError> {
+    let mut resp = check_response(req.send().await?).await?;
+    let hex = blob.split(':').nth(1).unwrap_or(blob);
+    let name = &hex[..hex.len().min(12)];
+    let mut bar = match size {
+        Some(size) => progress.start_known_blob(name, Some(size)),
+        None => progress.start_blob(name, resp.content_length()),
+    };
+    let partial = format!("{}.partial", file);
+    let written = async {
+        let mut out = tokio::fs::File::create(&partial)
+            .await
+            .context(format!("creating {}", partial))?;
+        let mut hasher = Sha256::new();
+        let mut written = 0;
+        while let Some(chunk) = resp.chunk().await? {
+            bar.inc(chunk.len() as u64);
+            hasher.update(&chunk);
+            out.write_all(&chunk)
+                .await
+                .context(format!("writing {}", partial))?;
+            written += chunk.len() as u64;
+        }
+        out.flush().await.context(format!("writing {}", partial))?;
+        let digest = format!("sha256:{}", hex::encode(hasher.finalize()));
+        if digest != blob {
+            return Err(MirrorError::DigestMismatch {
+                name: "blob".to_string(),
+                expected: blob.to_string(),
+                actual: digest,
+            });
+        }
+        Ok(written)
+    }
+    .await;
+    match written {
+        Ok(written) => {
+            fs::rename(&partial, file).context(format!("writing blob {}", file))?;
+            Ok(written)
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&partial);
+            bar.abort();
+            Err(error)
+        }
+    }
 }
 
-// untar layers in directory denoted by parameter 'dir'
-pub async fn untar_layers(dir: String) {
-    // change to the blobs/sha256 directory
-    let current_dir = env::current_dir().unwrap();
-    env::set_current_dir(&dir).expect("could not set current directory");
+// untar layers in directory denoted by parameter 'dir' into the 'cache'
+// directory next to it (cache/<first 6 chars of the digest>)
+pub async fn untar_layers(dir: String) -> Result<(), MirrorError> {
+    let cache = Path::new(&dir).join("../../cache");
     // read directory, iterate each file and untar
-    let paths = fs::read_dir(".").unwrap();
+    let paths = fs::read_dir(&dir)
+        .map_err(|why| MirrorError::Extraction(format!("reading {}: {}", dir, why)))?;
+    let progress = TransferProgress::new("extracting");
     for path in paths {
-        let entry = path.expect("could not resolve file entry");
-        let file = entry.path();
-        let tar_gz = File::open(file.clone()).expect("could not open file");
-        let tar = GzDecoder::new(tar_gz);
-        let mut archive = Archive::new(tar);
+        let file = path?.path();
+        let tar_gz = File::open(&file).map_err(|why| {
+            MirrorError::Extraction(format!("opening {}: {}", file.display(), why))
+        })?;
         // should always be a sha256 string
-        let tar_dir = file.into_os_string().into_string().unwrap();
-        log_info(&format!("untarring file {} ", &tar_dir[2..8]));
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let short = &name[..name.len().min(6)];
+        let len = tar_gz.metadata().ok().map(|m| m.len());
+        let reader = ProgressReader::new(tar_gz, progress.start_blob(short, len));
+        let mut archive = Archive::new(GzDecoder::new(reader));
+        log_info(&format!("untarring file {} ", short));
         // we are really interested in either the configs or release-images directories
-        match archive.unpack("../../cache/".to_string() + &tar_dir[2..8]) {
-            Ok(arch) => arch,
-            Err(error) => {
-                let msg = format!("skipping this error : {} ", &error.to_string());
-                log_warn(&msg);
-            }
-        };
+        if let Err(error) = archive.unpack(cache.join(short)) {
+            log_warn(&format!("skipping this error : {} ", error));
+        }
     }
-    env::set_current_dir(&current_dir).expect("could not set back to current directory");
+    Ok(())
 }
 
 // parse_image_index - best attempt to parse image index
 pub fn parse_image_index(image: String) -> ImageReference {
-    let mut img = image.split(":");
-    let index = img.nth(0).unwrap();
-    let mut img_ref = index.split("/");
-    let ver = img.nth(0).unwrap();
-    let ir = ImageReference {
-        registry: img_ref.nth(0).unwrap().to_string(),
-        namespace: img_ref.nth(0).unwrap().to_string(),
-        name: img_ref.nth(0).unwrap().to_string(),
-        version: ver.to_string(),
-    };
-    log_info(&format!("{:#?}", ir));
+    let ir = parse_image(image);
+    log_debug(&format!("{:#?}", ir));
     ir
 }
 
+// parse_image - parse an image reference in the form
+// registry/namespace/name:tag or registry/namespace/name@sha256:digest
+// nested namespaces are kept in the namespace field
+pub fn parse_image(image: String) -> ImageReference {
+    let (path, ver) = match image.split_once('@') {
+        Some((path, digest)) => (path.to_string(), digest.to_string()),
+        None => match image.rsplit_once(':') {
+            Some((path, tag)) if !tag.contains('/') => (path.to_string(), tag.to_string()),
+            _ => (image.clone(), "latest".to_string()),
+        },
+    };
+    let mut parts: Vec<&str> = path.split('/').collect();
+    let name = parts.pop().unwrap_or_default().to_string();
+    let registry = if parts.is_empty() {
+        String::new()
+    } else {
+        parts.remove(0).to_string()
+    };
+    ImageReference {
+        registry,
+        namespace: parts.join("/"),
+        name,
+        version: ver,
+    }
+}
+
 // contruct the manifest url
 pub fn get_image_manifest_url(image_ref: ImageReference) -> String {
     // return a string in the form of (example below)
     // "https://registry.redhat.io/v2/redhat/certified-operator-index/manifests/v4.12";
-    let mut url = String::from("https://");
+    let mut url = get_registry_scheme(&image_ref.registry) + "://";
     url.push_str(&image_ref.registry);
-    url.push_str(&"/v2/");
-    url.push_str(&image_ref.namespace);
-    url.push_str(&"/");
+    url.push_str("/v2/");
+    // two part references (registry/name) have no namespace
+    if !image_ref.namespace.is_empty() {
+        url.push_str(&image_ref.namespace);
+        url.push('/');
+    }
     url.push_str(&image_ref.name);
-    url.push_str(&"/");
-    url.push_str(&"manifests/");
+    url.push('/');
+    url.push_str("manifests/");
     url.push_str(&image_ref.version);
     url
 }
@@ -152,13 +494,151 @@ pub fn get_image_manifest_url(image_ref: ImageReference) -> String {
 pub fn get_blobs_url(image_ref: ImageReference) -> String {
     // return a string in the form of (example below)
     // "https://registry.redhat.io/v2/redhat/certified-operator-index/blobs/";
-    let mut url = String::from("https://");
+    let mut url = get_registry_scheme(&image_ref.registry) + "://";
     url.push_str(&image_ref.registry);
-    url.push_str(&"/v2/");
-    url.push_str(&image_ref.namespace);
-    url.push_str("/");
+    url.push_str("/v2/");
+    if !image_ref.namespace.is_empty() {
+        url.push_str(&image_ref.namespace);
+        url.push('/');
+    }
     url.push_str(&image_ref.name);
-    url.push_str(&"/");
-    url.push_str(&"blobs/");
+    url.push('/');
+    url.push_str("blobs/");
     url
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn urls_without_namespace() {
+        let img_ref = parse_image("quay.io/foo:tag".to_string());
+        assert_eq!(img_ref.namespace, "");
+        assert!(get_image_manifest_url(img_ref.clone()).ends_with("quay.io/v2/foo/manifests/tag"));
+        assert!(get_blobs_url(img_ref).ends_with("quay.io/v2/foo/blobs/"));
+    }
+
+    #[test]
+    fn urls_with_nested_namespace() {
+        let img_ref = parse_image("registry.example.com/a/b/foo@sha256:abc".to_string());
+        assert_eq!(img_ref.namespace, "a/b");
+        assert_eq!(img_ref.version, "sha256:abc");
+        assert!(get_image_manifest_url(img_ref.clone())
+            .ends_with("registry.example.com/v2/a/b/foo/manifests/sha256:abc"));
+        assert!(get_blobs_url(img_ref).ends_with("registry.example.com/v2/a/b/foo/blobs/"));
+    }
+
+    #[test]
+    fn manifest_validation() {
+        let url = "https://quay.io/v2/a/manifests/v1";
+        for manifest in [
+            r#"{"schemaVersion":2,"layers":[]}"#,
+            r#"{"schemaVersion":2,"manifests":[]}"#,
+            r#"{"schemaVersion":1,"fsLayers":[]}"#,
+        ] {
+            assert!(validate_manifest(url, manifest).is_ok(), "{}", manifest);
+        }
+        // an error body served with a success status keeps its code
+        let error = validate_manifest(
+            url,
+            r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown"}]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(error.registry_code(), Some(RegistryErrorCode::ManifestUnknown));
+        assert_eq!(error.exit_code(), 4);
+        for body in [
+            "<html>login</html>",
+            "",
+            r#"{"layers":[]}"#,
+            r#"{"schemaVersion":2}"#,
+        ] {
+            let error = validate_manifest(url, body).unwrap_err();
+            assert!(matches!(error, MirrorError::Parse(_)), "{:?} {}", body, error);
+        }
+    }
+
+    #[tokio::test]
+    async fn manifest_not_matching_the_pulled_digest() {
+        // the registry answers every request with the same manifest
+        let manifest = r#"{"schemaVersion":2,"layers":[]}"#;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    manifest.len(),
+                    manifest
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        set_insecure_registry(&registry).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("copy-digest-{}", std::process::id()));
+        let expected = get_digest(b"another manifest");
+        let img_ref = parse_image(format!("{}/test/app@{}", registry, expected));
+        let error = copy_image_from(img_ref, String::new(), dir.to_string_lossy().to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 6, "{}", error);
+        assert!(error.to_string().contains(&get_digest(manifest.as_bytes())), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn blobs_are_stored_once_verified() {
+        let data = vec![3u8; 50_000];
+        let good = get_digest(&data);
+        let bad = get_digest(b"expected content");
+        // every blob request is answered with 'data'
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry = listener.local_addr().unwrap().to_string();
+        let body = data.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        set_insecure_registry(&registry).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("copy-blobs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let blobs_dir = dir.to_string_lossy().to_string() + "/";
+        let url = get_blobs_url(parse_image(format!("{}/test/app:v1", registry)));
+        let layer = |digest: &str| Layer {
+            digest: digest.to_string(),
+            size: Some(data.len() as i64),
+            ..Default::default()
+        };
+        let stats = get_blobs(url.clone(), String::new(), vec![layer(&good)], blobs_dir.clone())
+            .await
+            .unwrap();
+        assert_eq!((stats.blobs, stats.bytes, stats.cached), (1, data.len() as u64, 0));
+        assert_eq!(fs::read(dir.join(&good[7..])).unwrap(), data);
+
+        // a blob not matching its digest is neither stored nor left behind
+        let error = get_blobs(url, String::new(), vec![layer(&good), layer(&bad)], blobs_dir)
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 6, "{}", error);
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec![good[7..].to_string()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,3 @@
 pub mod copy;
+pub mod source;
+pub mod push;
@@ -0,0 +1,226 @@
+use reqwest::{Client, StatusCode};
+use std::fs;
+use std::time::Instant;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::image::copy::*;
+use crate::log::logging::*;
+use crate::registry::client::*;
+use crate::registry::config::*;
+
+// push_image - push an image from the 'dir' layout (see copy_image) to the
+// 'destination' reference, for manifest lists every referenced image is
+// pushed (by digest) before the list itself, the token is requested with
+// pull and push access to the destination repository
+pub async fn push_image(
+    dir: String,
+    digest: String,
+    destination: String,
+) -> Result<(), MirrorError> {
+    let img_ref = parse_image(destination);
+    if is_insecure_registry(&img_ref.registry) {
+        set_insecure_registry(&img_ref.registry).await?;
+    }
+    let token = get_token(img_ref.registry.clone(), get_repository_scope(&img_ref, PUSH)).await?;
+    let client = get_registry_client(&get_blobs_url(img_ref.clone()))?;
+    let manifests_dir = dir.clone() + "/manifests/";
+    let blobs_dir = dir + "/blobs/sha256/";
+
+    let read_manifest = |digest: &str| -> Result<(String, ManifestV2), MirrorError> {
+        let hex = digest.split(':').nth(1).unwrap_or_default();
+        let file = manifests_dir.clone() + hex + ".json";
+        let manifest = fs::read_to_string(&file).context(format!("reading {}", file))?;
+        let res = parse_json_manifest_v2(manifest.clone())?;
+        Ok((manifest, res))
+    };
+
+    let (manifest, res) = read_manifest(&digest)?;
+    let mut images = vec![];
+    for child in res.manifests.clone().unwrap_or_default() {
+        let (child_manifest, child_res) = read_manifest(&child.digest)?;
+        images.push((child.digest, child_manifest, child_res));
+    }
+    images.push((img_ref.version.clone(), manifest, res));
+
+    for (reference, manifest, res) in images {
+        let mut blobs = vec![];
+        if let Some(config) = res.config.clone() {
+            blobs.push(config.digest);
+        }
+        for layer in res.layers.clone().unwrap_or_default() {
+            blobs.push(layer.digest);
+        }
+        for blob in blobs {
+            push_blob(&client, &img_ref, &token, &blobs_dir, &blob).await?;
+        }
+        let media_type = res
+            .media_type
+            .unwrap_or("application/vnd.oci.image.manifest.v1+json".to_string());
+        let mut url_ref = img_ref.clone();
+        url_ref.version = reference;
+        let mut req = client
+            .put(get_image_manifest_url(url_ref))
+            .header("Content-Type", media_type)
+            .body(manifest);
+        if !token.is_empty() {
+            req = req.bearer_auth(&token);
+        }
+        check_response(req.send().await?).await?;
+    }
+    Ok(())
+}
+
+// push_blob - upload a single blob (monolithic upload), blobs that already
+// exist in the destination repository are skipped
+async fn push_blob(
+    client: &Client,
+    img_ref: &ImageReference,
+    token: &str,
+    blobs_dir: &str,
+    digest: &str,
+) -> Result<(), MirrorError> {
+    let blobs_url = get_blobs_url(img_ref.clone());
+    let auth = |req: reqwest::RequestBuilder| {
+        if token.is_empty() {
+            req
+        } else {
+            req.bearer_auth(token)
+        }
+    };
+    let resp = auth(client.head(blobs_url.clone() + digest)).send().await?;
+    if resp.status() == StatusCode::OK {
+        return Ok(());
+    }
+
+    let resp = auth(client.post(blobs_url.clone() + "uploads/")).send().await?;
+    let resp = check_response(resp).await?;
+    let location = resp
+        .headers()
+        .get("Location")
+        .and_then(|l| l.to_str().ok())
+        .ok_or(MirrorError::Other(format!(
+            "{}uploads/ returned no upload location",
+            blobs_url
+        )))?
+        .to_string();
+    // the location can be relative to the registry
+    let mut url = if location.starts_with('/') {
+        format!(
+            "{}://{}{}",
+            get_registry_scheme(&img_ref.registry),
+            img_ref.registry,
+            location
+        )
+    } else {
+        location
+    };
+    url.push(if url.contains('?') { '&' } else { '?' });
+    url.push_str("digest=");
+    url.push_str(digest);
+
+    // the blob is streamed from the file, layers can be larger than the memory available
+    let hex = digest.split(':').nth(1).unwrap_or_default();
+    let file = blobs_dir.to_string() + hex;
+    let data = tokio::fs::File::open(&file)
+        .await
+        .context(format!("reading {}", file))?;
+    let size = data
+        .metadata()
+        .await
+        .context(format!("reading {}", file))?
+        .len();
+    let start = Instant::now();
+    let resp = auth(client.put(url))
+        .header("Content-Type", "application/octet-stream")
+        .header("Content-Length", size)
+        .body(reqwest::Body::from(data))
+        .send()
+        .await?;
+    check_response(resp).await?;
+    log_event(
+        LogLevel::Info,
+        &format!("pushed blob {}", hex),
+        LogFields {
+            digest: Some(digest.to_string()),
+            bytes: Some(size),
+            duration_ms: Some(start.elapsed().as_millis() as u64),
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    // serve - a registry answering each request by method, the body of the
+    // blob upload (PUT) is sent back on the channel
+    fn serve(listener: TcpListener, uploads: mpsc::Sender<(Option<u64>, Vec<u8>)>) {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut stream = stream;
+            loop {
+                let mut request = String::new();
+                if reader.read_line(&mut request).unwrap_or(0) == 0 {
+                    break;
+                }
+                let mut length = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse::<u64>().ok();
+                        }
+                    }
+                }
+                let mut body = vec![0; length.unwrap_or(0) as usize];
+                reader.read_exact(&mut body).unwrap();
+                let response = match request.split(' ').next().unwrap_or_default() {
+                    "POST" => "HTTP/1.1 202 Accepted\r\nLocation: /v2/test/app/blobs/uploads/1\r\nContent-Length: 0\r\n\r\n",
+                    "PUT" => {
+                        uploads.send((length, body)).unwrap();
+                        "HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n"
+                    }
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn push_blob_from_file() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry = listener.local_addr().unwrap().to_string();
+        let (sender, uploads) = mpsc::channel();
+        std::thread::spawn(move || serve(listener, sender));
+        set_insecure_registry(&registry).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("push-blob-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data = vec![7u8; 100_000];
+        let digest = get_digest(&data);
+        fs::write(dir.join(&digest[7..]), &data).unwrap();
+
+        let img_ref = parse_image(format!("{}/test/app:v1", registry));
+        let client = get_registry_client(&get_blobs_url(img_ref.clone())).unwrap();
+        let blobs_dir = dir.to_string_lossy().to_string() + "/";
+        push_blob(&client, &img_ref, "", &blobs_dir, &digest)
+            .await
+            .unwrap();
+        let (length, body) = uploads.recv().unwrap();
+        assert_eq!(length, Some(data.len() as u64));
+        assert_eq!(body, data);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
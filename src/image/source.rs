@@ -0,0 +1,412 @@
+use flate2::read::GzDecoder;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use tar::Archive;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::catalog::index::*;
+use crate::log::logging::*;
+use crate::log::progress::*;
+use crate::manifests::catalogs::*;
+
+// annotation used in oci layouts to tag a manifest in index.json
+const OCI_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+// get_source_catalog - resolve a local catalog source to its 'configs' directory
+// nothing is fetched from a registry, the supported sources are
+//   oci:<path>[:<tag>]          oci image layout (e.g. skopeo copy ... oci:<path>)
+//   docker-archive:<file.tar>   docker save tarball
+//   dir:<path>                  an already extracted 'configs' directory
+pub async fn get_source_catalog(source: String) -> Result<String, MirrorError> {
+    let (transport, path) = source.split_once(':').ok_or(MirrorError::Config(format!(
+        "invalid source {} (expected oci:, docker-archive: or dir:)",
+        source
+    )))?;
+    let cache = match transport {
+        "dir" => {
+            let configs = Path::new(path).join("configs");
+            if configs.is_dir() {
+                return Ok(configs.to_string_lossy().to_string());
+            } else if Path::new(path).is_dir() {
+                return Ok(path.to_string());
+            }
+            return Err(MirrorError::Extraction(format!("directory {} not found", path)));
+        }
+        "oci" => unpack_oci_layout(path)?,
+        "docker-archive" => unpack_docker_archive(path)?,
+        _ => {
+            return Err(MirrorError::Config(format!(
+                "unsupported source transport {}",
+                transport
+            )))
+        }
+    };
+    let dir = find_dir(cache, "configs".to_string()).await;
+    if dir.is_empty() {
+        return Err(MirrorError::Catalog(format!(
+            "configs directory not found in {}",
+            source
+        )));
+    }
+    Ok(dir)
+}
+
+// is_local_source - true if 'catalog' uses one of the local transports
+pub fn is_local_source(catalog: &str) -> bool {
+    ["oci:", "docker-archive:", "dir:"]
+        .iter()
+        .any(|t| catalog.starts_with(t))
+}
+
+// get_source_cache_dir - cache directory for a local source
+// <working dir>/<source file name>/<short manifest digest>/cache
+fn get_source_cache_dir(path: &str, digest: &str) -> String {
+    let name = Path::new(path)
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or("local".to_string());
+    let hex = digest.trim_start_matches("sha256:");
+    format!(
+        "{}/{}/{}/cache",
+        get_working_dir(),
+        name,
+        &hex[..hex.len().min(12)]
+    )
+}
+
+// unpack_oci_layout - untar the layers of the manifest in an oci layout
+// 'path' can end with ':<tag>' to select a manifest by its ref name annotation
+fn unpack_oci_layout(path: &str) -> Result<String, MirrorError> {
+    let (layout, tag) = match path.rsplit_once(':') {
+        Some((layout, tag)) if !tag.contains('/') => (layout, Some(tag)),
+        _ => (path, None),
+    };
+    let blob = |digest: &str| {
+        Path::new(layout)
+            .join("blobs/sha256")
+            .join(digest.trim_start_matches("sha256:"))
+    };
+    let data = fs::read_to_string(Path::new(layout).join("index.json")).map_err(|why| {
+        MirrorError::Extraction(format!("couldn't open {}/index.json: {}", layout, why))
+    })?;
+    let index: ManifestV2 = serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Extraction(format!("{}/index.json: {}", layout, why)))?;
+    let manifests = index.manifests.unwrap_or_default();
+    let selected = match tag {
+        Some(tag) => manifests.into_iter().find(|m| {
+            m.annotations
+                .as_ref()
+                .and_then(|a| a.get(OCI_REF_NAME))
+                .map(|name| name == tag)
+                .unwrap_or(false)
+        }),
+        None => manifests.into_iter().next(),
+    };
+    let mut digest = selected
+        .ok_or(MirrorError::Extraction(format!(
+            "no matching manifest in {}/index.json",
+            layout
+        )))?
+        .digest;
+    // follow nested indexes to the first image manifest
+    let manifest = loop {
+        let data = fs::read_to_string(blob(&digest)).map_err(|why| {
+            MirrorError::Extraction(format!("couldn't open {}: {}", blob(&digest).display(), why))
+        })?;
+        let manifest: ManifestV2 = serde_json::from_str(&data)
+            .map_err(|why| MirrorError::Extraction(format!("manifest {}: {}", digest, why)))?;
+        match manifest.manifests.as_ref().and_then(|m| m.first()) {
+            Some(child) => digest = child.digest.clone(),
+            None => break manifest,
+        }
+    };
+
+    let cache = get_source_cache_dir(layout, &digest);
+    if Path::new(&cache).exists() {
+        log_info("cache exists nothing to do");
+        return Ok(cache);
+    }
+    extract_to_cache(&cache, |dir| {
+        let progress = TransferProgress::new("extracting");
+        for layer in manifest.layers.unwrap_or_default() {
+            let file = File::open(blob(&layer.digest)).map_err(|why| {
+                MirrorError::Extraction(format!("couldn't open layer {}: {}", layer.digest, why))
+            })?;
+            let len = file.metadata().ok().map(|m| m.len());
+            unpack_layer(file, len, &progress, dir, &layer.digest)?;
+        }
+        Ok(())
+    })?;
+    log_info("completed untar of layers");
+    Ok(cache)
+}
+
+// unpack_docker_archive - untar the layers of the (first) image in a
+// docker-archive, the archive is read twice to avoid extracting it to disk
+fn unpack_docker_archive(file: &str) -> Result<String, MirrorError> {
+    let archive_error = |why: std::io::Error| MirrorError::Extraction(format!("{}: {}", file, why));
+    let mut manifests: Option<Vec<DockerArchiveManifest>> = None;
+    let mut archive = Archive::new(File::open(file).map_err(archive_error)?);
+    for entry in archive.entries().map_err(archive_error)? {
+        let mut entry = entry.map_err(archive_error)?;
+        if entry.path().map_err(archive_error)?.to_string_lossy() == "manifest.json" {
+            let mut data = String::new();
+            entry.read_to_string(&mut data).map_err(archive_error)?;
+            manifests = Some(serde_json::from_str(&data).map_err(|why| {
+                MirrorError::Extraction(format!("{} manifest.json: {}", file, why))
+            })?);
+            break;
+        }
+    }
+    let manifest = manifests
+        .and_then(|m| m.into_iter().next())
+        .ok_or(MirrorError::Extraction(format!(
+            "no manifest.json found in {}",
+            file
+        )))?;
+
+    // the config file name is its digest (either <hex>.json or blobs/sha256/<hex>)
+    let config = Path::new(&manifest.config)
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let cache = get_source_cache_dir(file, &config);
+    if Path::new(&cache).exists() {
+        log_info("cache exists nothing to do");
+        return Ok(cache);
+    }
+    extract_to_cache(&cache, |dir| {
+        let progress = TransferProgress::new("extracting");
+        let mut unpacked = vec![];
+        let mut archive = Archive::new(File::open(file).map_err(archive_error)?);
+        for entry in archive.entries().map_err(archive_error)? {
+            let entry = entry.map_err(archive_error)?;
+            let name = entry.path().map_err(archive_error)?.to_string_lossy().to_string();
+            if manifest.layers.contains(&name) && !unpacked.contains(&name) {
+                // layers are either <hex>/layer.tar or blobs/sha256/<hex>
+                let id = name
+                    .trim_end_matches("/layer.tar")
+                    .replace("blobs/sha256/", "");
+                let len = entry.header().size().ok();
+                unpack_layer(entry, len, &progress, dir, &id)?;
+                unpacked.push(name);
+            }
+        }
+        match manifest.layers.iter().find(|layer| !unpacked.contains(layer)) {
+            Some(layer) => Err(MirrorError::Extraction(format!(
+                "layer {} not found in {}",
+                layer, file
+            ))),
+            None => Ok(()),
+        }
+    })?;
+    log_info("completed untar of layers");
+    Ok(cache)
+}
+
+// extract_to_cache - run 'extract' into a temporary directory next to 'cache'
+// that is renamed to 'cache' once every layer is unpacked, a failed
+// extraction leaves no (partial) cache behind to be reused by the next run
+fn extract_to_cache(
+    cache: &str,
+    extract: impl FnOnce(&str) -> Result<(), MirrorError>,
+) -> Result<(), MirrorError> {
+    let partial = format!("{}.partial", cache);
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir_all(&partial).context(format!("creating {}", partial))?;
+    match extract(&partial) {
+        Ok(()) => fs::rename(&partial, cache).context(format!("creating {}", cache)),
+        Err(error) => {
+            let _ = fs::remove_dir_all(&partial);
+            Err(error)
+        }
+    }
+}
+
+// unpack_layer - untar a (gzipped or plain) layer into cache/<short digest>
+// using the same layout as untar_layers, 'len' bytes are read from 'reader'
+// a layer that can't be unpacked is an Extraction error
+fn unpack_layer(
+    reader: impl Read,
+    len: Option<u64>,
+    progress: &TransferProgress,
+    cache: &str,
+    digest: &str,
+) -> Result<(), MirrorError> {
+    let hex = digest.trim_start_matches("sha256:");
+    let short = &hex[..hex.len().min(6)];
+    let dest = format!("{}/{}", cache, short);
+    log_info(&format!("untarring file {} ", short));
+    let reader = ProgressReader::new(reader, progress.start_blob(short, len));
+    let mut reader = BufReader::new(reader);
+    let gzipped = reader
+        .fill_buf()
+        .map_err(|why| MirrorError::Extraction(format!("reading layer {}: {}", short, why)))?
+        .starts_with(&[0x1f, 0x8b]);
+    let res = if gzipped {
+        Archive::new(GzDecoder::new(reader)).unpack(&dest)
+    } else {
+        Archive::new(reader).unpack(&dest)
+    };
+    res.map_err(|why| MirrorError::Extraction(format!("unpacking layer {}: {}", digest, why)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::copy::get_digest;
+    use std::path::PathBuf;
+    use tar::{Builder, Header};
+
+    // test_dir - an empty temp directory, the caches go below the working dir
+    fn test_dir(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("source-{}", std::process::id()));
+        set_working_dir(root.join("working-dir").to_string_lossy().to_string());
+        let dir = root.join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // tar_of - a tar stream holding 'files' (path, content)
+    fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+        for (path, data) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    // package - a layer with the catalog of package 'name'
+    fn package(name: &str) -> Vec<u8> {
+        let data = format!(r#"{{"schema":"olm.package","name":"{}"}}"#, name);
+        tar_of(&[(&format!("configs/{}/catalog.json", name), data.as_bytes())])
+    }
+
+    // oci_layout - an oci layout with an image per (tag, layer), returns the
+    // manifest digests
+    fn oci_layout(dir: &Path, images: &[(&str, Vec<u8>)]) -> Vec<String> {
+        let blobs = dir.join("blobs/sha256");
+        fs::create_dir_all(&blobs).unwrap();
+        let write_blob = |data: &[u8]| {
+            let digest = get_digest(data);
+            fs::write(blobs.join(&digest[7..]), data).unwrap();
+            digest
+        };
+        let mut manifests = vec![];
+        let mut digests = vec![];
+        for (tag, layer) in images {
+            let manifest = serde_json::json!({
+                "schemaVersion": 2,
+                "layers": [{ "digest": write_blob(layer), "size": layer.len() }],
+            });
+            let digest = write_blob(manifest.to_string().as_bytes());
+            digests.push(digest.clone());
+            manifests.push(serde_json::json!({
+                "digest": digest,
+                "annotations": { OCI_REF_NAME: tag },
+            }));
+        }
+        let index = serde_json::json!({ "schemaVersion": 2, "manifests": manifests });
+        fs::write(dir.join("index.json"), index.to_string()).unwrap();
+        digests
+    }
+
+    // packages - the package directories in a 'configs' directory
+    fn packages(configs: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(configs)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn dir_source() {
+        let dir = test_dir("dir-source");
+        let source = format!("dir:{}", dir.display());
+        assert_eq!(get_source_catalog(source.clone()).await.unwrap(), dir.to_string_lossy());
+        fs::create_dir_all(dir.join("configs")).unwrap();
+        assert_eq!(
+            get_source_catalog(source).await.unwrap(),
+            dir.join("configs").to_string_lossy()
+        );
+        let error = get_source_catalog(format!("dir:{}", dir.join("missing").display()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 7, "{}", error);
+        let error = get_source_catalog("ftp:/tmp/index".to_string()).await.unwrap_err();
+        assert_eq!(error.exit_code(), 2, "{}", error);
+    }
+
+    #[tokio::test]
+    async fn oci_tag_selection() {
+        let dir = test_dir("oci-index");
+        oci_layout(&dir, &[("v1", package("first")), ("v2", package("second"))]);
+        let source = |tag: &str| format!("oci:{}{}", dir.display(), tag);
+        let configs = get_source_catalog(source(":v2")).await.unwrap();
+        assert_eq!(packages(&configs), vec!["second"]);
+        // without a tag the first manifest is used
+        let configs = get_source_catalog(source("")).await.unwrap();
+        assert_eq!(packages(&configs), vec!["first"]);
+        let error = get_source_catalog(source(":v3")).await.unwrap_err();
+        assert_eq!(error.exit_code(), 7, "{}", error);
+    }
+
+    #[tokio::test]
+    async fn oci_layer_that_is_not_a_tar() {
+        let dir = test_dir("oci-broken");
+        let digests = oci_layout(&dir, &[("v1", vec![0x1f, 0x8b, 0, 1, 2, 3])]);
+        let error = get_source_catalog(format!("oci:{}:v1", dir.display()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 7, "{}", error);
+        // nothing is cached for the next run
+        let cache = get_source_cache_dir(&dir.to_string_lossy(), &digests[0]);
+        assert!(!Path::new(&cache).exists());
+        assert!(!Path::new(&format!("{}.partial", cache)).exists());
+    }
+
+    #[tokio::test]
+    async fn docker_archive_layers() {
+        let dir = test_dir("docker-archive");
+        let manifest = |layers: &[&str]| {
+            serde_json::json!([{ "Config": "c0ffee.json", "Layers": layers }]).to_string()
+        };
+        let archive = |name: &str, manifest: String| {
+            let file = dir.join(name);
+            let data = tar_of(&[
+                ("manifest.json", manifest.as_bytes()),
+                ("aaa/layer.tar", &package("listed")),
+                ("bbb/layer.tar", &tar_of(&[("etc/os-release", b"ID=test")])),
+                ("ccc/layer.tar", &package("unlisted")),
+            ]);
+            fs::write(&file, data).unwrap();
+            format!("docker-archive:{}", file.display())
+        };
+
+        // only the layers of the manifest are unpacked
+        let source = archive("index.tar", manifest(&["aaa/layer.tar", "bbb/layer.tar"]));
+        let configs = get_source_catalog(source).await.unwrap();
+        assert_eq!(packages(&configs), vec!["listed"]);
+        let cache = Path::new(&configs).parent().unwrap().parent().unwrap();
+        assert_eq!(packages(&cache.to_string_lossy()), vec!["aaa", "bbb"]);
+
+        // a layer missing from the archive fails the extraction
+        let source = archive("missing.tar", manifest(&["aaa/layer.tar", "ddd/layer.tar"]));
+        let error = get_source_catalog(source).await.unwrap_err();
+        assert_eq!(error.exit_code(), 7, "{}", error);
+        assert!(error.to_string().contains("ddd/layer.tar"), "{}", error);
+        let cache = get_source_cache_dir(&dir.join("missing.tar").to_string_lossy(), "c0ffee");
+        assert!(!Path::new(&cache).exists());
+        assert!(!Path::new(&format!("{}.partial", cache)).exists());
+    }
+}
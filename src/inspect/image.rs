@@ -0,0 +1,521 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::image::copy::*;
+use crate::log::logging::*;
+use crate::registry::api::*;
+use crate::registry::config::*;
+
+// read_from_sources - run 'read' with a pull token against each pull source of
+// 'img_ref' (registries.conf mirrors first, a blocked registry is a Config
+// error), the result of the first location that answers is returned
+async fn read_from_sources<T, F, Fut>(img_ref: &ImageReference, read: F) -> Result<T, MirrorError>
+where
+    F: Fn(ImageReference, String) -> Fut,
+    Fut: Future<Output = Result<T, MirrorError>>,
+{
+    let mut last_error = None;
+    for source in get_pull_sources(img_ref).await? {
+        let scope = get_repository_scope(&source.img_ref, PULL);
+        let result = match get_token(source.img_ref.registry.clone(), scope).await {
+            Ok(token) => read(source.img_ref.clone(), token).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                if source.mirror {
+                    log_warn(&format!(
+                        "mirror {}/{} : {} trying next location",
+                        source.img_ref.registry, source.img_ref.name, error
+                    ));
+                }
+                last_error = Some(error);
+            }
+        }
+    }
+    Err(last_error.unwrap_or(MirrorError::Config("no pull source".to_string())))
+}
+
+// list_tags - the tags of the repository in 'image' in version order,
+// 'filter' (when not 'all') keeps the tags starting with it
+pub async fn list_tags(image: String, filter: String) -> Result<Vec<String>, MirrorError> {
+    let img_ref = parse_image(image);
+    let tags = read_from_sources(&img_ref, |source, token| async move {
+        get_tags(&source, &token).await
+    })
+    .await?;
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .filter(|tag| filter == "all" || tag.starts_with(&filter))
+        .collect();
+    tags.sort_by(|a, b| compare_versions(a, b));
+    Ok(tags)
+}
+
+// list_repositories - the repositories of 'registry', 'filter' (when not
+// 'all') keeps the repositories containing it
+pub async fn list_repositories(
+    registry: String,
+    filter: String,
+) -> Result<Vec<String>, MirrorError> {
+    // the registry without a repository ('registry/' has an empty name), mirrors
+    // hold other repositories so only the (possibly rewritten) location is listed
+    let img_ref = parse_image(registry + "/");
+    let source = get_pull_sources(&img_ref)
+        .await?
+        .into_iter()
+        .find(|source| !source.mirror)
+        .ok_or(MirrorError::Config("no pull source".to_string()))?;
+    let registry = source.img_ref.registry;
+    let token = get_token(registry.clone(), CATALOG_SCOPE.to_string()).await?;
+    let mut repositories: Vec<String> = get_repositories(&registry, &token)
+        .await?
+        .into_iter()
+        .filter(|repo| filter == "all" || repo.contains(&filter))
+        .collect();
+    repositories.sort();
+    Ok(repositories)
+}
+
+// compare_versions - natural order, runs of digits are compared as numbers
+// so that v4.9 sorts before v4.10
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let chunks = |s: &str| {
+        let mut chunks: Vec<(bool, String)> = vec![];
+        for c in s.chars() {
+            let digit = c.is_ascii_digit();
+            match chunks.last_mut() {
+                Some((d, chunk)) if *d == digit => chunk.push(c),
+                _ => chunks.push((digit, c.to_string())),
+            }
+        }
+        chunks
+    };
+    for (x, y) in chunks(a).iter().zip(chunks(b).iter()) {
+        let ord = match (x.0 && y.0, x.1.parse::<u64>(), y.1.parse::<u64>()) {
+            (true, Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.1.cmp(&y.1),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.len().cmp(&b.len()).then(a.cmp(b))
+}
+
+// print_list - print tags or repositories (one per line, json or yaml)
+pub fn print_list(items: &[String], output: Option<OutputFormat>) -> Result<(), MirrorError> {
+    match output {
+        Some(OutputFormat::Json) => println!("{}", serde_json::to_string_pretty(items)?),
+        Some(OutputFormat::Yaml) => print!("{}", serde_yaml::to_string(items)?),
+        None => {
+            for item in items.iter() {
+                println!("{}", item);
+            }
+        }
+    }
+    Ok(())
+}
+
+// default platform picked from manifest lists / indexes
+const DEFAULT_PLATFORM: &str = "linux/amd64";
+
+// inspect_image - summary of 'image' without pulling its layers, the manifest
+// (following manifest lists to the linux/amd64 image or the first one), the
+// config blob (labels, architecture, creation date, history) and the manifests
+// referring to it (signatures, sboms, attestations)
+pub async fn inspect_image(image: String) -> Result<InspectResult, MirrorError> {
+    let img_ref = parse_image(image.clone());
+    read_from_sources(&img_ref, |source, token| {
+        inspect_image_from(source, image.clone(), token)
+    })
+    .await
+}
+
+// inspect_image_from - inspect the image at a single location (see inspect_image)
+async fn inspect_image_from(
+    mut img_ref: ImageReference,
+    image: String,
+    token: String,
+) -> Result<InspectResult, MirrorError> {
+    let descriptor = head_manifest(&img_ref, &token).await?;
+    let referrers = get_referrers(&img_ref, &descriptor.digest, &token).await?;
+    let mut res = InspectResult {
+        name: image,
+        digest: descriptor.digest,
+        media_type: descriptor.media_type,
+        size: descriptor.size,
+        platforms: vec![],
+        architecture: String::new(),
+        os: String::new(),
+        created: String::new(),
+        labels: BTreeMap::new(),
+        env: vec![],
+        entrypoint: vec![],
+        cmd: vec![],
+        layers: vec![],
+        history: vec![],
+        referrers,
+    };
+
+    let mut data = get_manifest_data(&img_ref, &token).await?;
+    let mut manifest = parse_json_manifest_v2(data.clone())?;
+    if let Some(children) = manifest.manifests.clone() {
+        res.platforms = children
+            .iter()
+            .filter_map(|m| m.platform.as_ref().map(get_platform_name))
+            .collect();
+        let child = children
+            .iter()
+            .find(|m| {
+                m.platform.as_ref().map(get_platform_name).as_deref() == Some(DEFAULT_PLATFORM)
+            })
+            .or(children.first())
+            .ok_or(MirrorError::Parse(format!("{} is an empty manifest list", res.name)))?;
+        log_info(&format!(
+            "using {} from the manifest list",
+            child
+                .platform
+                .as_ref()
+                .map(get_platform_name)
+                .unwrap_or(child.digest.clone())
+        ));
+        img_ref.version = child.digest.clone();
+        data = get_manifest_data(&img_ref, &token).await?;
+        manifest = parse_json_manifest_v2(data.clone())?;
+    }
+
+    let config = match manifest.schema_version {
+        // schema 1, the config and history are in the v1Compatibility entries
+        1 => {
+            let v1 = parse_json_manifest(data)?;
+            res.layers = v1
+                .fs_layers
+                .iter()
+                .rev()
+                .map(|l| Layer {
+                    digest: l.blob_sum.clone(),
+                    ..Default::default()
+                })
+                .collect();
+            get_v1_config(&v1)?
+        }
+        _ => {
+            res.layers = manifest.layers.clone().unwrap_or_default();
+            let digest = manifest
+                .config
+                .ok_or(MirrorError::Parse(format!("manifest of {} has no config", res.name)))?
+                .digest;
+            serde_json::from_slice(&get_blob(&img_ref, &digest, &token).await?)
+                .map_err(|why| MirrorError::Parse(format!("image config {}: {}", digest, why)))?
+        }
+    };
+    res.architecture = config.architecture;
+    res.os = config.os;
+    res.created = config.created.unwrap_or_default();
+    res.history = config.history.unwrap_or_default();
+    let container = config.config.unwrap_or_default();
+    res.labels = container.labels.unwrap_or_default().into_iter().collect();
+    res.env = container.env.unwrap_or_default();
+    res.entrypoint = container.entrypoint.unwrap_or_default();
+    res.cmd = container.cmd.unwrap_or_default();
+    Ok(res)
+}
+
+// get_platform_name - os/architecture[/variant]
+fn get_platform_name(platform: &ManifestPlatform) -> String {
+    match &platform.variant {
+        Some(variant) => format!("{}/{}/{}", platform.os, platform.architecture, variant),
+        None => format!("{}/{}", platform.os, platform.architecture),
+    }
+}
+
+// get_v1_config - image config of a schema 1 manifest, the newest
+// v1Compatibility entry holds the config and every entry is a history step
+fn get_v1_config(v1: &ManifestSchema) -> Result<ImageConfig, MirrorError> {
+    let mut config: ImageConfig = match v1.history.first() {
+        Some(h) => serde_json::from_str(&h.v1compatibility)
+            .map_err(|why| MirrorError::Parse(format!("v1Compatibility: {}", why)))?,
+        None => ImageConfig::default(),
+    };
+    let mut history = vec![];
+    for h in v1.history.iter().rev() {
+        let step: serde_json::Value = serde_json::from_str(&h.v1compatibility)
+            .map_err(|why| MirrorError::Parse(format!("v1Compatibility: {}", why)))?;
+        let created_by = step["container_config"]["Cmd"].as_array().map(|cmd| {
+            cmd.iter()
+                .filter_map(|c| c.as_str())
+                .collect::<Vec<&str>>()
+                .join(" ")
+        });
+        history.push(ImageHistory {
+            created: step["created"].as_str().map(|c| c.to_string()),
+            created_by,
+            comment: step["comment"].as_str().map(|c| c.to_string()),
+            empty_layer: step["throwaway"].as_bool().unwrap_or(false),
+        });
+    }
+    config.history = Some(history);
+    if config.architecture.is_empty() {
+        config.architecture = v1.architecture.clone();
+    }
+    Ok(config)
+}
+
+// print_inspect - print the inspect result as text, json or yaml
+pub fn print_inspect(res: &InspectResult, output: Option<OutputFormat>) -> Result<(), MirrorError> {
+    match output {
+        Some(OutputFormat::Json) => println!("{}", serde_json::to_string_pretty(res)?),
+        Some(OutputFormat::Yaml) => print!("{}", serde_yaml::to_string(res)?),
+        None => {
+            println!("{:<14} {}", "Name:", res.name);
+            println!("{:<14} {}", "Digest:", res.digest);
+            println!("{:<14} {}", "MediaType:", res.media_type);
+            println!("{:<14} {}", "Size:", res.size);
+            if !res.platforms.is_empty() {
+                println!("{:<14} {}", "Platforms:", res.platforms.join(", "));
+            }
+            println!("{:<14} {}", "Architecture:", res.architecture);
+            println!("{:<14} {}", "Os:", res.os);
+            println!("{:<14} {}", "Created:", res.created);
+            println!("{:<14} {}", "Entrypoint:", res.entrypoint.join(" "));
+            println!("{:<14} {}", "Cmd:", res.cmd.join(" "));
+            println!("Labels:");
+            for (key, value) in res.labels.iter() {
+                println!("  {}={}", key, value);
+            }
+            println!("Env:");
+            for env in res.env.iter() {
+                println!("  {}", env);
+            }
+            let total: i64 = res.layers.iter().filter_map(|l| l.size).sum();
+            println!("Layers: ({}, {} bytes)", res.layers.len(), total);
+            for layer in res.layers.iter() {
+                let size = layer.size.map(|s| s.to_string()).unwrap_or("-".to_string());
+                println!("  {}  {:>12}", layer.digest, size);
+            }
+            println!("History:");
+            for step in res.history.iter() {
+                let mut created_by = step.created_by.clone().unwrap_or_default();
+                if created_by.len() > 100 {
+                    created_by = created_by.chars().take(97).collect::<String>() + "...";
+                }
+                println!(
+                    "  {:<30} {}{}",
+                    step.created.clone().unwrap_or_default(),
+                    created_by,
+                    if step.empty_layer { " (empty)" } else { "" }
+                );
+            }
+            println!("Referrers: ({})", res.referrers.len());
+            for referrer in res.referrers.iter() {
+                println!(
+                    "  {}  {}",
+                    referrer.digest,
+                    referrer
+                        .artifact_type
+                        .clone()
+                        .or(referrer.media_type.clone())
+                        .unwrap_or_default()
+                );
+            }
+        }
+    }
+    log_info(&format!("inspected {}", res.name));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::client::set_insecure_registry;
+    use clap::Parser;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn unsupported_output_is_rejected() {
+        let output = |args: &[&str]| {
+            Cli::try_parse_from([&["rust-container-tool"], args].concat()).map(|cli| cli.command)
+        };
+        match output(&["tags", "quay.io/a/b", "-o", "yaml"]) {
+            Ok(Command::Tags(tags)) => assert_eq!(tags.output, Some(OutputFormat::Yaml)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match output(&["repositories", "quay.io"]) {
+            Ok(Command::Repositories(repositories)) => assert_eq!(repositories.output, None),
+            other => panic!("unexpected {:?}", other),
+        }
+        match output(&["list", "configs", "-o", "wide"]) {
+            Ok(Command::List(list)) => assert_eq!(list.output, Some(ListFormat::Wide)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match output(&["graph", "configs"]) {
+            Ok(Command::Graph(graph)) => assert_eq!(graph.output, GraphFormat::Dot),
+            other => panic!("unexpected {:?}", other),
+        }
+        // values of another command (or none) are rejected
+        for args in [
+            &["tags", "quay.io/a/b", "-o", "xml"][..],
+            &["inspect", "quay.io/a/b:v1", "-o", "table"],
+            &["graph", "configs", "-o", "json"],
+        ] {
+            let error = output(args).unwrap_err();
+            assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn v1_config() {
+        let step = |v1: serde_json::Value| History {
+            v1compatibility: v1.to_string(),
+        };
+        // the newest entry first, as in a schema 1 manifest
+        let v1 = ManifestSchema {
+            architecture: "amd64".to_string(),
+            schema_version: 1,
+            history: vec![
+                step(serde_json::json!({
+                    "os": "linux",
+                    "created": "2020-01-02T00:00:00Z",
+                    "config": { "Labels": { "version": "1.0" }, "Cmd": ["/bin/app"] },
+                    "container_config": { "Cmd": ["/bin/sh", "-c", "#(nop) CMD /bin/app"] },
+                    "throwaway": true,
+                })),
+                step(serde_json::json!({
+                    "created": "2020-01-01T00:00:00Z",
+                    "container_config": { "Cmd": ["/bin/sh", "-c", "#(nop) ADD file"] },
+                    "comment": "base",
+                })),
+            ],
+            ..Default::default()
+        };
+        let config = get_v1_config(&v1).unwrap();
+        // the architecture comes from the manifest when the entry has none
+        assert_eq!(config.architecture, "amd64");
+        assert_eq!(config.os, "linux");
+        assert_eq!(config.created.as_deref(), Some("2020-01-02T00:00:00Z"));
+        let container = config.config.unwrap();
+        assert_eq!(container.labels.unwrap()["version"], "1.0");
+        assert_eq!(container.cmd.unwrap(), vec!["/bin/app"]);
+        let history = config.history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].created_by.as_deref(), Some("/bin/sh -c #(nop) ADD file"));
+        assert_eq!(history[0].comment.as_deref(), Some("base"));
+        assert!(!history[0].empty_layer);
+        assert!(history[1].empty_layer);
+
+        let broken = ManifestSchema {
+            history: vec![History {
+                v1compatibility: "{".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert!(matches!(get_v1_config(&broken), Err(MirrorError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn platform_of_a_manifest_list() {
+        let platform = |digest: &str, platform: serde_json::Value| {
+            serde_json::json!({
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": digest,
+                "size": 10,
+                "platform": platform,
+            })
+        };
+        let index = |manifests: Vec<serde_json::Value>| {
+            serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.index.v1+json",
+                "manifests": manifests,
+            })
+        };
+        let image = |config: &str| {
+            serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": { "digest": config, "size": 10 },
+                "layers": [{ "digest": "sha256:1a1a", "size": 100 }],
+            })
+        };
+        let config = |arch: &str| {
+            serde_json::json!({
+                "architecture": arch,
+                "os": "linux",
+                "config": { "Labels": { "arch": arch } },
+            })
+        };
+        let arm64 = serde_json::json!({ "os": "linux", "architecture": "arm64", "variant": "v8" });
+        let amd64 = serde_json::json!({ "os": "linux", "architecture": "amd64" });
+        let s390x = serde_json::json!({ "os": "linux", "architecture": "s390x" });
+        let served = [
+            (
+                "/v2/test/app/manifests/v1",
+                index(vec![
+                    platform("sha256:a001", arm64.clone()),
+                    platform("sha256:a002", amd64),
+                ]),
+            ),
+            (
+                "/v2/test/app/manifests/v2",
+                index(vec![
+                    platform("sha256:a001", arm64),
+                    platform("sha256:a003", s390x),
+                ]),
+            ),
+            ("/v2/test/app/manifests/sha256:a001", image("sha256:c001")),
+            ("/v2/test/app/manifests/sha256:a002", image("sha256:c002")),
+            ("/v2/test/app/blobs/sha256:c001", config("arm64")),
+            ("/v2/test/app/blobs/sha256:c002", config("amd64")),
+        ]
+        .map(|(path, body)| (path, body.to_string()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let response = match served.iter().find(|(p, _)| *p == path) {
+                    Some((_, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nDocker-Content-Digest: sha256:d1d1\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        if request.starts_with("HEAD") { "" } else { body }
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        set_insecure_registry(&registry).await.unwrap();
+
+        // linux/amd64 is picked wherever it is in the list
+        let image = format!("{}/test/app:v1", registry);
+        let res = inspect_image_from(parse_image(image.clone()), image, String::new())
+            .await
+            .unwrap();
+        assert_eq!(res.platforms, vec!["linux/arm64/v8", "linux/amd64"]);
+        assert_eq!(res.architecture, "amd64");
+        assert_eq!(res.labels["arch"], "amd64");
+        assert_eq!(res.layers.len(), 1);
+        assert!(res.referrers.is_empty());
+
+        // otherwise the first image of the list
+        let image = format!("{}/test/app:v2", registry);
+        let res = inspect_image_from(parse_image(image.clone()), image, String::new())
+            .await
+            .unwrap();
+        assert_eq!(res.platforms, vec!["linux/arm64/v8", "linux/s390x"]);
+        assert_eq!(res.architecture, "arm64");
+        assert_eq!(res.os, "linux");
+    }
+}
@@ -0,0 +1,32 @@
+//! The on disk image layout mirrored content is kept in: manifests in
+//! `<dir>/manifests/<hex>.json` and blobs in `<dir>/blobs/sha256/<hex>`.
+
+use crate::api::error::*;
+use crate::image::copy::*;
+use crate::image::push::*;
+
+pub use crate::api::schema::GcReport;
+
+/// Copy 'image' (manifests and blobs, every image of a manifest list) into
+/// the layout in 'dir' and return its manifest digest, registries.conf
+/// mirrors are tried first.
+pub async fn copy(image: &str, dir: &str) -> Result<String, MirrorError> {
+    let img_ref = parse_image(image.to_string());
+    copy_image(img_ref, dir.trim_end_matches('/').to_string()).await
+}
+
+/// Push the image with manifest 'digest' from the layout in 'dir' to 'destination'.
+pub async fn push(dir: &str, digest: &str, destination: &str) -> Result<(), MirrorError> {
+    push_image(
+        dir.trim_end_matches('/').to_string(),
+        digest.to_string(),
+        destination.to_string(),
+    )
+    .await
+}
+
+/// Remove the blobs no manifest in the layout refers to, with 'dry_run'
+/// they are only counted.
+pub fn collect_garbage(dir: &str, dry_run: bool) -> Result<GcReport, MirrorError> {
+    crate::mirror::gc::collect_garbage(dir.trim_end_matches('/').to_string() + "/", dry_run)
+}
@@ -0,0 +1,49 @@
+//! Library behind the rust-container-tool cli, it can be used to embed the
+//! catalog, registry and mirroring logic in other tools.
+//!
+//! The public api is
+//! - [`registry`] client settings, registries.conf, tags, repositories and inspect
+//! - [`auth`] registry credentials and tokens
+//! - [`catalog`] pull a catalog index, read and validate its file based catalog
+//! - [`layout`] copy and push images using the on disk layout
+//! - [`mirror`] mirror operators, pack, unpack and publish for air-gapped transfer
+//! - [`cli`] the commands of the binary
+//!
+//! Every call returns a [`MirrorError`] on failure, its kind tells registry,
+//! network, auth and catalog failures apart.
+//!
+//! ```no_run
+//! use rust_container_tool::{catalog, MirrorError};
+//!
+//! # async fn example() -> Result<(), MirrorError> {
+//! catalog::set_working_dir("working-dir");
+//! let dir = catalog::pull("registry.redhat.io/redhat/redhat-operator-index:v4.12").await?;
+//! let summary = catalog::get_summary(&dir, "kubevirt-hyperconverged")?;
+//! println!("{:?}", summary.default_channel);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod auth;
+pub mod catalog;
+pub mod cli;
+pub mod layout;
+pub mod mirror;
+pub mod registry;
+
+pub(crate) mod api;
+pub(crate) mod archive;
+pub(crate) mod config;
+pub(crate) mod diff;
+pub(crate) mod graph;
+pub(crate) mod image;
+pub(crate) mod inspect;
+pub(crate) mod list;
+pub(crate) mod log;
+pub(crate) mod manifests;
+pub(crate) mod release;
+pub(crate) mod search;
+pub(crate) mod validate;
+
+pub use api::error::{ErrorContext, MirrorError};
+pub use api::schema::RegistryErrorDetail;
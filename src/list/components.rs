@@ -1,73 +1,214 @@
+use crate::api::error::*;
 use crate::api::schema::*;
+use crate::graph::upgrade::*;
 use crate::log::logging::*;
 use crate::manifests::catalogs::*;
+use crate::mirror::related::*;
 use std::fs;
 
 // list all components in the current image index
-pub async fn list_components(ctype: String, dir: String, filter: String) {
-    let paths = fs::read_dir(&dir).unwrap();
+// with an output format (json, yaml, table or wide) the result is written to stdout
+pub async fn list_components(
+    ctype: String,
+    dir: String,
+    filter: String,
+    output: Option<ListFormat>,
+) -> Result<(), MirrorError> {
+    if let Some(output) = output {
+        let mut summaries = vec![];
+        for pkg in select_packages(dir.clone(), filter, None)? {
+            match read_operator_catalog(dir.clone() + "/" + &pkg) {
+                Ok(dc) => summaries.push(get_package_summary(pkg, dc)),
+                Err(error) => log_error(&error.to_string()),
+            }
+        }
+        match output {
+            ListFormat::Json => println!("{}", serde_json::to_string_pretty(&summaries)?),
+            ListFormat::Yaml => print!("{}", serde_yaml::to_string(&summaries)?),
+            ListFormat::Table => print!("{}", render_table(&summaries, false)),
+            ListFormat::Wide => print!("{}", render_table(&summaries, true)),
+        }
+        return Ok(());
+    }
+
+    let paths = fs::read_dir(&dir).context(format!("reading packages in {}", dir))?;
 
     if filter != "all" {
-        let dc = read_operator_catalog(dir + &"/".to_string() + &filter);
         log_hi(&filter);
-        list_channel_info(dc.unwrap());
+        match read_operator_catalog(dir + "/" + &filter) {
+            Ok(dc) => list_channel_info(dc),
+            Err(error) => log_error(&error.to_string()),
+        }
     } else {
         for path in paths {
-            let entry = path.expect("could not resolve path entry");
-            let dir_name = entry.path();
-            let str_dir = dir_name.into_os_string().into_string().unwrap();
-            let res = str_dir.split("/");
-            let name = format!("{} => {}", ctype, res.into_iter().last().unwrap());
-            let dc = read_operator_catalog(str_dir);
+            let entry = path.context(format!("reading packages in {}", dir))?;
+            if is_ignored_package(&entry.path())? {
+                continue;
+            }
+            let str_dir = entry.path().to_string_lossy().to_string();
+            let name = format!("{} => {}", ctype, entry.file_name().to_string_lossy());
             log_hi(&name);
-            list_channel_info(dc.unwrap());
+            match read_operator_catalog(str_dir) {
+                Ok(dc) => list_channel_info(dc),
+                Err(error) => log_error(&error.to_string()),
+            }
         }
     }
+    Ok(())
 }
 
 // iterate through object and display values
-pub fn list_channel_info(dc: serde_json::Value) {
-    // attempt to read entries first
-    // this is because some catalogs don't have entries and
-    // this would cause a panic
-    let de: Vec<DeclarativeEntries> = match serde_json::from_value(dc.clone()) {
-        Ok(val) => val,
-        Err(_) => {
-            let ch = ChannelEntry {
-                name: String::from("none"),
-                skips: Some(String::from("no-skips")),
-                skip_range: Some(String::from("no-skip_range")),
-                replaces: Some(String::from("no-replaces")),
-            };
-            let v = vec![ch];
-            let de = DeclarativeEntries { entries: Some(v) };
-            let vde = vec![de];
-            vde
-        }
-    };
-
-    // read the rest of the declarative config
-    let dc: Vec<DeclarativeConfig> = serde_json::from_value(dc).unwrap();
-
-    // list channel, bundles and defaultChannel
+pub fn list_channel_info(dc: Vec<DeclarativeConfig>) {
+    // list defaultChannel, channels (with entries) and bundles
     for x in dc {
-        if x.default_channel != None {
-            let channel = x.default_channel.clone().unwrap();
-            log_lo(&format!("  defaultChannel => {}", channel));
-        }
-        if x.schema == "olm.channel" {
-            log_lo(&format!("  channel => {}", x.name));
+        match x {
+            DeclarativeConfig::Package(pkg) => {
+                if let Some(channel) = pkg.default_channel {
+                    log_lo(&format!("  defaultChannel => {}", channel));
+                }
+            }
+            DeclarativeConfig::Channel(ch) => {
+                log_lo(&format!("  channel => {}", ch.name));
+                for item in ch.entries {
+                    log_ex(&format!("    entry => {}", item.name));
+                }
+            }
+            DeclarativeConfig::Bundle(bundle) => {
+                let version = bundle.version().unwrap_or_default();
+                log_mid(&format!("    bundle => {} {}", bundle.name, version));
+            }
+            DeclarativeConfig::Unknown(_) => {}
         }
-        if x.schema == "olm.bundle" {
-            log_mid(&format!("    bundle => {}", x.name));
+    }
+}
+
+// get_package_summary - structured view of a package
+// package -> default channel -> channels -> entries and bundles (with versions)
+pub fn get_package_summary(name: String, dc: Vec<DeclarativeConfig>) -> PackageSummary {
+    let versions = get_bundle_versions(&dc);
+    let mut summary = PackageSummary {
+        name,
+        default_channel: None,
+        channels: vec![],
+        bundles: vec![],
+    };
+    for obj in dc {
+        match obj {
+            DeclarativeConfig::Package(pkg) => {
+                summary.name = pkg.name;
+                summary.default_channel = pkg.default_channel;
+            }
+            DeclarativeConfig::Channel(ch) => {
+                let graph = build_upgrade_graph(&ch, &versions);
+                let entries = ch
+                    .entries
+                    .into_iter()
+                    .map(|e| EntrySummary {
+                        version: versions.get(&e.name).map(|v| v.to_string()),
+                        name: e.name,
+                        replaces: e.replaces,
+                        skips: e.skips,
+                        skip_range: e.skip_range,
+                    })
+                    .collect();
+                summary.channels.push(ChannelSummary {
+                    name: ch.name,
+                    head: graph.head,
+                    entries,
+                });
+            }
+            DeclarativeConfig::Bundle(bundle) => summary.bundles.push(BundleSummary {
+                version: bundle.version(),
+                name: bundle.name,
+                image: bundle.image,
+            }),
+            DeclarativeConfig::Unknown(_) => {}
         }
     }
-    // lis tall entries
-    for decl_entry in de.into_iter() {
-        if let Some(channel_entry) = decl_entry.entries {
-            for item in channel_entry {
-                log_ex(&format!("    entry => {}", item.name));
+    summary
+}
+
+// render_table - plain text table, one row per channel
+// or (wide) one row per channel entry including the bundle image
+pub fn render_table(summaries: &[PackageSummary], wide: bool) -> String {
+    let mut rows: Vec<Vec<String>> = vec![];
+    if wide {
+        rows.push(
+            [
+                "PACKAGE", "CHANNEL", "DEFAULT", "ENTRY", "VERSION", "REPLACES", "IMAGE",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+    } else {
+        rows.push(
+            [
+                "PACKAGE", "CHANNEL", "DEFAULT", "HEAD", "VERSION", "ENTRIES",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+    }
+    for pkg in summaries {
+        for ch in pkg.channels.iter() {
+            let default = if pkg.default_channel.as_ref() == Some(&ch.name) {
+                "*".to_string()
+            } else {
+                String::new()
+            };
+            if wide {
+                for entry in ch.entries.iter() {
+                    let image = pkg
+                        .bundles
+                        .iter()
+                        .find(|b| b.name == entry.name)
+                        .map(|b| b.image.clone())
+                        .unwrap_or_default();
+                    rows.push(vec![
+                        pkg.name.clone(),
+                        ch.name.clone(),
+                        default.clone(),
+                        entry.name.clone(),
+                        entry.version.clone().unwrap_or_default(),
+                        entry.replaces.clone().unwrap_or_default(),
+                        image,
+                    ]);
+                }
+            } else {
+                let head = ch.head.clone().unwrap_or_default();
+                let version = ch
+                    .entries
+                    .iter()
+                    .find(|e| e.name == head)
+                    .and_then(|e| e.version.clone())
+                    .unwrap_or_default();
+                rows.push(vec![
+                    pkg.name.clone(),
+                    ch.name.clone(),
+                    default,
+                    head,
+                    version,
+                    ch.entries.len().to_string(),
+                ]);
             }
         }
     }
+
+    let mut widths = vec![0; rows[0].len()];
+    for row in rows.iter() {
+        for (i, col) in row.iter().enumerate() {
+            widths[i] = widths[i].max(col.len());
+        }
+    }
+    let mut table = String::new();
+    for row in rows {
+        let cols: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, col)| format!("{:width$}", col, width = widths[i]))
+            .collect();
+        table.push_str(cols.join("   ").trim_end());
+        table.push('\n');
+    }
+    table
 }
@@ -1,44 +1,338 @@
 // module logging
 
+// levelled logging, every line goes to stderr so that stdout only carries
+// command output, either as (coloured when stderr is a tty) text or as one
+// json object per line with structured fields for log pipelines
+
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::api::schema::*;
+use crate::log::progress::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    // parse - level from its name (as used by --log-level)
+    pub fn parse(level: &str) -> Result<Self, String> {
+        match level.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!(
+                "unknown log level {} (error, warn, info, debug or trace)",
+                level
+            )),
+        }
+    }
+
+    // from_verbosity - info by default, each -v raises and each -q lowers the level
+    pub fn from_verbosity(verbose: u8, quiet: u8) -> Self {
+        match 2 + verbose as i16 - quiet as i16 {
+            i16::MIN..=0 => LogLevel::Error,
+            1 => LogLevel::Warn,
+            2 => LogLevel::Info,
+            3 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    // label - the padded (and coloured) level shown in text lines
+    fn label(&self, color: bool) -> String {
+        let (label, code) = match self {
+            LogLevel::Error => (" ERROR ", "1;91"),
+            LogLevel::Warn => (" WARN  ", "1;93"),
+            LogLevel::Info => (" INFO  ", "1;94"),
+            LogLevel::Debug => (" DEBUG ", "1;92"),
+            LogLevel::Trace => (" TRACE ", "1;90"),
+        };
+        match color {
+            true => format!("\x1b[{}m{}\x1b[0m", code, label),
+            false => label.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    // parse - format from its name (as used by --log-format)
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format {
+            "" | "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {} (text or json)", format)),
+        }
+    }
+}
+
+struct LogSettings {
+    level: LogLevel,
+    format: LogFormat,
+    color: bool,
+}
+
+static SETTINGS: OnceLock<LogSettings> = OnceLock::new();
+
+// init_logging - set the level and format once at startup, colour is only
+// used for text lines written to a terminal (and when NO_COLOR is not set)
+pub fn init_logging(level: LogLevel, format: LogFormat) {
+    let _ = SETTINGS.set(LogSettings {
+        level,
+        format,
+        color: use_color(format),
+    });
+}
+
+// use_color - colour text lines written to a terminal
+fn use_color(format: LogFormat) -> bool {
+    format == LogFormat::Text
+        && std::io::stderr().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none()
+}
+
+// get_settings - the settings from init_logging (info and text by default)
+fn get_settings() -> &'static LogSettings {
+    SETTINGS.get_or_init(|| LogSettings {
+        level: LogLevel::Info,
+        format: LogFormat::Text,
+        color: use_color(LogFormat::Text),
+    })
+}
+
+// log_enabled - true when lines of 'level' are written
+pub fn log_enabled(level: LogLevel) -> bool {
+    level <= get_settings().level
+}
+
+// log_interactive - true when log lines are text on a terminal at the info
+// level or above (progress bars are only drawn then)
+pub fn log_interactive() -> bool {
+    let settings = get_settings();
+    settings.format == LogFormat::Text
+        && std::io::stderr().is_terminal()
+        && log_enabled(LogLevel::Info)
+}
+
+// write_line - write a log line to stderr (above the progress bars if any)
+fn write_line(line: &str) {
+    if !progress_println(line) {
+        eprintln!("{}", line);
+    }
+}
+
+// log_event - write a line with structured fields (only shown in json lines)
+pub fn log_event(level: LogLevel, msg: &str, fields: LogFields) {
+    let settings = get_settings();
+    if level > settings.level {
+        return;
+    }
+    match settings.format {
+        LogFormat::Json => write_line(&get_json_line(level, msg, fields)),
+        LogFormat::Text => write_line(&format!("{} : {}", level.label(settings.color), msg)),
+    }
+}
+
+// get_json_line - a log line as a json object, the fields that are set
+// follow time, level and message
+fn get_json_line(level: LogLevel, msg: &str, fields: LogFields) -> String {
+    let mut line = serde_json::Map::new();
+    line.insert("time".to_string(), get_timestamp().into());
+    line.insert("level".to_string(), level.name().into());
+    line.insert("message".to_string(), msg.into());
+    if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(fields) {
+        line.extend(fields);
+    }
+    serde_json::Value::Object(line).to_string()
+}
+
+// get_timestamp - current utc time as rfc3339 with milliseconds
+fn get_timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        now.subsec_millis()
+    )
+}
+
 // logging convenience functions
 
 // info
 pub fn log_info(msg: &str) {
-    println!("\x1b[1;94m {} \x1b[0m  : {}", "INFO", msg);
+    log_event(LogLevel::Info, msg, LogFields::default());
 }
 
 // debug
 pub fn log_debug(msg: &str) {
-    println!("\x1b[1;92m {} \x1b[0m : {}", "DEBUG", msg);
+    log_event(LogLevel::Debug, msg, LogFields::default());
+}
+
+// warning
+pub fn log_warn(msg: &str) {
+    log_event(LogLevel::Warn, msg, LogFields::default());
+}
+
+// error
+pub fn log_error(msg: &str) {
+    log_event(LogLevel::Error, msg, LogFields::default());
+}
+
+// presentation helpers, info lines with a highlighted message in text mode
+// (plain info lines in json mode or without a terminal)
+
+// log_styled - info line with the message in colour 'code'
+fn log_styled(msg: &str, code: &str) {
+    let settings = get_settings();
+    if settings.color && log_enabled(LogLevel::Info) {
+        write_line(&format!(
+            "{} : \x1b[{}m{} \x1b[0m",
+            LogLevel::Info.label(true),
+            code,
+            msg
+        ));
+    } else {
+        log_info(msg);
+    }
 }
 
 // info with highlight
 pub fn log_hi(msg: &str) {
-    println!("\x1b[1;94m {}  \x1b[0m : \x1b[1;95m{} \x1b[0m", "INFO", msg);
+    log_styled(msg, "1;95");
 }
 
 // info with mid level highlight
 pub fn log_mid(msg: &str) {
-    println!("\x1b[1;94m {}  \x1b[0m : \x1b[1;96m{} \x1b[0m", "INFO", msg);
+    log_styled(msg, "1;96");
 }
 
 // info with low level highlight
 pub fn log_lo(msg: &str) {
-    println!("\x1b[1;94m {}  \x1b[0m : \x1b[1;93m{} \x1b[0m", "INFO", msg);
+    log_styled(msg, "1;93");
 }
 
 // info with extra level highlight
 pub fn log_ex(msg: &str) {
-    println!("\x1b[1;94m {}  \x1b[0m : \x1b[1;98m{} \x1b[0m", "INFO", msg);
+    log_styled(msg, "1;98");
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-// warning
-pub fn log_warn(msg: &str) {
-    println!("\x1b[1;93m {} \x1b[0m  : {}", "WARN", msg);
-}
+    #[test]
+    fn verbosity_and_quiet() {
+        let tests = vec![
+            (0, 0, LogLevel::Info),
+            (1, 0, LogLevel::Debug),
+            (2, 0, LogLevel::Trace),
+            (5, 0, LogLevel::Trace),
+            (0, 1, LogLevel::Warn),
+            (0, 2, LogLevel::Error),
+            (0, 5, LogLevel::Error),
+            // -v and -q cancel out
+            (1, 1, LogLevel::Info),
+            (u8::MAX, 0, LogLevel::Trace),
+            (0, u8::MAX, LogLevel::Error),
+        ];
+        for (verbose, quiet, expected) in tests {
+            let level = LogLevel::from_verbosity(verbose, quiet);
+            assert_eq!(level, expected, "-v {} -q {}", verbose, quiet);
+        }
+    }
 
-// error
-pub fn log_error(msg: &str) {
-    println!("\x1b[1;91m {} \x1b[0m : {}", "ERROR", msg);
+    #[test]
+    fn level_and_format_names() {
+        let tests = vec![
+            ("error", Ok(LogLevel::Error)),
+            ("warn", Ok(LogLevel::Warn)),
+            ("warning", Ok(LogLevel::Warn)),
+            ("INFO", Ok(LogLevel::Info)),
+            ("debug", Ok(LogLevel::Debug)),
+            ("trace", Ok(LogLevel::Trace)),
+            ("", Err(())),
+            ("verbose", Err(())),
+        ];
+        for (name, expected) in tests {
+            assert_eq!(LogLevel::parse(name).map_err(|_| ()), expected, "{}", name);
+        }
+        assert_eq!(LogFormat::parse(""), Ok(LogFormat::Text));
+        assert_eq!(LogFormat::parse("json"), Ok(LogFormat::Json));
+        assert!(LogFormat::parse("JSON").is_err());
+    }
+
+    #[test]
+    fn json_line_fields() {
+        let line = get_json_line(
+            LogLevel::Warn,
+            "mirrored",
+            LogFields {
+                image: Some("quay.io/a/operator:v1".to_string()),
+                digest: Some("sha256:1111".to_string()),
+                bytes: Some(1024),
+                duration_ms: Some(250),
+            },
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        let mut keys: Vec<&str> = value.as_object().unwrap().keys().map(|k| k.as_str()).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["bytes", "digest", "duration_ms", "image", "level", "message", "time"]
+        );
+        assert_eq!(value["level"], "warn");
+        assert_eq!(value["message"], "mirrored");
+        assert_eq!(value["digest"], "sha256:1111");
+        assert_eq!(value["bytes"], 1024);
+        assert_eq!(value["duration_ms"], 250);
+        assert!(value["time"].as_str().unwrap().ends_with('Z'));
+
+        // unset fields are left out
+        let line = get_json_line(LogLevel::Info, "done", LogFields::default());
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value.as_object().unwrap().len(), 3);
+    }
 }
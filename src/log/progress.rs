@@ -0,0 +1,194 @@
+// module progress
+
+// live progress bars for blob downloads and layer extraction, only drawn when
+// log lines go to a terminal as text (see log_interactive), log lines written
+// while bars are shown are printed above them
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::Read;
+use std::sync::Mutex;
+
+use crate::log::logging::*;
+
+// the bars being drawn (one set at a time), used to print log lines above them
+static ACTIVE: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+const BLOB_TEMPLATE: &str =
+    "  {msg:14} [{bar:30.cyan/blue}] {bytes:>10}/{total_bytes:<10} {binary_bytes_per_sec:>12} eta {eta}";
+const TOTAL_TEMPLATE: &str =
+    "{msg:16} [{bar:30.green/white}] {bytes:>10}/{total_bytes:<10} {binary_bytes_per_sec:>12} eta {eta}";
+const SPINNER_TEMPLATE: &str = "  {spinner} {msg:14} {bytes:>10} {binary_bytes_per_sec:>12}";
+
+// progress_println - print a log line above the active bars, false when no
+// bars are drawn (the caller writes the line itself)
+pub fn progress_println(line: &str) -> bool {
+    match ACTIVE.lock().ok().as_ref().and_then(|active| active.as_ref()) {
+        Some(multi) => multi.println(line).is_ok(),
+        None => false,
+    }
+}
+
+// TransferProgress - an aggregate bar (bytes of all blobs, the length grows as
+// the size of each blob becomes known) and a bar per blob being transferred
+pub struct TransferProgress {
+    multi: Option<MultiProgress>,
+    total: Option<ProgressBar>,
+}
+
+impl TransferProgress {
+    // new - 'label' is shown on the aggregate bar, nothing is drawn when
+    // the output is not interactive
+    pub fn new(label: &str) -> Self {
+        if !log_interactive() {
+            return TransferProgress {
+                multi: None,
+                total: None,
+            };
+        }
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+        let total = multi.add(ProgressBar::new(0));
+        total.set_style(get_style(TOTAL_TEMPLATE));
+        total.set_message(label.to_string());
+        if let Ok(mut active) = ACTIVE.lock() {
+            *active = Some(multi.clone());
+        }
+        TransferProgress {
+            multi: Some(multi),
+            total: Some(total),
+        }
+    }
+
+    // add_total - seed the aggregate bar with 'bytes' known up front (e.g. the
+    // sizes in a manifest), their blobs are started with start_known_blob
+    pub fn add_total(&self, bytes: u64) {
+        if let Some(total) = &self.total {
+            total.inc_length(bytes);
+        }
+    }
+
+    // start_blob - a bar for a single blob of 'len' bytes (a spinner when unknown)
+    pub fn start_blob(&self, name: &str, len: Option<u64>) -> BlobProgress {
+        let mut progress = self.start_known_blob(name, len);
+        if let (Some(total), Some(len)) = (&self.total, len) {
+            total.inc_length(len);
+        }
+        progress.len = len;
+        progress
+    }
+
+    // start_known_blob - a bar for a blob whose 'len' is already counted in the
+    // aggregate bar (see add_total), it stays counted when an attempt is aborted
+    pub fn start_known_blob(&self, name: &str, len: Option<u64>) -> BlobProgress {
+        let bar = self.multi.as_ref().map(|multi| {
+            let bar = match len {
+                Some(len) => {
+                    let bar = ProgressBar::new(len);
+                    bar.set_style(get_style(BLOB_TEMPLATE));
+                    bar
+                }
+                None => {
+                    let bar = ProgressBar::new_spinner();
+                    bar.set_style(get_style(SPINNER_TEMPLATE));
+                    bar
+                }
+            };
+            bar.set_message(name.to_string());
+            multi.add(bar)
+        });
+        BlobProgress {
+            bar,
+            total: self.total.clone(),
+            len: None,
+            done: 0,
+        }
+    }
+}
+
+// the bars are removed when the transfer is done
+impl Drop for TransferProgress {
+    fn drop(&mut self) {
+        if let Some(total) = &self.total {
+            total.finish_and_clear();
+        }
+        if let Some(multi) = &self.multi {
+            let _ = multi.clear();
+            if let Ok(mut active) = ACTIVE.lock() {
+                *active = None;
+            }
+        }
+    }
+}
+
+// BlobProgress - progress of a single blob (counted in the aggregate bar too)
+pub struct BlobProgress {
+    bar: Option<ProgressBar>,
+    total: Option<ProgressBar>,
+    // the length this blob added to the aggregate bar (start_blob)
+    len: Option<u64>,
+    done: u64,
+}
+
+impl BlobProgress {
+    // inc - 'n' more bytes transferred
+    pub fn inc(&mut self, n: u64) {
+        self.done += n;
+        if let Some(bar) = &self.bar {
+            bar.inc(n);
+        }
+        if let Some(total) = &self.total {
+            total.inc(n);
+        }
+    }
+
+    // abort - undo the bytes counted for an attempt that failed (it is retried
+    // or reported), the blob length is removed from the aggregate bar
+    pub fn abort(self) {
+        if let Some(total) = &self.total {
+            total.set_position(total.position().saturating_sub(self.done));
+            if let Some(len) = self.len {
+                total.set_length(total.length().unwrap_or_default().saturating_sub(len));
+            }
+        }
+    }
+}
+
+// the bar of a blob is removed once it is transferred (or aborted)
+impl Drop for BlobProgress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+// ProgressReader - a reader that advances a progress bar (extraction of layers)
+pub struct ProgressReader<R: Read> {
+    inner: R,
+    progress: BlobProgress,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, progress: BlobProgress) -> Self {
+        ProgressReader { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.inc(n as u64);
+        Ok(n)
+    }
+}
+
+// get_style - bar style from 'template' (falls back to the default style)
+fn get_style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .map(|style| style.progress_chars("=> "))
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+}
+
+// format_bytes - human readable size (binary units)
+pub fn format_bytes(bytes: u64) -> String {
+    indicatif::HumanBytes(bytes).to_string()
+}
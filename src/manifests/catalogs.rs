@@ -1,33 +1,305 @@
+use crate::api::error::*;
 use crate::api::schema::*;
 use crate::log::logging::*;
-use std::error::Error;
+use crate::registry::client::lock;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
-
-// read_operator_catalog - simple function tha treads the specific catalog.json file
-// and unmarshals it to DeclarativeConfig struct
-pub fn read_operator_catalog(path: String) -> Result<serde_json::Value, Box<dyn Error>> {
-    let catalog = path + &"/catalog.json".to_owned();
-    // Open the path in read-only mode, returns `io::Result<File>`
-    let mut file = match File::open(&catalog) {
-        Err(why) => panic!("couldn't open {}: {}", catalog, why),
-        Ok(file) => file,
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+// '.indexignore' patterns of the catalog root directories, read once per directory
+static ROOT_IGNORES: OnceLock<Mutex<HashMap<PathBuf, Vec<String>>>> = OnceLock::new();
+
+// read_operator_catalog - read all file based catalog objects for a package
+// 'path' can be a single file or a directory, in which case every json and
+// yaml file in the directory (and sub directories) is read in name order
+// files matching the patterns of a '.indexignore' file are skipped (as opm does),
+// the one at the catalog root (the parent of 'path') included
+pub fn read_operator_catalog(path: String) -> Result<Vec<DeclarativeConfig>, MirrorError> {
+    let path = Path::new(&path);
+    let mut ignores = get_root_ignores(path)?;
+    if is_ignored(path, &ignores) {
+        log_debug(&format!("{} is ignored by {}", path.display(), INDEX_IGNORE));
+        return Ok(vec![]);
+    }
+    let mut files = vec![];
+    find_catalog_files(path, &mut ignores, &mut files)?;
+    files.sort();
+    let mut dc = vec![];
+    for file in files {
+        parse_catalog_file(&file, |obj| dc.push(obj))?;
+    }
+    Ok(dc)
+}
+
+// find_catalog_files - collect all json and yaml files below 'path'
+// 'ignores' holds the '.indexignore' patterns of the parent directories
+fn find_catalog_files(
+    path: &Path,
+    ignores: &mut Vec<(PathBuf, Vec<String>)>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), MirrorError> {
+    if path.is_dir() {
+        let catalog_error = |why: std::io::Error| {
+            MirrorError::Catalog(format!("reading {}: {}", path.display(), why))
+        };
+        let ignore_file = path.join(INDEX_IGNORE);
+        let pushed = ignore_file.is_file();
+        if pushed {
+            let data = fs::read_to_string(&ignore_file).map_err(catalog_error)?;
+            ignores.push((path.to_path_buf(), get_ignore_patterns(&data)));
+        }
+        for entry in fs::read_dir(path).map_err(catalog_error)? {
+            let entry = entry.map_err(catalog_error)?.path();
+            if !is_ignored(&entry, ignores) {
+                find_catalog_files(&entry, ignores, files)?;
+            }
+        }
+        if pushed {
+            ignores.pop();
+        }
+    } else if matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("json") | Some("yaml") | Some("yml")
+    ) {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+// file with gitignore style patterns, relative to the directory it is in
+const INDEX_IGNORE: &str = ".indexignore";
+
+// get_root_ignores - the '.indexignore' patterns of the catalog root, the
+// parent directory of the package 'path' (read once for all its packages)
+fn get_root_ignores(path: &Path) -> Result<Vec<(PathBuf, Vec<String>)>, MirrorError> {
+    let root = match path.parent() {
+        Some(root) if !root.as_os_str().is_empty() => root.to_path_buf(),
+        _ => return Ok(vec![]),
     };
+    let mut cache = lock(ROOT_IGNORES.get_or_init(Default::default));
+    if !cache.contains_key(&root) {
+        let file = root.join(INDEX_IGNORE);
+        let patterns = match file.is_file() {
+            true => get_ignore_patterns(&fs::read_to_string(&file).map_err(|why| {
+                MirrorError::Catalog(format!("reading {}: {}", file.display(), why))
+            })?),
+            false => vec![],
+        };
+        cache.insert(root.clone(), patterns);
+    }
+    Ok(match &cache[&root] {
+        patterns if patterns.is_empty() => vec![],
+        patterns => vec![(root, patterns.clone())],
+    })
+}
+
+// is_ignored_package - true when the package 'path' matches the '.indexignore'
+// patterns of its catalog root, or is the ignore file itself
+pub fn is_ignored_package(path: &Path) -> Result<bool, MirrorError> {
+    if path.file_name().and_then(|n| n.to_str()) == Some(INDEX_IGNORE) {
+        return Ok(true);
+    }
+    Ok(is_ignored(path, &get_root_ignores(path)?))
+}
+
+// get_ignore_patterns - the patterns of an '.indexignore' file (no blank lines or comments)
+fn get_ignore_patterns(data: &str) -> Vec<String> {
+    data.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+// is_ignored - gitignore semantics, the last matching pattern wins and '!'
+// negates, a pattern with a '/' is matched against the path relative to the
+// '.indexignore' directory, otherwise against the file name
+// a trailing '/' only matches directories
+fn is_ignored(path: &Path, ignores: &[(PathBuf, Vec<String>)]) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut ignored = false;
+    for (dir, patterns) in ignores.iter() {
+        let relative = match path.strip_prefix(dir) {
+            Ok(relative) => relative.to_string_lossy().to_string(),
+            Err(_) => continue,
+        };
+        for pattern in patterns.iter() {
+            let (negate, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern.as_str()),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            if dir_only && !path.is_dir() {
+                continue;
+            }
+            let matched = match pattern.contains('/') {
+                true => glob_match(pattern.trim_start_matches('/').as_bytes(), relative.as_bytes()),
+                false => glob_match(pattern.as_bytes(), name.as_bytes()),
+            };
+            if matched {
+                ignored = !negate;
+            }
+        }
+    }
+    ignored
+}
+
+// glob_match - '*' and '?' match within a path segment, '**' across segments
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            if rest.is_empty() {
+                return true;
+            }
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len())
+                .filter(|&i| i == 0 || text[i - 1] == b'/')
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(&pattern[1..], &text[i..])),
+        Some(b'?') => {
+            !text.is_empty() && text[0] != b'/' && glob_match(&pattern[1..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+// parse_catalog_file - stream the objects in a catalog file to 'handle'
+// json files are a sequence of json documents (pretty printed or not),
+// yaml files can hold multiple documents separated by '---' (a document
+// can also be ended by '...'), errors are reported with the file and line
+pub fn parse_catalog_file<F>(file: &Path, mut handle: F) -> Result<(), MirrorError>
+where
+    F: FnMut(DeclarativeConfig),
+{
+    let display = file.display();
+    let reader = File::open(file)
+        .map(BufReader::new)
+        .map_err(|why| MirrorError::Catalog(format!("couldn't open {}: {}", display, why)))?;
+    if file.extension().and_then(|ext| ext.to_str()) == Some("json") {
+        let mut stream =
+            serde_json::Deserializer::from_reader(reader).into_iter::<DeclarativeConfig>();
+        loop {
+            let offset = stream.byte_offset();
+            match stream.next() {
+                None => break,
+                Some(Ok(obj)) => handle(obj),
+                Some(Err(error)) => {
+                    // errors in a (syntactically valid) object carry no position
+                    // so we use the line the object starts on
+                    let line = match error.line() {
+                        0 => fs::read(file)
+                            .map(|data| get_line(&data, offset))
+                            .unwrap_or_default(),
+                        line => line,
+                    };
+                    return Err(MirrorError::Catalog(format!("{}:{}: {}", display, line, error)));
+                }
+            }
+        }
+        return Ok(());
+    }
 
-    // Read the file contents into a string, returns `io::Result<usize>`
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
-    let res = s.replace(" ", "");
-    // update to allow for well formatted json so that it can be processed
-    let updated_json =
-        "{ \"overview\": [".to_string() + &res.replace("}\n{", "},{") + &"]}".to_string();
-    // Parse the string of data into serde_json::Vec<DeclarativeConfig>
-    let root = match serde_json::from_str::<Catalog>(&updated_json) {
-        Ok(val) => val,
-        Err(error) => panic!("error {}", error),
+    // each document is parsed once its end is read, 'start' is the line
+    // the document starts on so error lines stay aligned with the file
+    let mut doc = String::new();
+    let mut start = 1;
+    let catalog_error = |line: usize, error: String| {
+        MirrorError::Catalog(format!("{}:{}: {}", display, line, error))
     };
-    Ok(root.overview)
+    let mut lines = reader.lines().enumerate();
+    loop {
+        let line = match lines.next() {
+            Some((i, line)) => {
+                let text = line.map_err(|why| catalog_error(i + 1, why.to_string()))?;
+                Some((i + 1, text))
+            }
+            None => None,
+        };
+        let marker = |text: &str, marker: &str| {
+            text.strip_prefix(marker)
+                .map(|rest| rest.is_empty() || rest.starts_with(' '))
+                .unwrap_or(false)
+        };
+        let (next, end) = match &line {
+            Some((_, text)) => (marker(text, "---"), marker(text, "...")),
+            None => (true, false),
+        };
+        if next || end {
+            if let Some(obj) = parse_yaml_document(&doc, start)
+                .map_err(|(line, error)| catalog_error(line, error.to_string()))?
+            {
+                handle(obj);
+            }
+            doc.clear();
+        }
+        match line {
+            // the next document starts after the end marker (only a comment can follow it)
+            Some((number, _)) if end => start = number + 1,
+            // content after the marker ('--- {...}') starts the next document
+            Some((number, text)) if next => {
+                doc.push_str(&text[3..]);
+                doc.push('\n');
+                start = number;
+            }
+            Some((_, text)) => {
+                doc.push_str(&text);
+                doc.push('\n');
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+// parse_yaml_document - the object in a single yaml document starting on
+// line 'start', empty documents (blank lines or comments only) are skipped
+fn parse_yaml_document(
+    doc: &str,
+    start: usize,
+) -> Result<Option<DeclarativeConfig>, (usize, serde_yaml::Error)> {
+    let content = doc
+        .lines()
+        .position(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+    let first = match content {
+        Some(first) => first,
+        None => return Ok(None),
+    };
+    match serde_yaml::from_str(doc) {
+        Ok(DeclarativeConfig::Unknown(serde_json::Value::Null)) => Ok(None),
+        Ok(obj) => Ok(Some(obj)),
+        Err(error) => {
+            let line = match error.location() {
+                Some(location) => start + location.line() - 1,
+                None => start + first,
+            };
+            Err((line, error))
+        }
+    }
+}
+
+// get_line - line number of the first non whitespace character after 'offset'
+fn get_line(data: &[u8], offset: usize) -> usize {
+    let offset = offset.min(data.len());
+    let start = data[offset..]
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .map(|pos| offset + pos)
+        .unwrap_or(offset);
+    data[..start].iter().filter(|&&c| c == b'\n').count() + 1
 }
 
 // find a specifc directory in the untar layers
@@ -39,15 +311,14 @@ pub async fn find_dir(dir: String, name: String) -> String {
     // down from the parent
     match paths {
         Ok(res_paths) => {
-            for path in res_paths {
-                let entry = path.expect("could not resolve path entry");
-                let file = entry.path();
+            for entry in res_paths.flatten() {
                 // go down one more level
-                let sub_paths = fs::read_dir(file).unwrap();
-                for sub_path in sub_paths {
-                    let sub_entry = sub_path.expect("could not resolve sub path entry");
-                    let sub_name = sub_entry.path();
-                    let str_dir = sub_name.into_os_string().into_string().unwrap();
+                let sub_paths = match fs::read_dir(entry.path()) {
+                    Ok(sub_paths) => sub_paths,
+                    Err(_) => continue,
+                };
+                for sub_entry in sub_paths.flatten() {
+                    let str_dir = sub_entry.path().to_string_lossy().to_string();
                     if str_dir.contains(&name) {
                         return str_dir;
                     }
@@ -59,5 +330,149 @@ pub async fn find_dir(dir: String, name: String) -> String {
             log_warn(&msg);
         }
     }
-    return "".to_string();
+    "".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // write 'files' (relative path, content) below a fresh temp directory
+    fn catalog_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fbc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, data) in files {
+            let file = dir.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, data).unwrap();
+        }
+        dir
+    }
+
+    fn names(dc: &[DeclarativeConfig]) -> Vec<String> {
+        dc.iter()
+            .map(|obj| match obj {
+                DeclarativeConfig::Package(pkg) => pkg.name.clone(),
+                DeclarativeConfig::Channel(channel) => channel.name.clone(),
+                DeclarativeConfig::Bundle(bundle) => bundle.name.clone(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    const PACKAGE: &str = r#"{"schema": "olm.package", "name": "etcd", "defaultChannel": "stable"}"#;
+    const CHANNEL: &str = r#"{
+        "schema": "olm.channel",
+        "name": "stable",
+        "package": "etcd",
+        "entries": [{"name": "etcd.v0.9.4"}]
+    }"#;
+
+    #[test]
+    fn json_stream() {
+        let dir = catalog_dir("json", &[("etcd/catalog.json", &format!("{}\n{}", PACKAGE, CHANNEL))]);
+        let dc = read_operator_catalog(dir.join("etcd").to_string_lossy().to_string()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names(&dc), vec!["etcd", "stable"]);
+    }
+
+    #[test]
+    fn json_error_line() {
+        let data = format!("{}\n{{\n  \"schema\": \"olm.channel\",\n  \"name\": 1\n}}\n", PACKAGE);
+        let dir = catalog_dir("json-error", &[("catalog.json", &data)]);
+        let error = read_operator_catalog(dir.to_string_lossy().to_string()).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(error, MirrorError::Catalog(_)));
+        assert!(error.to_string().contains("catalog.json:2:"), "{}", error);
+    }
+
+    #[test]
+    fn yaml_documents() {
+        let data = "# comment\n---\nschema: olm.package\nname: etcd\n---\n---\n# empty\n---\nschema: olm.channel\nname: stable\npackage: etcd\nentries: []\n";
+        let dir = catalog_dir("yaml", &[("catalog.yaml", data)]);
+        let dc = read_operator_catalog(dir.to_string_lossy().to_string()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names(&dc), vec!["etcd", "stable"]);
+    }
+
+    #[test]
+    fn yaml_error_line_after_empty_documents() {
+        let data = "---\n---\nschema: olm.package\nname: etcd\n---\n\n---\nschema: olm.channel\nname: [stable\n";
+        let dir = catalog_dir("yaml-error", &[("catalog.yaml", data)]);
+        let error = read_operator_catalog(dir.to_string_lossy().to_string()).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(error.to_string().contains("catalog.yaml:10:"), "{}", error);
+    }
+
+    #[test]
+    fn yaml_document_end_markers() {
+        let data = "schema: olm.package\nname: etcd\n...\n---\nschema: olm.channel\nname: stable\npackage: etcd\nentries: []\n... # end\nschema: olm.channel\nname: fast\npackage: etcd\nentries: []\n...\n";
+        let dir = catalog_dir("yaml-end", &[("catalog.yaml", data)]);
+        let dc = read_operator_catalog(dir.to_string_lossy().to_string()).unwrap();
+        assert_eq!(names(&dc), vec!["etcd", "stable", "fast"]);
+
+        // lines after an end marker keep their numbers
+        let data = "schema: olm.package\nname: etcd\n...\nschema: olm.channel\nname: [stable\n";
+        fs::write(dir.join("catalog.yaml"), data).unwrap();
+        let error = read_operator_catalog(dir.to_string_lossy().to_string()).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(error.to_string().contains("catalog.yaml:6:"), "{}", error);
+    }
+
+    #[test]
+    fn root_indexignore() {
+        let dir = catalog_dir(
+            "root-ignore",
+            &[
+                (".indexignore", "**/tests/\ndeprecated/\n"),
+                ("etcd/catalog.json", PACKAGE),
+                ("etcd/channel.json", CHANNEL),
+                ("etcd/tests/fixture.json", "not json"),
+                ("deprecated/catalog.json", "not json"),
+            ],
+        );
+        let package = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let dc = read_operator_catalog(package("etcd")).unwrap();
+        assert_eq!(names(&dc), vec!["etcd", "stable"]);
+        assert!(read_operator_catalog(package("deprecated")).unwrap().is_empty());
+        assert!(is_ignored_package(&dir.join("deprecated")).unwrap());
+        assert!(is_ignored_package(&dir.join(".indexignore")).unwrap());
+        assert!(!is_ignored_package(&dir.join("etcd")).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn indexignore() {
+        let dir = catalog_dir(
+            "ignore",
+            &[
+                (".indexignore", "# local files\n*.md.json\ntests/\n/etcd/extra.json\n"),
+                ("etcd/catalog.json", PACKAGE),
+                ("etcd/channel.json", CHANNEL),
+                ("etcd/extra.json", "not json"),
+                ("etcd/README.md.json", "not json"),
+                ("etcd/tests/fixture.json", "not json"),
+            ],
+        );
+        let dc = read_operator_catalog(dir.to_string_lossy().to_string()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names(&dc), vec!["etcd", "stable"]);
+    }
+
+    #[test]
+    fn glob_patterns() {
+        for (pattern, text, matched) in [
+            ("*.json", "catalog.json", true),
+            ("*.json", "a/catalog.json", false),
+            ("a/*.json", "a/catalog.json", true),
+            ("**/catalog.json", "catalog.json", true),
+            ("**/catalog.json", "a/b/catalog.json", true),
+            ("**/catalog.json", "a/bcatalog.json", false),
+            ("a/**", "a/b/c.json", true),
+            ("c?t.json", "cat.json", true),
+            ("c?t.json", "c/t.json", false),
+        ] {
+            assert_eq!(glob_match(pattern.as_bytes(), text.as_bytes()), matched, "{} {}", pattern, text);
+        }
+    }
 }
@@ -1 +1,2 @@
 pub mod catalogs;
+pub mod resources;
@@ -0,0 +1,314 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::image::copy::*;
+use crate::image::source::*;
+use crate::log::logging::*;
+use crate::mirror::related::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// get_repository - strip the tag or digest from an image reference
+fn get_repository(image: &str) -> String {
+    let img_ref = parse_image(image.to_string());
+    let mut repo = img_ref.registry;
+    if !img_ref.namespace.is_empty() {
+        repo.push('/');
+        repo.push_str(&img_ref.namespace);
+    }
+    repo.push('/');
+    repo.push_str(&img_ref.name);
+    repo
+}
+
+// get_mirror_sources - group the source -> destination mappings by repository
+// 'digest' selects images pulled by digest, otherwise images pulled by tag
+pub fn get_mirror_sources(images: &[MirroredImage], digest: bool) -> Vec<MirrorSource> {
+    let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for img in images {
+        if !img.mirrored || img.source.contains('@') != digest {
+            continue;
+        }
+        let mirror = get_repository(&img.destination);
+        let mirrors = sources.entry(get_repository(&img.source)).or_default();
+        if !mirrors.contains(&mirror) {
+            mirrors.push(mirror);
+        }
+    }
+    sources
+        .into_iter()
+        .map(|(source, mirrors)| MirrorSource { source, mirrors })
+        .collect()
+}
+
+// generate_icsp - ImageContentSourcePolicy for clusters before 4.13
+pub fn generate_icsp(name: String, images: &[MirroredImage]) -> ImageContentSourcePolicy {
+    ImageContentSourcePolicy {
+        api_version: "operator.openshift.io/v1alpha1".to_string(),
+        kind: "ImageContentSourcePolicy".to_string(),
+        metadata: ObjectMeta {
+            name,
+            namespace: None,
+        },
+        spec: ImageContentSourcePolicySpec {
+            repository_digest_mirrors: get_mirror_sources(images, true),
+        },
+    }
+}
+
+// generate_idms - ImageDigestMirrorSet for images pulled by digest
+pub fn generate_idms(name: String, images: &[MirroredImage]) -> ImageMirrorSet {
+    ImageMirrorSet {
+        api_version: "config.openshift.io/v1".to_string(),
+        kind: "ImageDigestMirrorSet".to_string(),
+        metadata: ObjectMeta {
+            name,
+            namespace: None,
+        },
+        spec: ImageMirrorSetSpec {
+            image_digest_mirrors: Some(get_mirror_sources(images, true)),
+            image_tag_mirrors: None,
+        },
+    }
+}
+
+// generate_itms - ImageTagMirrorSet for images pulled by tag
+pub fn generate_itms(name: String, images: &[MirroredImage]) -> ImageMirrorSet {
+    ImageMirrorSet {
+        api_version: "config.openshift.io/v1".to_string(),
+        kind: "ImageTagMirrorSet".to_string(),
+        metadata: ObjectMeta {
+            name,
+            namespace: None,
+        },
+        spec: ImageMirrorSetSpec {
+            image_digest_mirrors: None,
+            image_tag_mirrors: Some(get_mirror_sources(images, false)),
+        },
+    }
+}
+
+// get_catalog_destination - the catalog image in the destination registry
+// local sources (oci:, docker-archive:, dir:) have no repository, the name of
+// the source path and the oci tag (default latest) are used instead
+pub fn get_catalog_destination(catalog: &str, destination: String) -> String {
+    if !is_local_source(catalog) {
+        return get_destination(destination, parse_image(catalog.to_string()));
+    }
+    let (transport, path) = catalog.split_once(':').unwrap_or_default();
+    let (path, tag) = match path.rsplit_once(':') {
+        Some((path, tag)) if transport == "oci" && !tag.contains('/') => (path, tag),
+        _ => (path, "latest"),
+    };
+    let name = Path::new(path.trim_end_matches('/'))
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or("catalog".to_string());
+    format!("{}/{}:{}", destination.trim_end_matches('/'), name, tag)
+}
+
+// generate_catalog_source - CatalogSource pointing at the catalog image in the destination
+pub fn generate_catalog_source(catalog: String, destination: String) -> CatalogSource {
+    let image = get_catalog_destination(&catalog, destination);
+    let img_ref = parse_image(image.clone());
+    CatalogSource {
+        api_version: "operators.coreos.com/v1alpha1".to_string(),
+        kind: "CatalogSource".to_string(),
+        metadata: ObjectMeta {
+            name: format!("cs-{}", img_ref.name),
+            namespace: Some("openshift-marketplace".to_string()),
+        },
+        spec: CatalogSourceSpec {
+            source_type: "grpc".to_string(),
+            image,
+        },
+    }
+}
+
+// get_mirror_set_files - the ImageContentSourcePolicy, ImageDigestMirrorSet
+// and ImageTagMirrorSet for 'images', file names start with 'prefix'
+// a resource without mappings (e.g. no image pulled by tag) is not written
+fn get_mirror_set_files(
+    name: &str,
+    prefix: &str,
+    images: &[MirroredImage],
+) -> Result<Vec<(String, String)>, MirrorError> {
+    let mut files = vec![];
+    if !get_mirror_sources(images, true).is_empty() {
+        let icsp = generate_icsp(name.to_string(), images);
+        let idms = generate_idms(name.to_string(), images);
+        files.push((
+            format!("{}imageContentSourcePolicy.yaml", prefix),
+            serde_yaml::to_string(&icsp)?,
+        ));
+        files.push((
+            format!("{}imageDigestMirrorSet.yaml", prefix),
+            serde_yaml::to_string(&idms)?,
+        ));
+    } else {
+        log_debug(&format!("no images pulled by digest for {}", name));
+    }
+    if !get_mirror_sources(images, false).is_empty() {
+        let itms = generate_itms(name.to_string(), images);
+        files.push((
+            format!("{}imageTagMirrorSet.yaml", prefix),
+            serde_yaml::to_string(&itms)?,
+        ));
+    } else {
+        log_debug(&format!("no images pulled by tag for {}", name));
+    }
+    Ok(files)
+}
+
+// write_resources - write all cluster resources for a mirror run to 'dir'
+// the catalog image is not copied by a mirror run (pack and publish push it),
+// its mapping is added to the mirror sets but not to the report, a local
+// catalog (source or 'configs' directory) has no repository to map
+pub fn write_resources(
+    report: &MirrorReport,
+    destination: String,
+    dir: String,
+) -> Result<(), MirrorError> {
+    let cs = generate_catalog_source(report.catalog.clone(), destination);
+    log_warn(&format!(
+        "catalog {} is not mirrored, the CatalogSource expects it at {} (use pack and publish)",
+        report.catalog, cs.spec.image
+    ));
+    let mut images = report.images.clone();
+    if !is_local_source(&report.catalog) && !Path::new(&report.catalog).is_dir() {
+        images.push(MirroredImage {
+            name: "catalog".to_string(),
+            source: report.catalog.clone(),
+            destination: cs.spec.image.clone(),
+            digest: String::new(),
+            mirrored: true,
+        });
+    }
+    let mut files = get_mirror_set_files("operator-0", "", &images)?;
+    files.push((
+        format!("catalogSource-{}.yaml", cs.metadata.name),
+        serde_yaml::to_string(&cs)?,
+    ));
+    write_files(files, dir)
+}
+
+// write_release_resources - write the mirror sets for the release payloads
+// and their component images to 'dir' (release-*.yaml, no CatalogSource)
+pub fn write_release_resources(report: &MirrorReport, dir: String) -> Result<(), MirrorError> {
+    let files = get_mirror_set_files("release-0", "release-", &report.images)?;
+    write_files(files, dir)
+}
+
+// write_files - write each (file, yaml) pair to 'dir'
+fn write_files(files: Vec<(String, String)>, dir: String) -> Result<(), MirrorError> {
+    fs::create_dir_all(&dir).context(format!("creating {}", dir))?;
+    for (file, yaml) in files {
+        fs::write(dir.clone() + &file, yaml).context(format!("writing {}{}", dir, file))?;
+        log_info(&format!("written {}{}", dir, file));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_source_image_from_destination() {
+        for (catalog, image) in [
+            (
+                "registry.redhat.io/redhat/redhat-operator-index:v4.14",
+                "mirror.example.com:5000/ocp/redhat/redhat-operator-index:v4.14",
+            ),
+            (
+                "oci:/tmp/catalogs/my-index:v1",
+                "mirror.example.com:5000/ocp/my-index:v1",
+            ),
+            ("oci:/tmp/catalogs/my-index", "mirror.example.com:5000/ocp/my-index:latest"),
+            ("docker-archive:/tmp/index.tar", "mirror.example.com:5000/ocp/index:latest"),
+            ("dir:/tmp/catalogs/custom/", "mirror.example.com:5000/ocp/custom:latest"),
+        ] {
+            let cs = generate_catalog_source(
+                catalog.to_string(),
+                "mirror.example.com:5000/ocp".to_string(),
+            );
+            assert_eq!(cs.spec.image, image, "{}", catalog);
+        }
+    }
+
+    #[test]
+    fn mirror_sets_with_the_catalog() {
+        let dir = std::env::temp_dir().join(format!("resources-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dir = dir.to_string_lossy().to_string() + "/";
+        let image = |source: &str, destination: &str| MirroredImage {
+            name: String::new(),
+            source: source.to_string(),
+            destination: destination.to_string(),
+            digest: String::new(),
+            mirrored: true,
+        };
+        let mut report = MirrorReport {
+            catalog: "registry.redhat.io/redhat/redhat-operator-index:v4.14".to_string(),
+            total: 1,
+            unique: 1,
+            mirrored: 1,
+            failed: 0,
+            images: vec![image(
+                "quay.io/a/operator@sha256:1111",
+                "mirror.example.com/a/operator@sha256:1111",
+            )],
+        };
+        let files = |dir: &str| {
+            let mut names: Vec<String> = fs::read_dir(dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // the catalog (pulled by tag) is the only tag mapping
+        write_resources(&report, "mirror.example.com".to_string(), dir.clone()).unwrap();
+        assert_eq!(
+            files(&dir),
+            vec![
+                "catalogSource-cs-redhat-operator-index.yaml",
+                "imageContentSourcePolicy.yaml",
+                "imageDigestMirrorSet.yaml",
+                "imageTagMirrorSet.yaml",
+            ]
+        );
+        let yaml = fs::read_to_string(dir.clone() + "imageTagMirrorSet.yaml").unwrap();
+        let itms: ImageMirrorSet = serde_yaml::from_str(&yaml).unwrap();
+        let mirrors = itms.spec.image_tag_mirrors.unwrap();
+        assert_eq!(mirrors.len(), 1);
+        assert_eq!(mirrors[0].source, "registry.redhat.io/redhat/redhat-operator-index");
+        assert_eq!(mirrors[0].mirrors, vec!["mirror.example.com/redhat/redhat-operator-index"]);
+        // the report is left as is (push only pushes what was mirrored)
+        assert_eq!(report.images.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // a local catalog has no mapping, no empty ImageTagMirrorSet is written
+        report.catalog = "oci:/tmp/catalogs/my-index:v1".to_string();
+        write_resources(&report, "mirror.example.com".to_string(), dir.clone()).unwrap();
+        assert_eq!(
+            files(&dir),
+            vec![
+                "catalogSource-cs-my-index.yaml",
+                "imageContentSourcePolicy.yaml",
+                "imageDigestMirrorSet.yaml",
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        // releases pulled by tag only have an ImageTagMirrorSet
+        report.images = vec![image(
+            "quay.io/ocp/release:4.14.1",
+            "mirror.example.com/ocp/release:4.14.1",
+        )];
+        write_release_resources(&report, dir.clone()).unwrap();
+        assert_eq!(files(&dir), vec!["release-imageTagMirrorSet.yaml"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use std::fs;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::log::logging::*;
+use crate::log::progress::*;
+
+// collect_garbage - remove the blobs of the mirror directory 'dir' that no
+// manifest in 'dir/manifests' refers to (left behind by failed or replaced
+// mirror runs), with 'dry_run' the blobs are only reported
+pub fn collect_garbage(dir: String, dry_run: bool) -> Result<GcReport, MirrorError> {
+    let manifests_dir = dir.clone() + "manifests/";
+    let blobs_dir = dir + "blobs/sha256/";
+    let mut referenced = HashSet::new();
+    let manifests = fs::read_dir(&manifests_dir).context(format!("reading {}", manifests_dir))?;
+    for entry in manifests {
+        let file = entry?.path();
+        let data = fs::read_to_string(&file).context(format!("reading {}", file.display()))?;
+        for digest in get_referenced_blobs(data).context(format!("parsing {}", file.display()))? {
+            referenced.insert(digest.trim_start_matches("sha256:").to_string());
+        }
+    }
+
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+    let blobs = match fs::read_dir(&blobs_dir) {
+        Ok(blobs) => blobs,
+        Err(_) => return Ok(report),
+    };
+    for entry in blobs {
+        let entry = entry?;
+        let hex = entry.file_name().to_string_lossy().to_string();
+        report.blobs += 1;
+        if referenced.contains(&hex) {
+            continue;
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or_default();
+        log_debug(&format!("unreferenced blob {} ({})", hex, format_bytes(size)));
+        if !dry_run {
+            fs::remove_file(entry.path()).context(format!("removing blob {}", hex))?;
+        }
+        report.removed += 1;
+        report.bytes += size;
+    }
+    log_info(&format!(
+        "{} {} of {} blobs ({})",
+        if dry_run { "would remove" } else { "removed" },
+        report.removed,
+        report.blobs,
+        format_bytes(report.bytes)
+    ));
+    Ok(report)
+}
+
+// get_referenced_blobs - the config and layer digests of a (docker v2, oci or
+// schema 1) manifest, manifest lists refer to other manifests only
+fn get_referenced_blobs(data: String) -> Result<Vec<String>, MirrorError> {
+    let manifest = parse_json_manifest_v2(data.clone())?;
+    if manifest.schema_version == 1 {
+        let v1 = parse_json_manifest(data)?;
+        return Ok(v1.fs_layers.into_iter().map(|l| l.blob_sum).collect());
+    }
+    let mut digests: Vec<String> = manifest
+        .layers
+        .unwrap_or_default()
+        .into_iter()
+        .map(|l| l.digest)
+        .collect();
+    if let Some(config) = manifest.config {
+        digests.push(config.digest);
+    }
+    Ok(digests)
+}
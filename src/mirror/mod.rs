@@ -0,0 +1,80 @@
+//! Mirroring operators and carrying the mirrored content across an air gap.
+
+pub(crate) mod gc;
+pub(crate) mod related;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::archive::pack::*;
+use related::*;
+
+pub use crate::api::schema::{ArchiveFile, ArchiveMetadata, MirrorReport, MirroredImage};
+
+/// Mirror the bundles and related images of 'packages' (every package when
+/// empty) of the catalog in 'configs_dir' into the layout in 'dir', the
+/// report lists each image with its location below 'destination'.
+pub async fn mirror_operators(
+    catalog: &str,
+    configs_dir: &str,
+    packages: &[&str],
+    destination: &str,
+    dir: &str,
+) -> Result<MirrorReport, MirrorError> {
+    let packages = packages
+        .iter()
+        .map(|name| Image {
+            name: name.to_string(),
+        })
+        .collect();
+    let selected = select_packages(configs_dir.to_string(), "all".to_string(), Some(packages))?;
+    let images = collect_related_images(configs_dir.to_string(), selected);
+    let total = images.len();
+    Ok(mirror_related_images(
+        catalog.to_string(),
+        total,
+        dedupe_related_images(images),
+        destination.to_string(),
+        dir.trim_end_matches('/').to_string(),
+    )
+    .await)
+}
+
+/// Pack the mirrored content of 'working_dir' into archives of at most
+/// 'size' MiB in 'archive_dir', with a 'storage' directory only what was not
+/// packed in an earlier sequence is packed.
+pub fn pack(
+    working_dir: &str,
+    archive_dir: &str,
+    size: u64,
+    destination: &str,
+    storage: Option<&str>,
+) -> Result<ArchiveMetadata, MirrorError> {
+    pack_mirror(
+        working_dir.to_string(),
+        archive_dir.to_string(),
+        size,
+        destination.to_string(),
+        storage.map(|s| s.to_string()),
+    )
+}
+
+/// Extract and verify the archives in 'archive_dir' into 'working_dir' in
+/// sequence order, returns the sets applied.
+pub fn unpack(archive_dir: &str, working_dir: &str) -> Result<Vec<ArchiveMetadata>, MirrorError> {
+    unpack_archives(archive_dir.to_string(), working_dir.to_string())
+}
+
+/// Push the images of the unpacked 'sets' to their destination, or below
+/// 'destination' when it is set.
+pub async fn publish(
+    sets: &[ArchiveMetadata],
+    destination: Option<&str>,
+    working_dir: &str,
+) -> MirrorReport {
+    publish_images(
+        sets,
+        destination.map(|d| d.to_string()),
+        working_dir.to_string(),
+    )
+    .await
+}
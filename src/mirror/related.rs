@@ -0,0 +1,329 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::image::copy::*;
+use crate::log::logging::*;
+use crate::manifests::catalogs::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::Instant;
+
+// select_packages - decide which operator packages to work with
+// the filter flag wins, then the packages in the image set config,
+// otherwise every package in the catalog (not ignored by its '.indexignore')
+pub fn select_packages(
+    dir: String,
+    filter: String,
+    packages: Option<Vec<Image>>,
+) -> Result<Vec<String>, MirrorError> {
+    if filter != "all" {
+        return Ok(vec![filter]);
+    }
+    if let Some(pkgs) = packages {
+        if !pkgs.is_empty() {
+            return Ok(pkgs.into_iter().map(|p| p.name).collect());
+        }
+    }
+    let mut names = vec![];
+    for path in fs::read_dir(&dir).context(format!("reading packages in {}", dir))? {
+        let entry = path.context(format!("reading packages in {}", dir))?;
+        if !is_ignored_package(&entry.path())? {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// collect_related_images - read the catalog of each selected package and
+// gather every bundle image together with its related (operand) images
+pub fn collect_related_images(dir: String, packages: Vec<String>) -> Vec<RelatedImage> {
+    let mut images = vec![];
+    for pkg in packages {
+        let dc = match read_operator_catalog(dir.clone() + "/" + &pkg) {
+            Ok(dc) => dc,
+            Err(error) => {
+                log_error(&format!("reading catalog for {} : {}", pkg, error));
+                continue;
+            }
+        };
+        for obj in dc {
+            let bundle = match obj {
+                DeclarativeConfig::Bundle(bundle) => bundle,
+                _ => continue,
+            };
+            // bundles without an image only carry their objects in the catalog
+            if !bundle.image.is_empty() {
+                images.push(RelatedImage {
+                    name: bundle.name.clone(),
+                    image: bundle.image,
+                });
+            }
+            for ri in bundle.related_images {
+                images.push(ri);
+            }
+        }
+    }
+    images
+}
+
+// dedupe_related_images - remove duplicates, images referenced by digest
+// are considered equal if repository and digest are the same, the same
+// digest in another repository is kept so its mapping ends up in the report
+// (mirror_related_images only copies each digest once)
+pub fn dedupe_related_images(images: Vec<RelatedImage>) -> Vec<RelatedImage> {
+    let mut seen = HashSet::new();
+    let mut unique = vec![];
+    for img in images {
+        let key = match img.image.split_once('@') {
+            Some((repository, digest)) => (repository.to_string(), digest.to_string()),
+            None => (img.image.clone(), String::new()),
+        };
+        if seen.insert(key) {
+            unique.push(img);
+        }
+    }
+    unique
+}
+
+// mirror_related_images - copy each image to the mirror directory
+// and return a report of the run, a digest already copied for another
+// repository is not downloaded again but still gets its own destination
+pub async fn mirror_related_images(
+    catalog: String,
+    total: usize,
+    images: Vec<RelatedImage>,
+    destination: String,
+    dir: String,
+) -> MirrorReport {
+    let mut copied: HashMap<String, String> = HashMap::new();
+    let mut report = MirrorReport {
+        catalog,
+        total,
+        unique: images.len(),
+        mirrored: 0,
+        failed: 0,
+        images: vec![],
+    };
+    for img in images {
+        let img_ref = parse_image(img.image.clone());
+        let target = get_destination(destination.clone(), img_ref.clone());
+        if let Some(digest) = copied.get(&img_ref.version) {
+            log_debug(&format!("{} already mirrored as {}", img.image, digest));
+            report.mirrored += 1;
+            report.images.push(MirroredImage {
+                name: img.name,
+                source: img.image,
+                destination: target,
+                digest: digest.clone(),
+                mirrored: true,
+            });
+            continue;
+        }
+        log_info(&format!("mirroring {}", img.image));
+        let start = Instant::now();
+        let version = img_ref.version.clone();
+        let (digest, mirrored) = match copy_image(img_ref, dir.clone()).await {
+            Ok(digest) => {
+                log_event(
+                    LogLevel::Info,
+                    &format!("mirrored {}", img.image),
+                    LogFields {
+                        image: Some(img.image.clone()),
+                        digest: Some(digest.clone()),
+                        duration_ms: Some(start.elapsed().as_millis() as u64),
+                        ..Default::default()
+                    },
+                );
+                report.mirrored += 1;
+                if version.starts_with("sha256:") {
+                    copied.insert(version, digest.clone());
+                }
+                (digest, true)
+            }
+            Err(error) => {
+                log_error(&format!("mirroring {} : {}", img.image, error));
+                report.failed += 1;
+                (String::new(), false)
+            }
+        };
+        report.images.push(MirroredImage {
+            name: img.name,
+            source: img.image,
+            destination: target,
+            digest,
+            mirrored,
+        });
+    }
+    log_transfer_stats();
+    report
+}
+
+// get_destination - map a source image to its location in the destination
+// registry, the repository path (namespace/name) is preserved
+pub fn get_destination(destination: String, img_ref: ImageReference) -> String {
+    let mut dest = destination.trim_end_matches('/').to_string();
+    dest.push('/');
+    if !img_ref.namespace.is_empty() {
+        dest.push_str(&img_ref.namespace);
+        dest.push('/');
+    }
+    dest.push_str(&img_ref.name);
+    if img_ref.version.starts_with("sha256:") {
+        dest.push('@');
+    } else {
+        dest.push(':');
+    }
+    dest.push_str(&img_ref.version);
+    dest
+}
+
+// write_report - persist the report and display a summary
+pub fn write_report(report: &MirrorReport, file: String) -> Result<(), MirrorError> {
+    let json = serde_json::to_string_pretty(report)?;
+    if let Some(parent) = std::path::Path::new(&file).parent() {
+        fs::create_dir_all(parent).context(format!("creating {}", parent.display()))?;
+    }
+    fs::write(&file, json).context(format!("writing {}", file))?;
+    log_hi(&format!("mirror summary for {}", report.catalog));
+    log_lo(&format!("  related images found => {}", report.total));
+    log_lo(&format!("  unique images        => {}", report.unique));
+    log_mid(&format!("  mirrored             => {}", report.mirrored));
+    log_ex(&format!("  failed               => {}", report.failed));
+    log_info(&format!("report written to {}", file));
+    Ok(())
+}
+
+// read_reports - the mirrored images of every report (related images and
+// releases) in the mirror directory 'dir'
+pub fn read_reports(dir: String) -> Vec<MirroredImage> {
+    let mut images = vec![];
+    let mut files: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return images,
+    };
+    files.sort();
+    for file in files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if name != "related-images.json" && !name.starts_with("release-") {
+            continue;
+        }
+        let report: Option<MirrorReport> = fs::read_to_string(&file)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok());
+        match report {
+            Some(report) => images.extend(report.images.into_iter().filter(|i| i.mirrored)),
+            None => log_warn(&format!("skipping unreadable report {}", file.display())),
+        }
+    }
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn related(image: &str) -> RelatedImage {
+        RelatedImage {
+            name: String::new(),
+            image: image.to_string(),
+        }
+    }
+
+    #[test]
+    fn dedupe_keeps_every_repository_of_a_digest() {
+        let images = vec![
+            related("quay.io/a/operator@sha256:1111"),
+            related("quay.io/a/operator@sha256:1111"),
+            related("registry.example.com/b/operator@sha256:1111"),
+            related("quay.io/a/operand:v1"),
+            related("quay.io/a/operand:v1"),
+        ];
+        let unique: Vec<String> = dedupe_related_images(images)
+            .into_iter()
+            .map(|img| img.image)
+            .collect();
+        assert_eq!(
+            unique,
+            vec![
+                "quay.io/a/operator@sha256:1111",
+                "registry.example.com/b/operator@sha256:1111",
+                "quay.io/a/operand:v1",
+            ]
+        );
+    }
+
+    #[test]
+    fn packages_of_a_catalog() {
+        let dir = std::env::temp_dir().join(format!("packages-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for name in ["etcd", "deprecated", "argocd"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join(".indexignore"), "deprecated/\n").unwrap();
+        let configs = dir.to_string_lossy().to_string();
+        let select = |filter: &str, packages: Option<Vec<Image>>| {
+            select_packages(configs.clone(), filter.to_string(), packages).unwrap()
+        };
+        assert_eq!(select("all", None), vec!["argocd", "etcd"]);
+        assert_eq!(select("all", Some(vec![])), vec!["argocd", "etcd"]);
+        assert_eq!(select("etcd", None), vec!["etcd"]);
+        let image = Image {
+            name: "argocd".to_string(),
+        };
+        assert_eq!(select("all", Some(vec![image])), vec!["argocd"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_of_mirror_and_release_runs() {
+        let dir = std::env::temp_dir().join(format!("reports-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dir = dir.to_string_lossy().to_string() + "/";
+        let image = |source: &str, mirrored: bool| MirroredImage {
+            name: String::new(),
+            source: source.to_string(),
+            destination: String::new(),
+            digest: String::new(),
+            mirrored,
+        };
+        for (file, images) in [
+            (
+                "related-images.json",
+                vec![
+                    image("quay.io/a/operator@sha256:1111", true),
+                    image("quay.io/a/operand:v1", false),
+                ],
+            ),
+            (
+                "release-4.14.1-amd64.json",
+                vec![image("quay.io/ocp/release@sha256:2222", true)],
+            ),
+            ("release-graph-image.json", vec![image("graph-data", true)]),
+            ("push-report.json", vec![image("quay.io/a/pushed:v1", true)]),
+        ] {
+            let report = MirrorReport {
+                catalog: file.to_string(),
+                total: images.len(),
+                unique: images.len(),
+                mirrored: 0,
+                failed: 0,
+                images,
+            };
+            write_report(&report, dir.clone() + file).unwrap();
+        }
+        fs::write(dir.clone() + "release-broken.json", "{").unwrap();
+        let sources: Vec<String> = read_reports(dir.clone())
+            .into_iter()
+            .map(|img| img.source)
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                "quay.io/a/operator@sha256:1111",
+                "quay.io/ocp/release@sha256:2222",
+                "graph-data",
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+use reqwest::{Response, StatusCode};
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::image::copy::*;
+use crate::registry::client::*;
+
+// page size requested from the paginated apis (registries may return less)
+const PAGE_SIZE: u32 = 1000;
+
+// media type of the referrers response (an oci image index)
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+// get_repository_url - base url of the repository in 'img_ref'
+// e.g. https://registry.redhat.io/v2/redhat/redhat-operator-index/
+fn get_repository_url(img_ref: &ImageReference) -> String {
+    let mut url = get_registry_url(&img_ref.registry);
+    if !img_ref.namespace.is_empty() {
+        url = url + &img_ref.namespace + "/";
+    }
+    url + &img_ref.name + "/"
+}
+
+// get_registry_url - base url of the v2 api of 'registry'
+fn get_registry_url(registry: &str) -> String {
+    format!("{}://{}/v2/", get_registry_scheme(registry), registry)
+}
+
+// registry_request - send a request (anonymous when there is no token)
+async fn registry_request(
+    method: reqwest::Method,
+    url: &str,
+    token: &str,
+    accept: &str,
+) -> Result<Response, MirrorError> {
+    let mut req = get_registry_client(url)?.request(method, url);
+    if !accept.is_empty() {
+        req = req.header("Accept", accept);
+    }
+    if !token.is_empty() {
+        req = req.bearer_auth(token);
+    }
+    Ok(req.send().await?)
+}
+
+// get_next_link - the url of the next page from the 'Link' header
+fn get_next_link(resp: &Response, registry: &str) -> Option<String> {
+    let link = resp.headers().get("Link")?.to_str().ok()?;
+    parse_next_link(link, registry)
+}
+
+// parse_next_link - the rel="next" url of a 'Link' header value, e.g.
+// </v2/_catalog?last=b&n=100>; rel="next", relative urls are on 'registry'
+fn parse_next_link(link: &str, registry: &str) -> Option<String> {
+    let next = link.split(',').find(|l| l.contains("rel=\"next\""))?;
+    let url = next.split_once('<')?.1.split_once('>')?.0;
+    match url.starts_with('/') {
+        true => Some(format!(
+            "{}://{}{}",
+            get_registry_scheme(registry),
+            registry,
+            url
+        )),
+        false => Some(url.to_string()),
+    }
+}
+
+// get_tags - all tags of the repository in 'img_ref' (following the pages)
+pub async fn get_tags(
+    img_ref: &ImageReference,
+    token: &str,
+) -> Result<Vec<String>, MirrorError> {
+    let mut tags = vec![];
+    let mut url = Some(format!(
+        "{}tags/list?n={}",
+        get_repository_url(img_ref),
+        PAGE_SIZE
+    ));
+    while let Some(next) = url {
+        let resp = registry_request(reqwest::Method::GET, &next, token, "").await?;
+        let resp = check_response(resp).await?;
+        url = get_next_link(&resp, &img_ref.registry);
+        let list: TagList = serde_json::from_str(&resp.text().await?)
+            .map_err(|why| MirrorError::Parse(format!("tags list {}: {}", next, why)))?;
+        tags.extend(list.tags.unwrap_or_default());
+    }
+    Ok(tags)
+}
+
+// get_repositories - all repositories of 'registry' (_catalog api, following the pages)
+pub async fn get_repositories(registry: &str, token: &str) -> Result<Vec<String>, MirrorError> {
+    let mut repositories = vec![];
+    let mut url = Some(format!(
+        "{}_catalog?n={}",
+        get_registry_url(registry),
+        PAGE_SIZE
+    ));
+    while let Some(next) = url {
+        let resp = registry_request(reqwest::Method::GET, &next, token, "").await?;
+        let resp = check_response(resp).await?;
+        url = get_next_link(&resp, registry);
+        let list: RepositoryList = serde_json::from_str(&resp.text().await?)
+            .map_err(|why| MirrorError::Parse(format!("repository list {}: {}", next, why)))?;
+        repositories.extend(list.repositories.unwrap_or_default());
+    }
+    Ok(repositories)
+}
+
+// head_manifest - digest, media type and size of a manifest without
+// downloading it, falls back to a GET when the registry does not
+// return the Docker-Content-Digest header
+pub async fn head_manifest(
+    img_ref: &ImageReference,
+    token: &str,
+) -> Result<ManifestDescriptor, MirrorError> {
+    let url = get_image_manifest_url(img_ref.clone());
+    let accept = MANIFEST_MEDIA_TYPES.join(",");
+    let resp = registry_request(reqwest::Method::HEAD, &url, token, &accept).await?;
+    let resp = check_response(resp).await?;
+    let header = |name: &str| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let media_type = header("Content-Type").unwrap_or_default();
+    if let Some(digest) = header("Docker-Content-Digest") {
+        return Ok(ManifestDescriptor {
+            digest,
+            media_type,
+            size: header("Content-Length")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or_default(),
+        });
+    }
+    let resp = registry_request(reqwest::Method::GET, &url, token, &accept).await?;
+    let data = check_response(resp).await?.bytes().await?;
+    Ok(ManifestDescriptor {
+        digest: get_digest(&data),
+        media_type,
+        size: data.len() as i64,
+    })
+}
+
+// get_referrers - the manifests referring to 'digest' (signatures, sboms,
+// attestations) using the oci 1.1 referrers api, registries without it
+// are queried with the 'sha256-<hex>' tag schema
+pub async fn get_referrers(
+    img_ref: &ImageReference,
+    digest: &str,
+    token: &str,
+) -> Result<Vec<Layer>, MirrorError> {
+    let url = format!("{}referrers/{}", get_repository_url(img_ref), digest);
+    let resp = registry_request(reqwest::Method::GET, &url, token, OCI_INDEX).await?;
+    let is_index = resp
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with(OCI_INDEX))
+        .unwrap_or(false);
+    let resp = match resp.status() {
+        StatusCode::OK if is_index => resp,
+        StatusCode::OK | StatusCode::NOT_FOUND => {
+            let mut tag_ref = img_ref.clone();
+            tag_ref.version = digest.replace(':', "-");
+            let resp = registry_request(
+                reqwest::Method::GET,
+                &get_image_manifest_url(tag_ref),
+                token,
+                OCI_INDEX,
+            )
+            .await?;
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Ok(vec![]);
+            }
+            check_response(resp).await?
+        }
+        _ => check_response(resp).await?,
+    };
+    let index = parse_json_manifest_v2(resp.text().await?)?;
+    Ok(index.manifests.unwrap_or_default())
+}
+
+// get_manifest_data - the manifest (or manifest list / index) of 'img_ref'
+pub async fn get_manifest_data(
+    img_ref: &ImageReference,
+    token: &str,
+) -> Result<String, MirrorError> {
+    let url = get_image_manifest_url(img_ref.clone());
+    let resp = registry_request(
+        reqwest::Method::GET,
+        &url,
+        token,
+        &MANIFEST_MEDIA_TYPES.join(","),
+    )
+    .await?;
+    let data = check_response(resp).await?.text().await?;
+    validate_manifest(&url, &data)?;
+    Ok(data)
+}
+
+// get_blob - download a (small) blob such as an image config
+pub async fn get_blob(
+    img_ref: &ImageReference,
+    digest: &str,
+    token: &str,
+) -> Result<Vec<u8>, MirrorError> {
+    let url = get_blobs_url(img_ref.clone()) + digest;
+    let resp = registry_request(reqwest::Method::GET, &url, token, "").await?;
+    let resp = check_response(resp).await?;
+    Ok(resp.bytes().await?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn next_links() {
+        let tests = vec![
+            (
+                r#"</v2/_catalog?last=b&n=100>; rel="next""#,
+                Some("https://quay.io/v2/_catalog?last=b&n=100"),
+            ),
+            (
+                r#"<https://cdn.quay.io/v2/a/tags/list?last=v1>; rel="next""#,
+                Some("https://cdn.quay.io/v2/a/tags/list?last=v1"),
+            ),
+            (
+                r#"</v2/a/tags/list?last=v0>; rel="prev", </v2/a/tags/list?last=v2>; rel="next""#,
+                Some("https://quay.io/v2/a/tags/list?last=v2"),
+            ),
+            (r#"</v2/a/tags/list?last=v0>; rel="prev""#, None),
+            ("</v2/a/tags/list?last=v2>", None),
+            ("", None),
+        ];
+        for (link, expected) in tests {
+            assert_eq!(
+                parse_next_link(link, "quay.io").as_deref(),
+                expected,
+                "{}",
+                link
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn referrers_from_the_tag_schema() {
+        // no referrers api, the signature index is tagged sha256-<hex>
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": "sha256:5166",
+                "size": 10,
+                "artifactType": "application/vnd.dev.cosign.simplesigning.v1+json",
+            }],
+        })
+        .to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let response = match request.split(' ').nth(1).unwrap_or_default() {
+                    "/v2/test/app/manifests/sha256-1111" => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        OCI_INDEX,
+                        index.len(),
+                        index
+                    ),
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        set_insecure_registry(&registry).await.unwrap();
+
+        let img_ref = parse_image(format!("{}/test/app:v1", registry));
+        let referrers = get_referrers(&img_ref, "sha256:1111", "").await.unwrap();
+        assert_eq!(referrers.len(), 1);
+        assert_eq!(referrers[0].digest, "sha256:5166");
+        assert_eq!(
+            referrers[0].artifact_type.as_deref(),
+            Some("application/vnd.dev.cosign.simplesigning.v1+json")
+        );
+        // neither the api nor the tag, no referrers
+        let referrers = get_referrers(&img_ref, "sha256:2222", "").await.unwrap();
+        assert!(referrers.is_empty());
+    }
+}
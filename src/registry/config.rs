@@ -0,0 +1,451 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::image::copy::*;
+use crate::log::logging::*;
+use crate::registry::client::*;
+
+// system wide registries.conf and drop-in directory
+const REGISTRIES_CONF: &str = "/etc/containers/registries.conf";
+const REGISTRIES_CONF_DIR: &str = "/etc/containers/registries.conf.d";
+
+// the merged registries.conf, loaded once at startup
+static REGISTRIES: OnceLock<RegistriesConf> = OnceLock::new();
+
+// load_registries_conf - read the registries.conf used for all pulls
+// an explicit 'path' (or $CONTAINERS_REGISTRIES_CONF) replaces the defaults,
+// otherwise ~/.config/containers/registries.conf or /etc/containers/registries.conf
+// is used, followed by the files in the registries.conf.d directories (name order)
+pub fn load_registries_conf(path: String) -> Result<(), MirrorError> {
+    let mut files = vec![];
+    let path = match path.is_empty() {
+        true => env::var("CONTAINERS_REGISTRIES_CONF").unwrap_or_default(),
+        false => path,
+    };
+    if !path.is_empty() {
+        files.push(PathBuf::from(path));
+    } else {
+        let user = env::var("HOME")
+            .map(|home| Path::new(&home).join(".config/containers/registries.conf"))
+            .ok();
+        match user {
+            Some(user) if user.exists() => files.push(user),
+            _ => files.push(PathBuf::from(REGISTRIES_CONF)),
+        }
+        let mut dirs = vec![PathBuf::from(REGISTRIES_CONF_DIR)];
+        if let Ok(home) = env::var("HOME") {
+            dirs.push(Path::new(&home).join(".config/containers/registries.conf.d"));
+        }
+        for dir in dirs {
+            if let Ok(entries) = fs::read_dir(dir) {
+                let mut conf: Vec<PathBuf> = entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().and_then(|ext| ext.to_str()) == Some("conf"))
+                    .collect();
+                conf.sort();
+                files.extend(conf);
+            }
+        }
+    }
+
+    let mut merged = RegistriesConf::default();
+    for file in files {
+        if !file.exists() {
+            continue;
+        }
+        let data = fs::read_to_string(&file).map_err(|why| {
+            MirrorError::Config(format!("couldn't open {}: {}", file.display(), why))
+        })?;
+        let conf: RegistriesConf = toml::from_str(&data)
+            .map_err(|why| MirrorError::Config(format!("parsing {}: {}", file.display(), why)))?;
+        log_debug(&format!("using registries.conf {}", file.display()));
+        merge_registries_conf(&mut merged, conf);
+    }
+    let _ = REGISTRIES.set(merged);
+    Ok(())
+}
+
+// merge_registries_conf - later files replace registries with the same prefix
+// and add to the version 1 lists
+fn merge_registries_conf(merged: &mut RegistriesConf, conf: RegistriesConf) {
+    for reg in conf.registries {
+        let prefix = get_prefix(&reg);
+        merged.registries.retain(|r| get_prefix(r) != prefix);
+        merged.registries.push(reg);
+    }
+    if let Some(v1) = conf.v1 {
+        let merged_v1 = merged.v1.get_or_insert_with(RegistriesV1::default);
+        for (from, to) in [
+            (v1.insecure, &mut merged_v1.insecure),
+            (v1.block, &mut merged_v1.block),
+        ] {
+            if let Some(list) = from {
+                to.get_or_insert_with(RegistryList::default)
+                    .registries
+                    .extend(list.registries);
+            }
+        }
+    }
+}
+
+// get_prefix - the prefix of a registry entry defaults to its location
+fn get_prefix(reg: &RegistryConf) -> String {
+    reg.prefix
+        .clone()
+        .or(reg.location.clone())
+        .unwrap_or_default()
+}
+
+// get_registries_conf - the loaded registries.conf (empty if none was loaded)
+pub fn get_registries_conf() -> RegistriesConf {
+    REGISTRIES.get().cloned().unwrap_or_default()
+}
+
+// prefix_matches - a prefix matches on a path boundary, '*.example.com'
+// matches any sub domain of example.com
+fn prefix_matches(prefix: &str, image: &str) -> bool {
+    if let Some(domain) = prefix.strip_prefix("*.") {
+        let host = image.split('/').next().unwrap_or_default();
+        return host.ends_with(&format!(".{}", domain));
+    }
+    image == prefix
+        || image
+            .strip_prefix(prefix)
+            .map(|rest| rest.starts_with('/'))
+            .unwrap_or(false)
+}
+
+// find_registry - the entry with the longest matching prefix
+fn find_registry<'a>(conf: &'a RegistriesConf, image: &str) -> Option<&'a RegistryConf> {
+    conf.registries
+        .iter()
+        .filter(|reg| prefix_matches(&get_prefix(reg), image))
+        .max_by_key(|reg| get_prefix(reg).len())
+}
+
+// rewrite_image - replace the matched prefix of 'image' with 'location'
+fn rewrite_image(prefix: &str, location: &str, image: &str, version: &str) -> ImageReference {
+    let path = match prefix.starts_with("*.") {
+        // wildcard prefixes only match the host, the location replaces it
+        true => match image.split_once('/') {
+            Some((_, rest)) => format!("{}/{}", location, rest),
+            None => location.to_string(),
+        },
+        false => location.to_string() + &image[prefix.len()..],
+    };
+    let separator = if version.starts_with("sha256:") {
+        "@"
+    } else {
+        ":"
+    };
+    parse_image(format!("{}{}{}", path, separator, version))
+}
+
+// get_pull_sources - the locations to try (in order) when pulling 'img_ref'
+// configured mirrors first then the (possibly rewritten) source location
+// an error is returned if the registry is blocked
+pub async fn get_pull_sources(img_ref: &ImageReference) -> Result<Vec<PullSource>, MirrorError> {
+    let sources = get_sources(&get_registries_conf(), img_ref)?;
+    // an unreachable insecure mirror is skipped, the source location is still tried
+    let mut reachable = vec![];
+    for source in sources {
+        if source.insecure {
+            match set_insecure_registry(&source.img_ref.registry).await {
+                Ok(()) => {}
+                Err(error) if source.mirror => {
+                    log_warn(&format!("skipping mirror : {}", error));
+                    continue;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        reachable.push(source);
+    }
+    Ok(reachable)
+}
+
+// get_sources - the pull sources of 'img_ref' from the entries in 'conf'
+fn get_sources(
+    conf: &RegistriesConf,
+    img_ref: &ImageReference,
+) -> Result<Vec<PullSource>, MirrorError> {
+    let mut image = img_ref.registry.clone();
+    if !img_ref.namespace.is_empty() {
+        image = image + "/" + &img_ref.namespace;
+    }
+    image = image + "/" + &img_ref.name;
+    let by_digest = img_ref.version.starts_with("sha256:");
+
+    let v1 = conf.v1.clone().unwrap_or_default();
+    let in_list = |list: &Option<RegistryList>| {
+        list.as_ref()
+            .map(|l| l.registries.contains(&img_ref.registry))
+            .unwrap_or(false)
+    };
+    if in_list(&v1.block) {
+        return Err(MirrorError::Config(format!(
+            "registry {} is blocked in registries.conf",
+            img_ref.registry
+        )));
+    }
+
+    let mut sources = vec![];
+    match find_registry(conf, &image) {
+        Some(reg) => {
+            if reg.blocked {
+                return Err(MirrorError::Config(format!(
+                    "registry {} is blocked in registries.conf",
+                    image
+                )));
+            }
+            let prefix = get_prefix(reg);
+            for mirror in reg.mirrors.iter() {
+                let pull_from = mirror.pull_from_mirror.clone().unwrap_or("all".to_string());
+                let allowed = match pull_from.as_str() {
+                    "digest-only" => by_digest,
+                    "tag-only" => !by_digest,
+                    _ => by_digest || !reg.mirror_by_digest_only,
+                };
+                if allowed {
+                    sources.push(PullSource {
+                        img_ref: rewrite_image(&prefix, &mirror.location, &image, &img_ref.version),
+                        insecure: mirror.insecure,
+                        mirror: true,
+                    });
+                }
+            }
+            let location = reg.location.clone().unwrap_or(prefix.clone());
+            sources.push(PullSource {
+                img_ref: rewrite_image(&prefix, &location, &image, &img_ref.version),
+                insecure: reg.insecure || in_list(&v1.insecure),
+                mirror: false,
+            });
+        }
+        None => sources.push(PullSource {
+            img_ref: img_ref.clone(),
+            insecure: in_list(&v1.insecure),
+            mirror: false,
+        }),
+    }
+    Ok(sources)
+}
+
+// is_insecure_registry - true if registries.conf marks 'registry' as insecure
+pub fn is_insecure_registry(registry: &str) -> bool {
+    let conf = get_registries_conf();
+    let v1 = conf.v1.clone().unwrap_or_default();
+    if let Some(list) = v1.insecure {
+        if list.registries.iter().any(|r| r == registry) {
+            return true;
+        }
+    }
+    conf.registries.iter().any(|reg| {
+        let host = |location: &str| location.split('/').next().unwrap_or_default().to_string();
+        (reg.insecure && host(&reg.location.clone().unwrap_or(get_prefix(reg))) == registry)
+            || reg
+                .mirrors
+                .iter()
+                .any(|m| m.insecure && host(&m.location) == registry)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf(data: &str) -> RegistriesConf {
+        toml::from_str(data).unwrap()
+    }
+
+    // reference - 'img_ref' back as an image string
+    fn reference(img_ref: &ImageReference) -> String {
+        let mut path = img_ref.registry.clone();
+        if !img_ref.namespace.is_empty() {
+            path = path + "/" + &img_ref.namespace;
+        }
+        let separator = if img_ref.version.starts_with("sha256:") {
+            "@"
+        } else {
+            ":"
+        };
+        format!("{}/{}{}{}", path, img_ref.name, separator, img_ref.version)
+    }
+
+    // sources - the pull sources of 'image' as 'image (mirror|source)' strings
+    fn sources(conf: &RegistriesConf, image: &str) -> Vec<String> {
+        get_sources(conf, &parse_image(image.to_string()))
+            .unwrap()
+            .iter()
+            .map(|s| {
+                let kind = if s.mirror { "mirror" } else { "source" };
+                format!("{} ({})", reference(&s.img_ref), kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prefix_matching() {
+        let tests = vec![
+            ("quay.io", "quay.io/org/app", true),
+            ("quay.io/org", "quay.io/org/app", true),
+            ("quay.io/org", "quay.io/organization/app", false),
+            ("quay.io/org/app", "quay.io/org/app", true),
+            ("*.example.com", "registry.example.com/org/app", true),
+            ("*.example.com", "example.com/org/app", false),
+            ("*.example.com", "registry.example.org/org/app", false),
+        ];
+        for (prefix, image, expected) in tests {
+            assert_eq!(prefix_matches(prefix, image), expected, "{} {}", prefix, image);
+        }
+    }
+
+    #[test]
+    fn longest_prefix() {
+        let conf = conf(
+            r#"
+            [[registry]]
+            location = "quay.io"
+
+            [[registry]]
+            prefix = "quay.io/org"
+            location = "mirror.local/org"
+            "#,
+        );
+        let reg = find_registry(&conf, "quay.io/org/app").unwrap();
+        assert_eq!(get_prefix(reg), "quay.io/org");
+        let reg = find_registry(&conf, "quay.io/other/app").unwrap();
+        assert_eq!(get_prefix(reg), "quay.io");
+        assert!(find_registry(&conf, "docker.io/library/app").is_none());
+    }
+
+    #[test]
+    fn rewrite() {
+        let tests = vec![
+            (
+                "quay.io/org",
+                "mirror.local/copy",
+                "quay.io/org/app",
+                "v1",
+                "mirror.local/copy/app:v1",
+            ),
+            (
+                "*.example.com",
+                "mirror.local",
+                "a.example.com/org/app",
+                "v1",
+                "mirror.local/org/app:v1",
+            ),
+            (
+                "quay.io",
+                "mirror.local",
+                "quay.io/org/app",
+                "sha256:abc",
+                "mirror.local/org/app@sha256:abc",
+            ),
+        ];
+        for (prefix, location, image, version, expected) in tests {
+            assert_eq!(reference(&rewrite_image(prefix, location, image, version)), expected);
+        }
+    }
+
+    #[test]
+    fn pull_sources() {
+        let conf = conf(
+            r#"
+            [[registry]]
+            prefix = "quay.io/org"
+            location = "internal.local/org"
+            mirror-by-digest-only = true
+            [[registry.mirror]]
+            location = "mirror.local/org"
+
+            [[registry]]
+            location = "registry.io"
+            [[registry.mirror]]
+            location = "tags.local"
+            pull-from-mirror = "tag-only"
+            [[registry.mirror]]
+            location = "digests.local"
+            pull-from-mirror = "digest-only"
+            "#,
+        );
+        assert_eq!(
+            sources(&conf, "quay.io/org/app:v1"),
+            vec!["internal.local/org/app:v1 (source)"]
+        );
+        assert_eq!(
+            sources(&conf, "quay.io/org/app@sha256:abc"),
+            vec![
+                "mirror.local/org/app@sha256:abc (mirror)",
+                "internal.local/org/app@sha256:abc (source)"
+            ]
+        );
+        assert_eq!(
+            sources(&conf, "registry.io/ns/app:v1"),
+            vec!["tags.local/ns/app:v1 (mirror)", "registry.io/ns/app:v1 (source)"]
+        );
+        assert_eq!(
+            sources(&conf, "registry.io/ns/app@sha256:abc"),
+            vec![
+                "digests.local/ns/app@sha256:abc (mirror)",
+                "registry.io/ns/app@sha256:abc (source)"
+            ]
+        );
+        assert_eq!(
+            sources(&conf, "docker.io/library/app:v1"),
+            vec!["docker.io/library/app:v1 (source)"]
+        );
+    }
+
+    #[test]
+    fn blocked_registries() {
+        let conf = conf(
+            r#"
+            [registries.block]
+            registries = ["blocked.io"]
+
+            [[registry]]
+            prefix = "quay.io/private"
+            blocked = true
+            "#,
+        );
+        for image in ["blocked.io/org/app:v1", "quay.io/private/app:v1"] {
+            let res = get_sources(&conf, &parse_image(image.to_string()));
+            assert!(matches!(res, Err(MirrorError::Config(_))), "{}", image);
+        }
+        assert!(get_sources(&conf, &parse_image("quay.io/public/app:v1".to_string())).is_ok());
+    }
+
+    #[test]
+    fn merge() {
+        let mut merged = conf(
+            r#"
+            [[registry]]
+            location = "quay.io"
+            insecure = true
+            [registries.insecure]
+            registries = ["a.local"]
+            "#,
+        );
+        merge_registries_conf(
+            &mut merged,
+            conf(
+                r#"
+                [[registry]]
+                location = "quay.io"
+                [registries.insecure]
+                registries = ["b.local"]
+                "#,
+            ),
+        );
+        assert_eq!(merged.registries.len(), 1);
+        assert!(!merged.registries[0].insecure);
+        let insecure = merged.v1.unwrap().insecure.unwrap().registries;
+        assert_eq!(insecure, vec!["a.local", "b.local"]);
+    }
+}
@@ -0,0 +1,47 @@
+//! Registry access: client settings, registries.conf and the distribution api.
+
+pub(crate) mod api;
+pub(crate) mod client;
+pub(crate) mod config;
+
+use crate::api::error::*;
+use crate::image::copy::*;
+use crate::inspect::image as inspect;
+
+pub use crate::api::schema::{ClientSettings, ImageReference, InspectResult, ProxyConfig};
+
+/// Set the tls, timeout and proxy settings used for every registry call,
+/// call it once before any other call (the defaults are used otherwise).
+pub fn init(settings: ClientSettings) -> Result<(), MirrorError> {
+    client::init_registry_client(settings)
+}
+
+/// Load the registries.conf (mirrors, blocked and insecure registries) used
+/// for every pull, an empty 'path' reads the default locations.
+pub fn load_registries_conf(path: &str) -> Result<(), MirrorError> {
+    config::load_registries_conf(path.to_string())
+}
+
+/// Split an image reference into registry, namespace, name and tag or digest.
+pub fn parse_reference(image: &str) -> ImageReference {
+    parse_image(image.to_string())
+}
+
+/// The tags of 'repository' in version order, optionally only the ones
+/// starting with 'filter'.
+pub async fn list_tags(repository: &str, filter: Option<&str>) -> Result<Vec<String>, MirrorError> {
+    inspect::list_tags(repository.to_string(), filter.unwrap_or("all").to_string()).await
+}
+
+/// The repositories of 'registry', optionally only the ones containing 'filter'.
+pub async fn list_repositories(
+    registry: &str,
+    filter: Option<&str>,
+) -> Result<Vec<String>, MirrorError> {
+    inspect::list_repositories(registry.to_string(), filter.unwrap_or("all").to_string()).await
+}
+
+/// Manifest, config and referrers of 'image' without pulling its layers.
+pub async fn inspect(image: &str) -> Result<InspectResult, MirrorError> {
+    inspect::inspect_image(image.to_string()).await
+}
@@ -0,0 +1,252 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::log::logging::*;
+use crate::registry::client::*;
+use semver::Version;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+
+// get_graph - get the update graph for a channel either from a cincinnati
+// compatible endpoint or from a local json file (file:// or a plain path)
+pub async fn get_graph(
+    url: String,
+    channel: String,
+    arch: String,
+) -> Result<Graph, MirrorError> {
+    let data = if url.starts_with("http://") || url.starts_with("https://") {
+        log_info(&format!("querying update graph {} for {}", url, channel));
+        let client = get_registry_client(&url)?;
+        let resp = client
+            .get(url)
+            .query(&[("channel", channel), ("arch", arch)])
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+        check_response(resp).await?.text().await?
+    } else {
+        let file = url.trim_start_matches("file://");
+        log_info(&format!("reading update graph from {}", file));
+        fs::read_to_string(file).context(format!("reading {}", file))?
+    };
+    parse_json_graph(data)
+}
+
+// parse the update graph json
+pub fn parse_json_graph(data: String) -> Result<Graph, MirrorError> {
+    // Parse the string of data into serde_json::Graph.
+    let root: Graph = serde_json::from_str(&data)
+        .map_err(|why| MirrorError::Parse(format!("update graph: {}", why)))?;
+    Ok(root)
+}
+
+// get_latest_node - the release with the highest version in the graph
+pub fn get_latest_node(graph: &Graph) -> Option<GraphNode> {
+    graph
+        .nodes
+        .iter()
+        .filter_map(|node| Version::parse(&node.version).ok().map(|v| (v, node)))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, node)| node.clone())
+}
+
+// get_channel_releases - the releases needed for a channel
+// without min/max versions only the latest release is returned, a missing
+// maxVersion defaults to the latest release and a missing minVersion to maxVersion
+// with shortestPath only the releases on the shortest upgrade path are returned
+pub fn get_channel_releases(
+    graph: &Graph,
+    channel: &ChannelP,
+) -> Result<Vec<GraphNode>, MirrorError> {
+    let latest = get_latest_node(graph).ok_or(MirrorError::Other(
+        "no releases found in update graph".to_string(),
+    ))?;
+    let parse = |v: &str| {
+        Version::parse(v).map_err(|why| MirrorError::Config(format!("version {}: {}", v, why)))
+    };
+    let max = match &channel.max_version {
+        Some(v) => parse(v)?,
+        None => parse(&latest.version)?,
+    };
+    let min = match &channel.min_version {
+        Some(v) => parse(v)?,
+        None => max.clone(),
+    };
+    if min > max {
+        return Err(MirrorError::Config(format!(
+            "minVersion {} is greater than maxVersion {}",
+            min, max
+        )));
+    }
+    if channel.shortest_path.unwrap_or(false) {
+        return get_shortest_path(graph, &min, &max);
+    }
+    let mut nodes: Vec<(Version, GraphNode)> = graph
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            Version::parse(&node.version)
+                .ok()
+                .map(|v| (v, node.clone()))
+        })
+        .filter(|(v, _)| *v >= min && *v <= max)
+        .collect();
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(nodes.into_iter().map(|(_, node)| node).collect())
+}
+
+// get_shortest_path - breadth first search over the graph edges for the
+// upgrade path with the fewest hops from 'min' to 'max'
+pub fn get_shortest_path(
+    graph: &Graph,
+    min: &Version,
+    max: &Version,
+) -> Result<Vec<GraphNode>, MirrorError> {
+    let find = |v: &Version| {
+        graph
+            .nodes
+            .iter()
+            .position(|node| Version::parse(&node.version).ok().as_ref() == Some(v))
+            .ok_or(MirrorError::Config(format!(
+                "version {} not found in update graph",
+                v
+            )))
+    };
+    let start = find(min)?;
+    let end = find(max)?;
+
+    let mut previous: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        if current == end {
+            break;
+        }
+        for (from, to) in graph.edges.iter() {
+            if *from == current && *to != start && !previous.contains_key(to) {
+                previous.insert(*to, current);
+                queue.push_back(*to);
+            }
+        }
+    }
+    if start != end && !previous.contains_key(&end) {
+        return Err(MirrorError::Config(format!(
+            "no upgrade path from {} to {}",
+            min, max
+        )));
+    }
+
+    let mut path = vec![graph.nodes[end].clone()];
+    let mut current = end;
+    while current != start {
+        current = previous[&current];
+        path.push(graph.nodes[current].clone());
+    }
+    path.reverse();
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // nodes out of version order, 4.12.0 -> 4.12.3 skips two releases and
+    // 4.12.1 / 4.12.2 have no path to 4.12.3
+    const GRAPH: &str = r#"{
+        "nodes": [
+            {"version": "4.12.2", "payload": "quay.io/openshift-release-dev/ocp-release@sha256:2222"},
+            {"version": "4.12.0", "payload": "quay.io/openshift-release-dev/ocp-release@sha256:0000"},
+            {"version": "4.12.1", "payload": "quay.io/openshift-release-dev/ocp-release@sha256:1111"},
+            {"version": "4.12.4", "payload": "quay.io/openshift-release-dev/ocp-release@sha256:4444"},
+            {"version": "4.12.3", "payload": "quay.io/openshift-release-dev/ocp-release@sha256:3333"},
+            {"version": "4.11.9", "payload": "quay.io/openshift-release-dev/ocp-release@sha256:0119"}
+        ],
+        "edges": [[5, 1], [1, 2], [2, 0], [0, 3], [1, 4], [4, 3]]
+    }"#;
+
+    fn channel(min: Option<&str>, max: Option<&str>, shortest_path: bool) -> ChannelP {
+        ChannelP {
+            name: "stable-4.12".to_string(),
+            channel_type: "ocp".to_string(),
+            min_version: min.map(String::from),
+            max_version: max.map(String::from),
+            shortest_path: Some(shortest_path),
+        }
+    }
+
+    fn versions(graph: &Graph, channel: &ChannelP) -> Vec<String> {
+        get_channel_releases(graph, channel)
+            .unwrap()
+            .into_iter()
+            .map(|node| node.version)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn graph_from_file() {
+        let file = std::env::temp_dir().join(format!("graph-{}.json", std::process::id()));
+        fs::write(&file, GRAPH).unwrap();
+        let url = format!("file://{}", file.display());
+        let graph = get_graph(url, "stable-4.12".to_string(), "amd64".to_string()).await;
+        fs::remove_file(&file).unwrap();
+        let graph = graph.unwrap();
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(get_latest_node(&graph).unwrap().version, "4.12.4");
+    }
+
+    #[test]
+    fn latest_release() {
+        let graph = parse_json_graph(GRAPH.to_string()).unwrap();
+        // without versions only the latest release
+        assert_eq!(versions(&graph, &channel(None, None, false)), vec!["4.12.4"]);
+    }
+
+    #[test]
+    fn channel_releases() {
+        let graph = parse_json_graph(GRAPH.to_string()).unwrap();
+        for (min, max, expected) in [
+            // a missing minVersion defaults to maxVersion
+            (None, Some("4.12.2"), vec!["4.12.2"]),
+            // a missing maxVersion defaults to the latest release
+            (Some("4.12.3"), None, vec!["4.12.3", "4.12.4"]),
+            (
+                Some("4.12.0"),
+                Some("4.12.3"),
+                vec!["4.12.0", "4.12.1", "4.12.2", "4.12.3"],
+            ),
+        ] {
+            assert_eq!(versions(&graph, &channel(min, max, false)), expected, "{:?} {:?}", min, max);
+        }
+    }
+
+    #[test]
+    fn min_greater_than_max() {
+        let graph = parse_json_graph(GRAPH.to_string()).unwrap();
+        let error = get_channel_releases(&graph, &channel(Some("4.12.3"), Some("4.12.1"), false))
+            .unwrap_err();
+        assert!(matches!(error, MirrorError::Config(_)));
+    }
+
+    #[test]
+    fn shortest_path() {
+        let graph = parse_json_graph(GRAPH.to_string()).unwrap();
+        for (min, max, expected) in [
+            ("4.12.0", "4.12.4", vec!["4.12.0", "4.12.3", "4.12.4"]),
+            ("4.11.9", "4.12.2", vec!["4.11.9", "4.12.0", "4.12.1", "4.12.2"]),
+            ("4.12.2", "4.12.2", vec!["4.12.2"]),
+        ] {
+            let channel = channel(Some(min), Some(max), true);
+            assert_eq!(versions(&graph, &channel), expected, "{} {}", min, max);
+        }
+    }
+
+    #[test]
+    fn no_upgrade_path() {
+        let graph = parse_json_graph(GRAPH.to_string()).unwrap();
+        for (min, max) in [("4.12.1", "4.12.3"), ("4.12.4", "4.12.5")] {
+            let channel = channel(Some(min), Some(max), true);
+            assert!(matches!(
+                get_channel_releases(&graph, &channel),
+                Err(MirrorError::Config(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::auth::credentials::*;
+use crate::image::copy::*;
+use crate::log::logging::*;
+use crate::registry::client::*;
+use crate::mirror::related::*;
+use crate::release::graph::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use tar::{Archive, Builder};
+
+// upstream cincinnati graph data used to build the graph image
+const GRAPH_DATA_URL: &str = "https://api.openshift.com/api/upgrades_info/graph-data";
+
+// architecture mirrored when the platform lists none
+const DEFAULT_ARCHITECTURE: &str = "amd64";
+
+// mirror_platform - resolve each platform channel to a release and mirror
+// the release payload with all of its component images, for every
+// architecture of the platform (amd64 by default)
+// every channel is mirrored, the run fails when a channel or an image failed,
+// the returned report holds the images of every release (not the graph image)
+pub async fn mirror_platform(
+    platform: Platform,
+    graph_url: String,
+    destination: String,
+    dir: String,
+) -> Result<MirrorReport, MirrorError> {
+    let architectures = match platform.architectures.is_empty() {
+        true => vec![DEFAULT_ARCHITECTURE.to_string()],
+        false => platform.architectures.clone(),
+    };
+    let mut report = MirrorReport {
+        catalog: "release".to_string(),
+        total: 0,
+        unique: 0,
+        mirrored: 0,
+        failed: 0,
+        images: vec![],
+    };
+    let mut errors = 0;
+    for arch in architectures.iter() {
+        for channel in platform.channels.iter() {
+            match mirror_channel(channel, arch, &graph_url, &destination, &dir).await {
+                Ok(reports) => {
+                    for release in reports {
+                        report.total += release.total;
+                        report.unique += release.unique;
+                        report.mirrored += release.mirrored;
+                        report.failed += release.failed;
+                        report.images.extend(release.images);
+                    }
+                }
+                Err(error) => {
+                    log_error(&format!("channel {} ({}) : {}", channel.name, arch, error));
+                    errors += 1;
+                }
+            }
+        }
+    }
+    if platform.graph {
+        match build_graph_image(destination, dir.clone(), architectures[0].clone()).await {
+            Ok(img) => {
+                let graph = MirrorReport {
+                    catalog: img.source.clone(),
+                    total: 1,
+                    unique: 1,
+                    mirrored: 1,
+                    failed: 0,
+                    images: vec![img],
+                };
+                // the release- prefix has push (read_reports) pick it up
+                write_report(&graph, dir + "release-graph-image.json")?;
+            }
+            Err(error) => {
+                log_error(&format!("building graph image : {}", error));
+                errors += 1;
+            }
+        }
+    }
+    if errors > 0 || report.failed > 0 {
+        return Err(MirrorError::Other(format!(
+            "release mirroring failed ({} images, {} channels or graph image)",
+            report.failed, errors
+        )));
+    }
+    Ok(report)
+}
+
+// mirror_channel - mirror the releases of 'channel' for one architecture
+// and write a report per release, a channel of another type is skipped
+async fn mirror_channel(
+    channel: &ChannelP,
+    arch: &str,
+    graph_url: &str,
+    destination: &str,
+    dir: &str,
+) -> Result<Vec<MirrorReport>, MirrorError> {
+    if channel.channel_type != "ocp" {
+        log_warn(&format!(
+            "channel {} has unsupported type {} skipping",
+            channel.name, channel.channel_type
+        ));
+        return Ok(vec![]);
+    }
+    let graph = get_graph(graph_url.to_string(), channel.name.clone(), arch.to_string())
+        .await
+        .context("update graph")?;
+    let releases = get_channel_releases(&graph, channel).context("selecting releases")?;
+    let versions: Vec<String> = releases.iter().map(|node| node.version.clone()).collect();
+    log_hi(&format!(
+        "channel {} ({}) => releases {}",
+        channel.name,
+        arch,
+        versions.join(", ")
+    ));
+    let mut reports = vec![];
+    for node in releases {
+        let report = mirror_release(node.clone(), destination.to_string(), dir.to_string()).await;
+        let file = format!("{}release-{}-{}.json", dir, node.version, arch);
+        write_report(&report, file)?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+// mirror_release - copy the release payload image, read the component
+// images from 'release-manifests/image-references' and mirror each of them
+pub async fn mirror_release(node: GraphNode, destination: String, dir: String) -> MirrorReport {
+    let img_ref = parse_image(node.payload.clone());
+    let mut payload = MirroredImage {
+        name: format!("release-{}", node.version),
+        source: node.payload.clone(),
+        destination: get_destination(destination.clone(), img_ref.clone()),
+        digest: String::new(),
+        mirrored: false,
+    };
+    let mut images = vec![];
+    match copy_image(img_ref, dir.clone()).await {
+        Ok(digest) => {
+            payload.digest = digest.clone();
+            payload.mirrored = true;
+            match get_image_references(digest, dir.clone()) {
+                Ok(is) => {
+                    for tag in is.spec.tags {
+                        images.push(RelatedImage {
+                            name: tag.name,
+                            image: tag.from.name,
+                        });
+                    }
+                }
+                Err(error) => log_error(&format!("reading image-references : {}", error)),
+            }
+        }
+        Err(error) => log_error(&format!("mirroring release {} : {}", node.payload, error)),
+    }
+    let total = images.len();
+    let unique = dedupe_related_images(images);
+    let mut report = mirror_related_images(node.payload, total, unique, destination, dir).await;
+    if payload.mirrored {
+        report.mirrored += 1;
+    } else {
+        report.failed += 1;
+    }
+    report.images.insert(0, payload);
+    report
+}
+
+// get_image_references - find and parse the image-references file in the
+// layers of a mirrored release payload (the first image of a manifest list is used)
+pub fn get_image_references(digest: String, dir: String) -> Result<ImageStream, MirrorError> {
+    let manifests_dir = dir.clone() + "/manifests/";
+    let blobs_dir = dir + "/blobs/sha256/";
+    let read_manifest = |hex: &str| {
+        let file = manifests_dir.clone() + hex + ".json";
+        let data = fs::read_to_string(&file).context(format!("reading {}", file))?;
+        parse_json_manifest_v2(data)
+    };
+    let hex = digest.split(':').nth(1).unwrap_or_default().to_string();
+    let mut manifest = read_manifest(&hex)?;
+    if let Some(children) = manifest.manifests.clone() {
+        let child = children
+            .first()
+            .ok_or(MirrorError::Parse(format!("{} is an empty manifest list", digest)))?;
+        let hex = child
+            .digest
+            .split(':')
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+        manifest = read_manifest(&hex)?;
+    }
+    for layer in manifest.layers.unwrap_or_default() {
+        let hex = layer
+            .digest
+            .split(':')
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+        if let Some(data) = extract_file(
+            blobs_dir.clone() + &hex,
+            "release-manifests/image-references",
+        )? {
+            let is: ImageStream = serde_json::from_str(&data)
+                .map_err(|why| MirrorError::Parse(format!("image-references: {}", why)))?;
+            return Ok(is);
+        }
+    }
+    Err(MirrorError::Extraction(
+        "release-manifests/image-references not found in payload".to_string(),
+    ))
+}
+
+// extract_file - read a single file from a gzipped layer blob
+pub fn extract_file(blob: String, name: &str) -> Result<Option<String>, MirrorError> {
+    let extract_error = |why: std::io::Error| MirrorError::Extraction(format!("{}: {}", blob, why));
+    let tar = GzDecoder::new(File::open(&blob).map_err(extract_error)?);
+    let mut archive = Archive::new(tar);
+    for entry in archive.entries().map_err(extract_error)? {
+        let mut entry = entry.map_err(extract_error)?;
+        if entry.path().map_err(extract_error)?.ends_with(name) {
+            let mut s = String::new();
+            entry.read_to_string(&mut s).map_err(extract_error)?;
+            return Ok(Some(s));
+        }
+    }
+    Ok(None)
+}
+
+// build_graph_image - download the cincinnati graph data and build a single
+// layer oci image with the data in /var/lib/cincinnati-graph-data
+pub async fn build_graph_image(
+    destination: String,
+    dir: String,
+    arch: String,
+) -> Result<MirroredImage, MirrorError> {
+    let manifests_dir = dir.clone() + "/manifests/";
+    let blobs_dir = dir + "/blobs/sha256/";
+    fs::create_dir_all(&manifests_dir).context(format!("creating {}", manifests_dir))?;
+    fs::create_dir_all(&blobs_dir).context(format!("creating {}", blobs_dir))?;
+
+    log_info(&format!("downloading graph data from {}", GRAPH_DATA_URL));
+    let resp = get_registry_client(GRAPH_DATA_URL)?
+        .get(GRAPH_DATA_URL)
+        .send()
+        .await?;
+    let data = check_response(resp).await?.bytes().await?;
+
+    // re-package the graph data under the expected directory
+    let extract_error = |why: std::io::Error| MirrorError::Extraction(format!("graph data: {}", why));
+    let mut archive = Archive::new(GzDecoder::new(&data[..]));
+    let mut builder = Builder::new(Vec::new());
+    for entry in archive.entries().map_err(extract_error)? {
+        let mut entry = entry.map_err(extract_error)?;
+        let path = std::path::Path::new("var/lib/cincinnati-graph-data")
+            .join(entry.path().map_err(extract_error)?);
+        let mut header = entry.header().clone();
+        builder
+            .append_data(&mut header, path, &mut entry)
+            .map_err(extract_error)?;
+    }
+    let layer_tar = builder.into_inner().context("building the graph layer")?;
+    let diff_id = get_digest(&layer_tar);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    std::io::Write::write_all(&mut encoder, &layer_tar).context("compressing the graph layer")?;
+    let layer = encoder.finish().context("compressing the graph layer")?;
+    let layer_digest = get_digest(&layer);
+
+    let config = serde_json::json!({
+        "architecture": arch,
+        "os": "linux",
+        "config": {},
+        "rootfs": { "type": "layers", "diff_ids": [diff_id] },
+    })
+    .to_string();
+    let config_digest = get_digest(config.as_bytes());
+
+    let manifest = ManifestV2 {
+        schema_version: 2,
+        media_type: Some("application/vnd.oci.image.manifest.v1+json".to_string()),
+        config: Some(Layer {
+            media_type: Some("application/vnd.oci.image.config.v1+json".to_string()),
+            digest: config_digest.clone(),
+            size: Some(config.len() as i64),
+            ..Default::default()
+        }),
+        layers: Some(vec![Layer {
+            media_type: Some("application/vnd.oci.image.layer.v1.tar+gzip".to_string()),
+            digest: layer_digest.clone(),
+            size: Some(layer.len() as i64),
+            ..Default::default()
+        }]),
+        manifests: None,
+    };
+    let manifest = serde_json::to_string(&manifest)?;
+    let digest = get_digest(manifest.as_bytes());
+
+    fs::write(blobs_dir.clone() + &layer_digest[7..], &layer).context("writing the graph layer")?;
+    fs::write(blobs_dir + &config_digest[7..], &config).context("writing the graph config")?;
+    fs::write(manifests_dir + &digest[7..] + ".json", &manifest)
+        .context("writing the graph manifest")?;
+    log_info(&format!("graph image built {}", digest));
+
+    Ok(MirroredImage {
+        name: "graph-image".to_string(),
+        source: "graph-data".to_string(),
+        destination: destination.trim_end_matches('/').to_string()
+            + "/openshift/graph-image:latest",
+        digest,
+        mirrored: true,
+    })
+}
@@ -0,0 +1,372 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::log::logging::*;
+use crate::manifests::catalogs::*;
+use crate::mirror::related::*;
+use base64::{engine::general_purpose, Engine as _};
+use semver::Version;
+use std::fs;
+
+// PackageIndex the searchable fields of a package
+#[derive(Default)]
+struct PackageIndex {
+    name: String,
+    display_name: Option<String>,
+    description: String,
+    provider: Option<String>,
+    categories: Vec<String>,
+    keywords: Vec<String>,
+    apis: Vec<GvkProperty>,
+}
+
+// CsvInfo the csv fields we index, from olm.csv.metadata or
+// (for older catalogs) the csv in an olm.bundle.object property
+#[derive(Default)]
+struct CsvInfo {
+    display_name: Option<String>,
+    description: Option<String>,
+    provider: Option<String>,
+    categories: Vec<String>,
+    keywords: Vec<String>,
+}
+
+// get_csv_info - read the csv fields from the bundle properties
+fn get_csv_info(bundle: &Bundle) -> Option<CsvInfo> {
+    for p in bundle.properties.iter() {
+        if let Property::CsvMetadata(csv) = p {
+            return Some(CsvInfo {
+                display_name: csv.display_name.clone(),
+                description: csv.description.clone(),
+                provider: csv.provider.as_ref().and_then(|p| p.name.clone()),
+                categories: get_categories(
+                    csv.annotations.as_ref().and_then(|a| a.get("categories")),
+                ),
+                keywords: csv.keywords.clone().unwrap_or_default(),
+            });
+        }
+    }
+    for p in bundle.properties.iter() {
+        if let Property::BundleObject(obj) = p {
+            let data = match general_purpose::STANDARD.decode(&obj.data) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            let csv: serde_json::Value = match serde_json::from_slice(&data) {
+                Ok(csv) => csv,
+                Err(_) => continue,
+            };
+            if csv["kind"] != "ClusterServiceVersion" {
+                continue;
+            }
+            let spec = &csv["spec"];
+            let text = |v: &serde_json::Value| v.as_str().map(|s| s.to_string());
+            return Some(CsvInfo {
+                display_name: text(&spec["displayName"]),
+                description: text(&spec["description"]),
+                provider: text(&spec["provider"]["name"]),
+                categories: get_categories(
+                    csv["metadata"]["annotations"]["categories"]
+                        .as_str()
+                        .map(|s| s.to_string())
+                        .as_ref(),
+                ),
+                keywords: spec["keywords"]
+                    .as_array()
+                    .map(|k| {
+                        k.iter()
+                            .filter_map(|v| v.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    None
+}
+
+// get_categories - split the comma separated categories annotation
+fn get_categories(categories: Option<&String>) -> Vec<String> {
+    match categories {
+        Some(c) => c
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        None => vec![],
+    }
+}
+
+// build_package_index - provided apis are collected from all bundles,
+// the csv fields are taken from the bundle with the highest version
+// (bundles without a valid version are skipped so file order doesn't matter)
+fn build_package_index(name: String, dc: Vec<DeclarativeConfig>) -> PackageIndex {
+    let mut index = PackageIndex {
+        name,
+        ..Default::default()
+    };
+    let mut latest: Option<Version> = None;
+    let mut csv_description = None;
+    for obj in dc {
+        match obj {
+            DeclarativeConfig::Package(pkg) => {
+                index.name = pkg.name;
+                index.description = pkg.description.unwrap_or_default();
+            }
+            DeclarativeConfig::Bundle(bundle) => {
+                for p in bundle.properties.iter() {
+                    if let Property::Gvk(gvk) = p {
+                        let exists = index.apis.iter().any(|a| {
+                            a.group == gvk.group && a.kind == gvk.kind && a.version == gvk.version
+                        });
+                        if !exists {
+                            index.apis.push(gvk.clone());
+                        }
+                    }
+                }
+                let version = bundle
+                    .version()
+                    .map(|v| Version::parse(&v).map_err(|error| (v, error)));
+                let version = match version {
+                    Some(Ok(version)) => version,
+                    Some(Err((version, error))) => {
+                        log_warn(&format!(
+                            "bundle {} has an invalid version {} : {}",
+                            bundle.name, version, error
+                        ));
+                        continue;
+                    }
+                    None => {
+                        log_warn(&format!("bundle {} has no version", bundle.name));
+                        continue;
+                    }
+                };
+                if latest.as_ref().is_some_and(|latest| &version <= latest) {
+                    continue;
+                }
+                if let Some(csv) = get_csv_info(&bundle) {
+                    latest = Some(version);
+                    index.display_name = csv.display_name;
+                    index.provider = csv.provider;
+                    index.categories = csv.categories;
+                    index.keywords = csv.keywords;
+                    csv_description = csv.description;
+                }
+            }
+            _ => {}
+        }
+    }
+    // only the description of the newest bundle is searched with the package description
+    if let Some(description) = csv_description {
+        index.description = index.description.clone() + "\n" + &description;
+    }
+    index
+}
+
+// score_package - every term has to match, the score is the sum of the
+// best match for each term (name and api matches rank highest)
+fn score_package(index: &PackageIndex, terms: &[String]) -> Option<(u32, Vec<String>)> {
+    let contains = |text: &str, term: &str| text.to_lowercase().contains(term);
+    let mut score = 0;
+    let mut matches = vec![];
+    for term in terms {
+        let mut best = 0;
+        let mut reason = String::new();
+        let mut candidate = |points: u32, why: String| {
+            if points > best {
+                best = points;
+                reason = why;
+            }
+        };
+        if index.name.to_lowercase() == *term {
+            candidate(100, format!("name {}", index.name));
+        } else if contains(&index.name, term) {
+            candidate(60, format!("name {}", index.name));
+        }
+        for api in index.apis.iter() {
+            let api_name = format!("{}.{}/{}", api.kind, api.group, api.version);
+            if api.kind.to_lowercase() == *term {
+                candidate(90, format!("api {}", api_name));
+            } else if contains(&api.kind, term) || contains(&api.group, term) {
+                candidate(50, format!("api {}", api_name));
+            }
+        }
+        if let Some(display_name) = &index.display_name {
+            if contains(display_name, term) {
+                candidate(40, format!("displayName {}", display_name));
+            }
+        }
+        if let Some(provider) = &index.provider {
+            if contains(provider, term) {
+                candidate(30, format!("provider {}", provider));
+            }
+        }
+        for category in index.categories.iter() {
+            if contains(category, term) {
+                candidate(30, format!("category {}", category));
+            }
+        }
+        for keyword in index.keywords.iter() {
+            if contains(keyword, term) {
+                candidate(20, format!("keyword {}", keyword));
+            }
+        }
+        if contains(&index.description, term) {
+            candidate(10, "description".to_string());
+        }
+        if best == 0 {
+            return None;
+        }
+        score += best;
+        matches.push(reason);
+    }
+    Some((score, matches))
+}
+
+// search_catalogs - search all packages of each (name, configs directory) catalog
+pub fn search_catalogs(
+    catalogs: Vec<(String, String)>,
+    query: String,
+) -> Result<Vec<SearchResult>, MirrorError> {
+    let terms: Vec<String> = query.split_whitespace().map(|t| t.to_lowercase()).collect();
+    let mut results = vec![];
+    for (catalog, dir) in catalogs {
+        for pkg in select_packages(dir.clone(), "all".to_string(), None)? {
+            let dc = match read_operator_catalog(dir.clone() + "/" + &pkg) {
+                Ok(dc) => dc,
+                Err(error) => {
+                    log_warn(&error.to_string());
+                    continue;
+                }
+            };
+            let index = build_package_index(pkg, dc);
+            if let Some((score, matches)) = score_package(&index, &terms) {
+                results.push(SearchResult {
+                    catalog: catalog.clone(),
+                    package: index.name,
+                    score,
+                    matches,
+                    display_name: index.display_name,
+                    provider: index.provider,
+                    provided_apis: index
+                        .apis
+                        .iter()
+                        .map(|a| format!("{}.{}/{}", a.kind, a.group, a.version))
+                        .collect(),
+                });
+            }
+        }
+    }
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(a.package.cmp(&b.package)));
+    Ok(results)
+}
+
+// find_cached_catalogs - every catalog index already untarred in 'dir'
+// (working-dir/<name>/<version>/cache) with its 'configs' directory
+pub async fn find_cached_catalogs(dir: String) -> Vec<(String, String)> {
+    let mut catalogs = vec![];
+    let names = match fs::read_dir(&dir) {
+        Ok(names) => names,
+        Err(_) => return catalogs,
+    };
+    for name in names.flatten() {
+        let versions = match fs::read_dir(name.path()) {
+            Ok(versions) => versions,
+            Err(_) => continue,
+        };
+        for version in versions.flatten() {
+            let cache = version.path().join("cache");
+            if !cache.is_dir() {
+                continue;
+            }
+            let configs =
+                find_dir(cache.to_string_lossy().to_string(), "configs".to_string()).await;
+            if !configs.is_empty() {
+                let catalog = format!(
+                    "{}:{}",
+                    name.file_name().to_string_lossy(),
+                    version.file_name().to_string_lossy()
+                );
+                catalogs.push((catalog, configs));
+            }
+        }
+    }
+    catalogs.sort();
+    catalogs
+}
+
+// print_search - write the ranked results to stdout in the requested format
+pub fn print_search(results: &[SearchResult], output: Option<OutputFormat>) -> Result<(), MirrorError> {
+    match output {
+        Some(OutputFormat::Json) => println!("{}", serde_json::to_string_pretty(results)?),
+        Some(OutputFormat::Yaml) => print!("{}", serde_yaml::to_string(results)?),
+        None => {
+            for res in results.iter() {
+                println!(
+                    "{:>4}  {:<45} {:<40} {}",
+                    res.score,
+                    res.package,
+                    res.catalog,
+                    res.matches.join(", ")
+                );
+            }
+        }
+    }
+    log_info(&format!("{} matching packages", results.len()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(version: &str, description: &str) -> DeclarativeConfig {
+        serde_json::from_value(serde_json::json!({
+            "schema": "olm.bundle",
+            "name": format!("etcd.v{}", version),
+            "package": "etcd",
+            "properties": [
+                {"type": "olm.package", "value": {"packageName": "etcd", "version": version}},
+                {"type": "olm.csv.metadata", "value": {"description": description}}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn description_of_the_newest_bundle() {
+        let package: DeclarativeConfig = serde_json::from_value(serde_json::json!({
+            "schema": "olm.package",
+            "name": "etcd",
+            "description": "etcd package"
+        }))
+        .unwrap();
+        let dc = vec![
+            package,
+            bundle("0.9.0", "old release"),
+            bundle("0.9.4", "newest release"),
+            bundle("0.9.2", "middle release"),
+        ];
+        let index = build_package_index("etcd".to_string(), dc);
+        assert_eq!(index.description, "etcd package\nnewest release");
+    }
+
+    #[test]
+    fn bundles_without_a_valid_version_are_skipped() {
+        let mut no_version = bundle("0.9.9", "no version");
+        if let DeclarativeConfig::Bundle(bundle) = &mut no_version {
+            bundle.properties.retain(|p| !matches!(p, Property::Package(_)));
+        }
+        let dc = vec![
+            bundle("latest", "invalid version"),
+            no_version,
+            bundle("0.9.2", "middle release"),
+            bundle("0.9.4", "newest release"),
+            bundle("0.9.0", "old release"),
+        ];
+        // the same bundle wins whatever the order of the objects
+        for dc in [dc.clone(), dc.into_iter().rev().collect()] {
+            let index = build_package_index("etcd".to_string(), dc);
+            assert_eq!(index.description, "\nnewest release");
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use crate::api::error::*;
+use crate::api::schema::*;
+use crate::graph::upgrade::*;
+use crate::log::logging::*;
+use crate::manifests::catalogs::*;
+use crate::mirror::related::*;
+use std::collections::HashSet;
+
+// add_finding - helper to keep the checks readable
+fn add_finding(
+    findings: &mut Vec<ValidationFinding>,
+    severity: &str,
+    package: &str,
+    object: &str,
+    message: String,
+) {
+    findings.push(ValidationFinding {
+        severity: severity.to_string(),
+        package: package.to_string(),
+        object: object.to_string(),
+        message,
+    });
+}
+
+// validate_package - structural and upgrade graph checks for a single package
+pub fn validate_package(name: String, dc: Vec<DeclarativeConfig>) -> Vec<ValidationFinding> {
+    let mut findings = vec![];
+    let mut packages = vec![];
+    let mut channels = vec![];
+    let mut bundles: HashSet<String> = HashSet::new();
+
+    for obj in dc.iter() {
+        match obj {
+            DeclarativeConfig::Package(pkg) => packages.push(pkg),
+            DeclarativeConfig::Channel(ch) => channels.push(ch),
+            DeclarativeConfig::Bundle(bundle) => {
+                let object = format!("olm.bundle/{}", bundle.name);
+                if !bundles.insert(bundle.name.clone()) {
+                    add_finding(
+                        &mut findings,
+                        "error",
+                        &name,
+                        &object,
+                        "duplicate bundle".to_string(),
+                    );
+                }
+                if bundle.image.is_empty() {
+                    // opm accepts an empty image when the bundle objects are inlined
+                    let inlined = bundle
+                        .properties
+                        .iter()
+                        .any(|p| matches!(p, Property::BundleObject(_)));
+                    let severity = if inlined { "warning" } else { "error" };
+                    add_finding(
+                        &mut findings,
+                        severity,
+                        &name,
+                        &object,
+                        "bundle has no image".to_string(),
+                    );
+                }
+                if bundle.version().is_none() {
+                    add_finding(
+                        &mut findings,
+                        "error",
+                        &name,
+                        &object,
+                        "bundle has no olm.package property".to_string(),
+                    );
+                }
+            }
+            DeclarativeConfig::Unknown(_) => {}
+        }
+    }
+
+    // package level checks
+    let pkg_object = format!("olm.package/{}", name);
+    match packages.len() {
+        0 => add_finding(
+            &mut findings,
+            "error",
+            &name,
+            &pkg_object,
+            "no olm.package object".to_string(),
+        ),
+        1 => {}
+        n => add_finding(
+            &mut findings,
+            "error",
+            &name,
+            &pkg_object,
+            format!("{} olm.package objects", n),
+        ),
+    }
+    for pkg in packages.iter() {
+        match &pkg.default_channel {
+            None => add_finding(
+                &mut findings,
+                "error",
+                &name,
+                &pkg_object,
+                "missing default channel".to_string(),
+            ),
+            Some(default) => {
+                if !channels.iter().any(|ch| &ch.name == default) {
+                    add_finding(
+                        &mut findings,
+                        "error",
+                        &name,
+                        &pkg_object,
+                        format!("default channel {} not found", default),
+                    );
+                }
+            }
+        }
+    }
+    if channels.is_empty() {
+        add_finding(
+            &mut findings,
+            "error",
+            &name,
+            &pkg_object,
+            "package has no channels".to_string(),
+        );
+    }
+
+    // channel and upgrade graph checks
+    let versions = get_bundle_versions(&dc);
+    for channel in channels {
+        let object = format!("olm.channel/{}", channel.name);
+        if channel.entries.is_empty() {
+            add_finding(
+                &mut findings,
+                "error",
+                &name,
+                &object,
+                "channel has no entries".to_string(),
+            );
+            continue;
+        }
+        let mut seen = HashSet::new();
+        for entry in channel.entries.iter() {
+            if !seen.insert(entry.name.clone()) {
+                add_finding(
+                    &mut findings,
+                    "error",
+                    &name,
+                    &object,
+                    format!("duplicate entry {}", entry.name),
+                );
+            }
+            if !bundles.contains(&entry.name) {
+                add_finding(
+                    &mut findings,
+                    "error",
+                    &name,
+                    &object,
+                    format!("entry {} has no matching bundle", entry.name),
+                );
+            }
+        }
+        let graph = build_upgrade_graph(channel, &versions);
+        if graph.heads.len() > 1 {
+            add_finding(
+                &mut findings,
+                "error",
+                &name,
+                &object,
+                format!("multiple channel heads {}", graph.heads.join(", ")),
+            );
+        }
+        if graph.heads.is_empty() {
+            add_finding(
+                &mut findings,
+                "error",
+                &name,
+                &object,
+                "no channel head (replaces cycle)".to_string(),
+            );
+        }
+        for entry in graph.unreachable.iter() {
+            add_finding(
+                &mut findings,
+                "error",
+                &name,
+                &object,
+                format!("entry {} has no upgrade path to the channel head", entry),
+            );
+        }
+        // replacing a bundle that was pruned from the catalog is allowed,
+        // but usually a mistake
+        for missing in graph.missing.iter() {
+            add_finding(
+                &mut findings,
+                "warning",
+                &name,
+                &object,
+                format!("replaces/skips {} which is not in the channel", missing),
+            );
+        }
+    }
+    findings
+}
+
+// validate_catalog - validate every package in a 'configs' directory
+pub fn validate_catalog(
+    dir: String,
+    filter: String,
+) -> Result<Vec<ValidationFinding>, MirrorError> {
+    let mut findings = vec![];
+    for pkg in select_packages(dir.clone(), filter, None)? {
+        match read_operator_catalog(dir.clone() + "/" + &pkg) {
+            Ok(dc) => findings.extend(validate_package(pkg, dc)),
+            Err(error) => add_finding(&mut findings, "error", &pkg, "", error.to_string()),
+        }
+    }
+    Ok(findings)
+}
+
+// print_findings - write the findings to stdout in the requested format
+// returns the number of errors
+pub fn print_findings(
+    findings: &[ValidationFinding],
+    output: Option<OutputFormat>,
+) -> Result<usize, MirrorError> {
+    match output {
+        Some(OutputFormat::Json) => println!("{}", serde_json::to_string_pretty(findings)?),
+        Some(OutputFormat::Yaml) => print!("{}", serde_yaml::to_string(findings)?),
+        None => {
+            for f in findings.iter() {
+                println!(
+                    "{:<8} {} {} : {}",
+                    f.severity.to_uppercase(),
+                    f.package,
+                    f.object,
+                    f.message
+                );
+            }
+        }
+    }
+    let errors = findings.iter().filter(|f| f.severity == "error").count();
+    let msg = format!(
+        "validation found {} errors and {} warnings",
+        errors,
+        findings.len() - errors
+    );
+    if errors > 0 {
+        log_error(&msg);
+    } else {
+        log_info(&msg);
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn package(default_channel: &str) -> serde_json::Value {
+        json!({"schema": "olm.package", "name": "etcd", "defaultChannel": default_channel})
+    }
+
+    fn bundle(version: &str) -> serde_json::Value {
+        json!({
+            "schema": "olm.bundle",
+            "name": format!("etcd.v{}", version),
+            "package": "etcd",
+            "image": format!("quay.io/operatorhubio/etcd:v{}", version),
+            "properties": [
+                {"type": "olm.package", "value": {"packageName": "etcd", "version": version}}
+            ]
+        })
+    }
+
+    fn channel(entries: serde_json::Value) -> serde_json::Value {
+        json!({"schema": "olm.channel", "name": "stable", "package": "etcd", "entries": entries})
+    }
+
+    // validate the objects and return (severity, message) of each finding
+    fn validate(objects: Vec<serde_json::Value>) -> Vec<(String, String)> {
+        let dc = objects
+            .into_iter()
+            .map(|obj| serde_json::from_value(obj).unwrap())
+            .collect();
+        validate_package("etcd".to_string(), dc)
+            .into_iter()
+            .map(|f| (f.severity, f.message))
+            .collect()
+    }
+
+    fn finding(severity: &str, message: &str) -> Vec<(String, String)> {
+        vec![(severity.to_string(), message.to_string())]
+    }
+
+    #[test]
+    fn valid_package() {
+        let findings = validate(vec![
+            package("stable"),
+            channel(json!([
+                {"name": "etcd.v0.9.0"},
+                {"name": "etcd.v0.9.2", "replaces": "etcd.v0.9.0"},
+                {"name": "etcd.v0.9.4", "replaces": "etcd.v0.9.2", "skipRange": ">=0.9.0 <0.9.4"}
+            ])),
+            bundle("0.9.0"),
+            bundle("0.9.2"),
+            bundle("0.9.4"),
+        ]);
+        assert!(findings.is_empty(), "{:?}", findings);
+    }
+
+    #[test]
+    fn validation_rules() {
+        let entries = json!([{"name": "etcd.v0.9.0"}]);
+        let mut no_image = bundle("0.9.0");
+        no_image["image"] = json!("");
+        let mut inlined = no_image.clone();
+        inlined["properties"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"type": "olm.bundle.object", "value": {"data": "e30="}}));
+        let mut no_version = bundle("0.9.0");
+        no_version["properties"] = json!([]);
+
+        for (objects, expected) in [
+            (
+                vec![channel(entries.clone()), bundle("0.9.0")],
+                finding("error", "no olm.package object"),
+            ),
+            (
+                vec![package("stable"), package("stable"), channel(entries.clone()), bundle("0.9.0")],
+                finding("error", "2 olm.package objects"),
+            ),
+            (
+                vec![
+                    json!({"schema": "olm.package", "name": "etcd"}),
+                    channel(entries.clone()),
+                    bundle("0.9.0"),
+                ],
+                finding("error", "missing default channel"),
+            ),
+            (
+                vec![package("fast"), channel(entries.clone()), bundle("0.9.0")],
+                finding("error", "default channel fast not found"),
+            ),
+            (
+                vec![package("stable"), bundle("0.9.0")],
+                vec![
+                    ("error".to_string(), "default channel stable not found".to_string()),
+                    ("error".to_string(), "package has no channels".to_string()),
+                ],
+            ),
+            (
+                vec![package("stable"), channel(entries.clone()), bundle("0.9.0"), bundle("0.9.0")],
+                finding("error", "duplicate bundle"),
+            ),
+            (
+                vec![package("stable"), channel(entries.clone()), no_image],
+                finding("error", "bundle has no image"),
+            ),
+            (
+                vec![package("stable"), channel(entries.clone()), inlined],
+                finding("warning", "bundle has no image"),
+            ),
+            (
+                vec![package("stable"), channel(entries.clone()), no_version],
+                finding("error", "bundle has no olm.package property"),
+            ),
+            (
+                vec![package("stable"), channel(json!([])), bundle("0.9.0")],
+                finding("error", "channel has no entries"),
+            ),
+            (
+                vec![
+                    package("stable"),
+                    channel(json!([{"name": "etcd.v0.9.0"}, {"name": "etcd.v0.9.0"}])),
+                    bundle("0.9.0"),
+                ],
+                finding("error", "duplicate entry etcd.v0.9.0"),
+            ),
+            (
+                vec![package("stable"), channel(entries.clone())],
+                finding("error", "entry etcd.v0.9.0 has no matching bundle"),
+            ),
+            (
+                vec![
+                    package("stable"),
+                    channel(json!([{"name": "etcd.v0.9.0"}, {"name": "etcd.v0.9.2"}])),
+                    bundle("0.9.0"),
+                    bundle("0.9.2"),
+                ],
+                // the highest version is taken as the head, the other one is stranded
+                vec![
+                    (
+                        "error".to_string(),
+                        "multiple channel heads etcd.v0.9.0, etcd.v0.9.2".to_string(),
+                    ),
+                    (
+                        "error".to_string(),
+                        "entry etcd.v0.9.0 has no upgrade path to the channel head".to_string(),
+                    ),
+                ],
+            ),
+            (
+                vec![
+                    package("stable"),
+                    channel(json!([
+                        {"name": "etcd.v0.9.0", "replaces": "etcd.v0.9.2"},
+                        {"name": "etcd.v0.9.2", "replaces": "etcd.v0.9.0"}
+                    ])),
+                    bundle("0.9.0"),
+                    bundle("0.9.2"),
+                ],
+                // without a head every entry is stranded
+                vec![
+                    (
+                        "error".to_string(),
+                        "no channel head (replaces cycle)".to_string(),
+                    ),
+                    (
+                        "error".to_string(),
+                        "entry etcd.v0.9.0 has no upgrade path to the channel head".to_string(),
+                    ),
+                    (
+                        "error".to_string(),
+                        "entry etcd.v0.9.2 has no upgrade path to the channel head".to_string(),
+                    ),
+                ],
+            ),
+            (
+                vec![
+                    package("stable"),
+                    channel(json!([{"name": "etcd.v0.9.2", "replaces": "etcd.v0.9.0"}])),
+                    bundle("0.9.2"),
+                ],
+                finding("warning", "replaces/skips etcd.v0.9.0 which is not in the channel"),
+            ),
+        ] {
+            assert_eq!(validate(objects.clone()), expected, "{:?}", objects);
+        }
+    }
+}